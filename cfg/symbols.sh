@@ -0,0 +1,30 @@
+#!/bin/sh
+# Generates the kernel's symbol table from a linked kernel, as nasm source for the `.symbols`
+# section. Each entry holds a function's address, size, and a pointer to and the length of its
+# demangled name, and entries are sorted by address so that they can be binary searched.
+#
+# Usage: symbols.sh <kernel.elf>
+
+nm --defined-only --demangle --print-size --numeric-sort "$1" | awk '
+BEGIN {
+    print "section .symbols progbits alloc noexec nowrite align=8"
+    print "section .symbols.names progbits alloc noexec nowrite align=1"
+}
+
+# Only functions with a known size are included, as only the addresses of code are resolved
+NF >= 4 && $3 ~ /^[tTwW]$/ {
+    name = $0
+    sub(/^[^ ]+ [^ ]+ [^ ]+ /, "", name)
+
+    # Names are written as nasm strings, so cannot contain quotes
+    if (index(name, "\"") != 0) {
+        next
+    }
+
+    print "section .symbols"
+    printf "    dq 0x%s, 0x%s, symbol_name_%d, %d\n", $1, $2, count, length(name)
+    print "section .symbols.names"
+    printf "symbol_name_%d: db \"%s\"\n", count, name
+    count++
+}
+'
@@ -0,0 +1,324 @@
+//! # ACPI
+//!
+//! Finds the ACPI tables through the RSDP passed by the bootloader, and reads what the kernel needs
+//! from them. Tables are read in place through the physical memory map.
+//!
+//! There is no AML interpreter, so the `_S5_` sleep values used to power off are found by scanning
+//! the DSDT for the package defining them. This works for the simple `Name` definitions used by
+//! firmware in practice, including QEMU and Bochs.
+//!
+//! The reset register and value used to reboot are read from the FADT. A reset register in memory
+//! is mapped when the tables are read, so that rebooting does not need to map memory.
+
+use core::slice;
+use memory::{self, VirtualAddress};
+use memory::mmio::{self, MmioError};
+use multiboot::BootInfo;
+use spin::RwLock;
+
+const RSDP_SIGNATURE: &'static [u8] = b"RSD PTR ";
+/// The size of the ACPI 1.0 RSDP, which is covered by its checksum
+const RSDP_V1_SIZE: usize = 20;
+/// The size of the ACPI 2.0 RSDP, which is covered by its extended checksum
+const RSDP_V2_SIZE: usize = 36;
+
+const FADT_SIGNATURE: &'static [u8] = b"FACP";
+const DSDT_SIGNATURE: &'static [u8] = b"DSDT";
+
+/// The size of the header common to every system description table
+const HEADER_SIZE: usize = 36;
+
+/// Offsets of the FADT fields used, from the start of the table
+const FADT_DSDT: usize = 40;
+const FADT_SMI_COMMAND: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_PM1A_CONTROL: usize = 64;
+const FADT_PM1B_CONTROL: usize = 68;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REGISTER: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_X_DSDT: usize = 140;
+
+/// The FADT flag set if the reset register is supported
+const FADT_RESET_SUPPORTED: u32 = 1 << 10;
+
+/// The offset of the address in a generic address structure
+const GAS_ADDRESS: usize = 4;
+/// The address space IDs of a generic address structure
+const GAS_SYSTEM_MEMORY: u8 = 0;
+const GAS_SYSTEM_IO: u8 = 1;
+
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_ROOT_PREFIX: u8 = b'\\';
+
+/// The values needed to power off through the fixed hardware PM1 control registers
+#[derive(Copy, Clone, Debug)]
+pub struct PowerOff {
+    /// The port written to enable ACPI mode, or 0 if ACPI is always enabled
+    pub smi_command: u16,
+    /// The value written to `smi_command` to enable ACPI mode
+    pub acpi_enable: u8,
+    pub pm1a_control: u16,
+    /// The PM1b control port, or 0 if there is none
+    pub pm1b_control: u16,
+    /// The `_S5_` sleep type written to the PM1a control register
+    pub sleep_type_a: u8,
+    /// The `_S5_` sleep type written to the PM1b control register
+    pub sleep_type_b: u8,
+}
+
+/// The register written to reset the machine
+#[derive(Copy, Clone, Debug)]
+pub enum ResetRegister {
+    Io(u16),
+    /// A register in memory, mapped at the given virtual address
+    Memory(VirtualAddress),
+}
+
+/// The register and value used to reset the machine
+#[derive(Copy, Clone, Debug)]
+pub struct Reset {
+    pub register: ResetRegister,
+    pub value: u8,
+}
+
+/// An error returned when reading the ACPI tables
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AcpiError {
+    /// The bootloader did not pass an RSDP
+    NoRsdp,
+    /// A table has the wrong signature or checksum
+    InvalidTable,
+    /// A table lies outside the physical memory map
+    Unmapped,
+    /// No table with the requested signature was found
+    TableNotFound,
+    /// The DSDT does not define `_S5_` in a form which can be read without an AML interpreter
+    NoSleepState,
+    /// The FADT does not define a reset register in an address space which can be written
+    NoResetRegister,
+    /// A register in memory could not be mapped
+    Mmio(MmioError),
+}
+
+static POWER_OFF: RwLock<Option<PowerOff>> = RwLock::new(None);
+static RESET: RwLock<Option<Reset>> = RwLock::new(None);
+
+/// The RSDP passed by the bootloader, through which tables are found
+static RSDP: RwLock<Option<&'static [u8]>> = RwLock::new(None);
+
+/// Reads the ACPI tables through the RSDP passed by the bootloader. This must be called after the
+/// physical memory map is set up.
+pub fn init(boot_info: &BootInfo) {
+    *RSDP.write() = boot_info.rsdp();
+
+    match find_power_off() {
+        Ok(power_off) => {
+            debug!("acpi: pm1a control at {:#x}, s5 sleep type {}", power_off.pm1a_control, power_off.sleep_type_a);
+            *POWER_OFF.write() = Some(power_off);
+        }
+        Err(error) => warn!("acpi: unable to read power off values: {:?}", error),
+    }
+
+    match find_reset() {
+        Ok(reset) => {
+            debug!("acpi: reset register {:?}, value {:#x}", reset.register, reset.value);
+            *RESET.write() = Some(reset);
+        }
+        Err(error) => {
+            debug!("acpi: unable to read reset register: {:?}", error);
+        }
+    }
+}
+
+/// Gets the values needed to power off the machine, if they were found in the ACPI tables
+pub fn power_off() -> Option<PowerOff> {
+    *POWER_OFF.read()
+}
+
+/// Gets the register and value used to reset the machine, if they were found in the ACPI tables
+pub fn reset() -> Option<Reset> {
+    *RESET.read()
+}
+
+/// Finds the table with the given signature, such as `b"DMAR"`. `init` must have been called.
+pub fn find(signature: &[u8]) -> Result<&'static [u8], AcpiError> {
+    let rsdp = RSDP.read().ok_or(AcpiError::NoRsdp)?;
+    find_table(rsdp, signature)
+}
+
+fn find_power_off() -> Result<PowerOff, AcpiError> {
+    let fadt = find(FADT_SIGNATURE)?;
+    if fadt.len() < FADT_PM1B_CONTROL + 4 {
+        return Err(AcpiError::InvalidTable);
+    }
+
+    let dsdt = if fadt.len() >= FADT_X_DSDT + 8 && read_u64(fadt, FADT_X_DSDT) != 0 {
+        read_u64(fadt, FADT_X_DSDT) as usize
+    } else {
+        read_u32(fadt, FADT_DSDT) as usize
+    };
+    let dsdt = table(dsdt)?;
+    if &dsdt[..4] != DSDT_SIGNATURE {
+        return Err(AcpiError::InvalidTable);
+    }
+
+    let (sleep_type_a, sleep_type_b) = find_s5(&dsdt[HEADER_SIZE..]).ok_or(AcpiError::NoSleepState)?;
+
+    Ok(PowerOff {
+        smi_command: read_u32(fadt, FADT_SMI_COMMAND) as u16,
+        acpi_enable: fadt[FADT_ACPI_ENABLE],
+        pm1a_control: read_u32(fadt, FADT_PM1A_CONTROL) as u16,
+        pm1b_control: read_u32(fadt, FADT_PM1B_CONTROL) as u16,
+        sleep_type_a,
+        sleep_type_b,
+    })
+}
+
+fn find_reset() -> Result<Reset, AcpiError> {
+    let fadt = find(FADT_SIGNATURE)?;
+
+    // The reset register was added in ACPI 2.0, and is optional
+    if fadt.len() <= FADT_RESET_VALUE || read_u32(fadt, FADT_FLAGS) & FADT_RESET_SUPPORTED == 0 {
+        return Err(AcpiError::NoResetRegister);
+    }
+
+    let address = read_u64(fadt, FADT_RESET_REGISTER + GAS_ADDRESS);
+
+    let register = match fadt[FADT_RESET_REGISTER] {
+        GAS_SYSTEM_IO if address <= u16::max_value() as u64 => ResetRegister::Io(address as u16),
+        GAS_SYSTEM_MEMORY => {
+            let region = mmio::map(address as usize, 1, "acpi reset").map_err(AcpiError::Mmio)?;
+            ResetRegister::Memory(region.as_ptr() as VirtualAddress)
+        }
+        // PCI configuration space and other address spaces are not supported
+        _ => return Err(AcpiError::NoResetRegister),
+    };
+
+    Ok(Reset {
+        register,
+        value: fadt[FADT_RESET_VALUE],
+    })
+}
+
+/// Finds the table with the given signature through the RSDT or XSDT
+fn find_table(rsdp: &[u8], signature: &[u8]) -> Result<&'static [u8], AcpiError> {
+    if rsdp.len() < RSDP_V1_SIZE || &rsdp[..8] != RSDP_SIGNATURE || !checksum(&rsdp[..RSDP_V1_SIZE]) {
+        return Err(AcpiError::InvalidTable);
+    }
+
+    let revision = rsdp[15];
+    let (root, entry_size) = if revision >= 2 && rsdp.len() >= RSDP_V2_SIZE && checksum(&rsdp[..RSDP_V2_SIZE]) {
+        (read_u64(rsdp, 24) as usize, 8)
+    } else {
+        (read_u32(rsdp, 16) as usize, 4)
+    };
+
+    let root = table(root)?;
+
+    for entry in root[HEADER_SIZE..].chunks(entry_size).filter(|entry| entry.len() == entry_size) {
+        let address = if entry_size == 8 { read_u64(entry, 0) as usize } else { read_u32(entry, 0) as usize };
+
+        // Tables which cannot be read are skipped, as they may not be the one searched for
+        match table(address) {
+            Ok(found) if &found[..4] == signature => return Ok(found),
+            _ => (),
+        }
+    }
+
+    Err(AcpiError::TableNotFound)
+}
+
+/// Gets the table at the given physical address, checking that it is mapped and its checksum
+fn table(address: usize) -> Result<&'static [u8], AcpiError> {
+    if address == 0 || address + HEADER_SIZE > memory::physical_map_end() {
+        return Err(AcpiError::Unmapped);
+    }
+
+    let header = unsafe { slice::from_raw_parts(memory::phys_to_virt(address) as *const u8, HEADER_SIZE) };
+    let length = read_u32(header, 4) as usize;
+
+    if length < HEADER_SIZE {
+        return Err(AcpiError::InvalidTable);
+    }
+    if address + length > memory::physical_map_end() {
+        return Err(AcpiError::Unmapped);
+    }
+
+    let table = unsafe { slice::from_raw_parts(memory::phys_to_virt(address) as *const u8, length) };
+
+    if checksum(table) {
+        Ok(table)
+    } else {
+        Err(AcpiError::InvalidTable)
+    }
+}
+
+/// Finds the SLP_TYPa and SLP_TYPb values of the `_S5_` package in the given AML
+fn find_s5(aml: &[u8]) -> Option<(u8, u8)> {
+    let position = aml.windows(4).position(|window| window == b"_S5_")?;
+
+    // The name must be defined by a Name operator, optionally with a root prefix
+    let defined = match position {
+        0 => false,
+        1 => aml[0] == AML_NAME_OP,
+        _ => aml[position - 1] == AML_NAME_OP ||
+            (aml[position - 1] == AML_ROOT_PREFIX && aml[position - 2] == AML_NAME_OP),
+    };
+
+    let mut rest = &aml[position + 4..];
+    if !defined || rest.first() != Some(&AML_PACKAGE_OP) {
+        return None;
+    }
+
+    // The package length encodes how many bytes follow its lead byte in its top two bits
+    let length_bytes = (*rest.get(1)? >> 6) as usize;
+    rest = rest.get(2 + length_bytes + 1..)?;
+
+    let (sleep_type_a, rest) = read_integer(rest)?;
+    let (sleep_type_b, _) = read_integer(rest)?;
+
+    Some((sleep_type_a, sleep_type_b))
+}
+
+/// Reads a byte sized integer from the start of the given AML, returning it and the remaining AML
+fn read_integer(aml: &[u8]) -> Option<(u8, &[u8])> {
+    match *aml.first()? {
+        AML_BYTE_PREFIX => Some((*aml.get(1)?, &aml[2..])),
+        // ZeroOp and OneOp
+        value @ 0...1 => Some((value, &aml[1..])),
+        _ => None,
+    }
+}
+
+/// Returns `true` if the given bytes sum to zero
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data[offset] as u16 | (data[offset + 1] as u16) << 8
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data[offset] as u32 |
+        (data[offset + 1] as u32) << 8 |
+        (data[offset + 2] as u32) << 16 |
+        (data[offset + 3] as u32) << 24
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
+
+kernel_test!(fn finds_s5_package() {
+    // Name (\_S5_, Package (0x04) { 0x05, Zero, Zero, Zero })
+    let aml = [0x10, 0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x08, 0x04, 0x0A, 0x05, 0x00, 0x00, 0x00];
+    test_assert_eq!(find_s5(&aml), Some((5, 0)));
+
+    // A method named _S5_ is not a package, and cannot be read
+    let method = [0x14, 0x08, b'_', b'S', b'5', b'_', 0x00];
+    test_assert_eq!(find_s5(&method), None);
+});
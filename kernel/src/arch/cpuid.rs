@@ -0,0 +1,192 @@
+//! # CPUID
+//!
+//! Enumerates the CPU's vendor, family/model and feature flags through the `cpuid` instruction.
+//! The results are read once and cached in the static `CPU`, so that other subsystems can cheaply
+//! check whether a feature is available before using it.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! if cpuid::has(Features::RDRAND) {
+//!     println!("rdrand is supported");
+//! }
+//! ```
+
+use core::str;
+
+lazy_static! {
+    /// Information about the CPU the kernel is running on
+    pub static ref CPU: CpuInfo = CpuInfo::read();
+}
+
+bitflags! {
+    pub struct Features: u64 {
+        /// If an x87 FPU is present
+        const FPU = 1 << 0;
+        /// If the time stamp counter (`rdtsc`) is supported
+        const TSC = 1 << 1;
+        /// If model specific registers (`rdmsr`/`wrmsr`) are supported
+        const MSR = 1 << 2;
+        /// If the machine check exception is supported
+        const MCE = 1 << 3;
+        /// If an on-chip APIC is present
+        const APIC = 1 << 4;
+        /// If the machine check architecture is supported
+        const MCA = 1 << 5;
+        /// If `fxsave`/`fxrstor` are supported
+        const FXSR = 1 << 6;
+        /// If SSE is supported
+        const SSE = 1 << 7;
+        /// If SSE2 is supported
+        const SSE2 = 1 << 8;
+        /// If SSE3 is supported
+        const SSE3 = 1 << 9;
+        /// If the x2APIC is supported
+        const X2APIC = 1 << 10;
+        /// If `xsave`/`xrstor` are supported
+        const XSAVE = 1 << 11;
+        /// If the `rdrand` instruction is supported
+        const RDRAND = 1 << 12;
+        /// If the `rdseed` instruction is supported
+        const RDSEED = 1 << 13;
+        /// If supervisor mode execution prevention is supported
+        const SMEP = 1 << 14;
+        /// If supervisor mode access prevention is supported
+        const SMAP = 1 << 15;
+        /// If the no-execute page bit is supported
+        const NX = 1 << 16;
+        /// If 1GiB pages are supported
+        const PAGE_1GB = 1 << 17;
+        /// If the time stamp counter runs at a constant rate in all power states
+        const INVARIANT_TSC = 1 << 18;
+        /// If `monitor`/`mwait` are supported
+        const MONITOR = 1 << 19;
+    }
+}
+
+/// The registers returned from a single `cpuid` invocation
+#[derive(Copy, Clone, Debug)]
+struct CpuIdResult {
+    eax: u32,
+    ebx: u32,
+    ecx: u32,
+    edx: u32,
+}
+
+/// Describes the CPU as reported by `cpuid`
+#[derive(Debug)]
+pub struct CpuInfo {
+    vendor: [u8; 12],
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    pub features: Features,
+}
+
+impl CpuInfo {
+    /// Reads the CPU information through `cpuid`
+    fn read() -> Self {
+        let vendor_leaf = cpuid(0, 0);
+        let max_leaf = vendor_leaf.eax;
+
+        let mut vendor = [0u8; 12];
+        for (i, &register) in [vendor_leaf.ebx, vendor_leaf.edx, vendor_leaf.ecx].iter().enumerate() {
+            for byte in 0..4 {
+                vendor[i * 4 + byte] = (register >> (byte * 8)) as u8;
+            }
+        }
+
+        let mut features = Features::empty();
+
+        let info = cpuid(1, 0);
+        let base_family = (info.eax >> 8) & 0xF;
+        let base_model = (info.eax >> 4) & 0xF;
+        let stepping = info.eax & 0xF;
+
+        // The extended family and model fields are only used by certain base families
+        let family = if base_family == 0xF {
+            base_family + ((info.eax >> 20) & 0xFF)
+        } else {
+            base_family
+        };
+        let model = if base_family == 0x6 || base_family == 0xF {
+            base_model | (((info.eax >> 16) & 0xF) << 4)
+        } else {
+            base_model
+        };
+
+        features.set(Features::FPU, bit(info.edx, 0));
+        features.set(Features::TSC, bit(info.edx, 4));
+        features.set(Features::MSR, bit(info.edx, 5));
+        features.set(Features::MCE, bit(info.edx, 7));
+        features.set(Features::APIC, bit(info.edx, 9));
+        features.set(Features::MCA, bit(info.edx, 14));
+        features.set(Features::FXSR, bit(info.edx, 24));
+        features.set(Features::SSE, bit(info.edx, 25));
+        features.set(Features::SSE2, bit(info.edx, 26));
+        features.set(Features::SSE3, bit(info.ecx, 0));
+        features.set(Features::MONITOR, bit(info.ecx, 3));
+        features.set(Features::X2APIC, bit(info.ecx, 21));
+        features.set(Features::XSAVE, bit(info.ecx, 26));
+        features.set(Features::RDRAND, bit(info.ecx, 30));
+
+        if max_leaf >= 7 {
+            let extended_features = cpuid(7, 0);
+            features.set(Features::SMEP, bit(extended_features.ebx, 7));
+            features.set(Features::RDSEED, bit(extended_features.ebx, 18));
+            features.set(Features::SMAP, bit(extended_features.ebx, 20));
+        }
+
+        let max_extended_leaf = cpuid(0x8000_0000, 0).eax;
+
+        if max_extended_leaf >= 0x8000_0001 {
+            let extended_info = cpuid(0x8000_0001, 0);
+            features.set(Features::NX, bit(extended_info.edx, 20));
+            features.set(Features::PAGE_1GB, bit(extended_info.edx, 26));
+        }
+
+        if max_extended_leaf >= 0x8000_0007 {
+            features.set(Features::INVARIANT_TSC, bit(cpuid(0x8000_0007, 0).edx, 8));
+        }
+
+        CpuInfo { vendor, family, model, stepping, features }
+    }
+
+    /// The vendor identification string of this CPU, such as `GenuineIntel`
+    pub fn vendor(&self) -> &str {
+        str::from_utf8(&self.vendor).unwrap_or("unknown")
+    }
+}
+
+/// Returns `true` if the CPU supports all of the given features
+pub fn has(features: Features) -> bool {
+    CPU.features.contains(features)
+}
+
+/// Prints a summary of the CPU information
+pub fn print_summary() {
+    info!(
+        "cpu: {} family {:#x} model {:#x} stepping {}",
+        CPU.vendor(), CPU.family, CPU.model, CPU.stepping
+    );
+    info!("cpu: features {:?}", CPU.features);
+}
+
+/// Executes `cpuid` for the given leaf and subleaf
+fn cpuid(leaf: u32, subleaf: u32) -> CpuIdResult {
+    let (eax, ebx, ecx, edx): (u32, u32, u32, u32);
+
+    unsafe {
+        asm!("cpuid"
+            : "={eax}"(eax), "={ebx}"(ebx), "={ecx}"(ecx), "={edx}"(edx)
+            : "{eax}"(leaf), "{ecx}"(subleaf)
+            :: "volatile");
+    }
+
+    CpuIdResult { eax, ebx, ecx, edx }
+}
+
+/// Returns `true` if the given bit is set in the register
+fn bit(register: u32, bit: u32) -> bool {
+    register & (1 << bit) != 0
+}
@@ -0,0 +1,94 @@
+//! # FPU/SSE
+//!
+//! Handles enabling the x87 FPU and SSE, and saving/restoring their state. The state of each
+//! thread is held in an [FpuState], which should be saved and restored across context switches so
+//! that threads cannot corrupt each other's floating point registers.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let mut state = FpuState::new();
+//! state.save();
+//! // ... run something else which uses the FPU ...
+//! state.restore();
+//! ```
+
+use super::cpuid::{self, Features};
+
+/// CR0 bit which enables monitoring of the FPU
+const CR0_MONITOR_COPROCESSOR: u64 = 1 << 1;
+/// CR0 bit which causes all FPU instructions to trap if set
+const CR0_EMULATION: u64 = 1 << 2;
+/// CR0 bit which causes FPU errors to be reported through exceptions
+const CR0_NUMERIC_ERROR: u64 = 1 << 5;
+/// CR4 bit which enables `fxsave`/`fxrstor` and SSE instructions
+const CR4_OSFXSR: u64 = 1 << 9;
+/// CR4 bit which enables unmasked SSE exceptions
+const CR4_OSXMMEXCPT: u64 = 1 << 10;
+
+/// The size of the `fxsave` area
+const FXSAVE_SIZE: usize = 512;
+/// The alignment required for the `fxsave` area
+const FXSAVE_ALIGN: usize = 16;
+
+/// Enables the FPU and SSE, if supported
+pub fn init() {
+    if !cpuid::has(Features::FPU | Features::FXSR | Features::SSE) {
+        warn!("fpu: fpu/sse unsupported, floating point unavailable");
+        return;
+    }
+
+    unsafe {
+        let mut cr0: u64;
+        asm!("mov %cr0, $0" : "=r"(cr0) ::: "volatile");
+        cr0 &= !CR0_EMULATION;
+        cr0 |= CR0_MONITOR_COPROCESSOR | CR0_NUMERIC_ERROR;
+        asm!("mov $0, %cr0" :: "r"(cr0) : "memory" : "volatile");
+
+        let mut cr4: u64;
+        asm!("mov %cr4, $0" : "=r"(cr4) ::: "volatile");
+        cr4 |= CR4_OSFXSR | CR4_OSXMMEXCPT;
+        asm!("mov $0, %cr4" :: "r"(cr4) : "memory" : "volatile");
+
+        asm!("fninit" :::: "volatile");
+    }
+
+    debug!("fpu: enabled fpu and sse");
+}
+
+/// The saved x87 FPU and SSE state of a thread
+#[allow(dead_code)] // To be used by the scheduler's context switch
+pub struct FpuState {
+    /// The `fxsave` area. This is over-sized so that a 16 byte aligned region can always be found
+    /// within it, regardless of where the state is placed in memory.
+    buffer: [u8; FXSAVE_SIZE + FXSAVE_ALIGN],
+}
+
+#[allow(dead_code)] // To be used by the scheduler's context switch
+impl FpuState {
+    /// Creates a new state, which should be filled by `save` before it is restored
+    pub const fn new() -> Self {
+        FpuState { buffer: [0; FXSAVE_SIZE + FXSAVE_ALIGN] }
+    }
+
+    /// Saves the current FPU and SSE state into this state
+    pub fn save(&mut self) {
+        unsafe {
+            asm!("fxsave ($0)" :: "r"(self.area()) : "memory" : "volatile");
+        }
+    }
+
+    /// Restores the FPU and SSE state from this state
+    pub fn restore(&mut self) {
+        unsafe {
+            asm!("fxrstor ($0)" :: "r"(self.area()) : "memory" : "volatile");
+        }
+    }
+
+    /// Gets the 16 byte aligned pointer to the `fxsave` area within the buffer
+    fn area(&mut self) -> *mut u8 {
+        let address = self.buffer.as_mut_ptr() as usize;
+        let aligned = (address + FXSAVE_ALIGN - 1) & !(FXSAVE_ALIGN - 1);
+        aligned as *mut u8
+    }
+}
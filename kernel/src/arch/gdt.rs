@@ -0,0 +1,109 @@
+//! # GDT and TSS
+//!
+//! Replaces the GDT set up during boot with one that also contains a task state segment. In long
+//! mode, the TSS is only used to hold the stacks switched to on interrupts. Exceptions which can
+//! happen when the current stack is unusable, such as a double fault caused by a stack overflow,
+//! are given their own stack in the interrupt stack table (IST) so that they can still be handled.
+//!
+//! The code and data segments are at the same selectors as in the boot GDT, so the segment
+//! registers do not need to be reloaded.
+
+use core::mem;
+
+/// The IST index of the stack used for double faults
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+/// The IST index of the stack used for machine checks
+pub const MACHINE_CHECK_IST_INDEX: u16 = 1;
+
+/// The size of each IST stack
+const IST_STACK_SIZE: usize = 4096 * 4;
+
+/// The selector of the TSS descriptor
+const TSS_SELECTOR: u16 = 3 << 3;
+
+/// A 64-bit kernel code segment, identical to the boot GDT's
+const KERNEL_CODE: u64 = (1 << 40) | (1 << 41) | (1 << 43) | (1 << 44) | (1 << 47) | (1 << 53);
+/// A kernel data segment, identical to the boot GDT's
+const KERNEL_DATA: u64 = (1 << 40) | (1 << 41) | (1 << 44) | (1 << 47);
+/// The type of an available 64-bit TSS descriptor
+const TSS_AVAILABLE: u64 = 0x9;
+/// The present bit of a descriptor
+const DESCRIPTOR_PRESENT: u64 = 1 << 47;
+
+/// The 64-bit task state segment
+#[allow(dead_code)] // Fields required for layout
+#[repr(C, packed)]
+struct TaskStateSegment {
+    reserved_0: u32,
+    /// The stacks switched to when changing privilege level
+    privilege_stack_table: [u64; 3],
+    reserved_1: u64,
+    /// The stacks switched to by interrupts with a non-zero IST index
+    interrupt_stack_table: [u64; 7],
+    reserved_2: u64,
+    reserved_3: u16,
+    io_map_base: u16,
+}
+
+/// The operand of `lgdt`
+#[allow(dead_code)] // Fields required for layout
+#[repr(C, packed)]
+struct DescriptorTablePointer {
+    limit: u16,
+    base: u64,
+}
+
+static mut TSS: TaskStateSegment = TaskStateSegment {
+    reserved_0: 0,
+    privilege_stack_table: [0; 3],
+    reserved_1: 0,
+    interrupt_stack_table: [0; 7],
+    reserved_2: 0,
+    reserved_3: 0,
+    io_map_base: mem::size_of::<TaskStateSegment>() as u16,
+};
+
+/// The null descriptor, kernel code and data, and the two entries of the TSS descriptor
+static mut GDT: [u64; 5] = [0, KERNEL_CODE, KERNEL_DATA, 0, 0];
+
+static mut DOUBLE_FAULT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut MACHINE_CHECK_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+
+/// Loads the new GDT and TSS. This must be called before any IDT entry uses an IST index.
+pub fn init() {
+    unsafe {
+        // Stacks grow down, so the IST holds the address of their ends
+        TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            DOUBLE_FAULT_STACK.as_ptr() as u64 + IST_STACK_SIZE as u64;
+        TSS.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] =
+            MACHINE_CHECK_STACK.as_ptr() as u64 + IST_STACK_SIZE as u64;
+
+        let (low, high) = tss_descriptor(&TSS as *const _ as u64);
+        GDT[3] = low;
+        GDT[4] = high;
+
+        let pointer = DescriptorTablePointer {
+            limit: (mem::size_of_val(&GDT) - 1) as u16,
+            base: GDT.as_ptr() as u64,
+        };
+
+        asm!("lgdt ($0)" :: "r"(&pointer as *const _ as u64) : "memory" : "volatile");
+        asm!("ltr $0" :: "r"(TSS_SELECTOR) : "memory" : "volatile");
+    }
+
+    debug!("gdt: loaded gdt and tss");
+}
+
+/// Creates the two entries of a TSS descriptor for the TSS at the given address
+fn tss_descriptor(base: u64) -> (u64, u64) {
+    let limit = (mem::size_of::<TaskStateSegment>() - 1) as u64;
+
+    let low = (limit & 0xFFFF)
+        | (base & 0xFF_FFFF) << 16
+        | TSS_AVAILABLE << 40
+        | DESCRIPTOR_PRESENT
+        | (limit >> 16 & 0xF) << 48
+        | (base >> 24 & 0xFF) << 56;
+
+    (low, base >> 32)
+}
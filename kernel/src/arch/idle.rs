@@ -0,0 +1,76 @@
+//! # CPU Idle
+//!
+//! Puts the CPU into a low power state until the next interrupt, using `mwait` when it is supported
+//! and `hlt` otherwise. The time spent idle is measured with the time stamp counter, so that the
+//! load of the CPU can be reported.
+//!
+//! There is no scheduler yet, so the kernel's main loop calls `wait` when it has no work left.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let stats = arch::idle::stats();
+//! info!("cpu: idle {} of {} cycles", stats.idle_cycles, stats.total_cycles);
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use super::cpuid::{self, Features};
+
+/// The cache line monitored by `mwait`. Writing to it wakes a CPU waiting on it without an
+/// interrupt.
+static WAKE: AtomicUsize = AtomicUsize::new(0);
+
+/// The amount of TSC cycles spent idle
+static IDLE_CYCLES: AtomicUsize = AtomicUsize::new(0);
+/// The TSC value when the first CPU went idle, or 0 if none has yet
+static START: AtomicUsize = AtomicUsize::new(0);
+
+/// The time spent idle, in TSC cycles
+#[derive(Copy, Clone, Debug)]
+pub struct IdleStats {
+    /// The amount of cycles spent idle
+    pub idle_cycles: u64,
+    /// The amount of cycles since the CPU first went idle
+    pub total_cycles: u64,
+}
+
+/// Idles the CPU until the next interrupt, and then enables interrupts. Interrupts should be
+/// disabled before checking for work and calling this, so that an interrupt which queues work cannot
+/// be missed between the two.
+pub fn wait() {
+    let start = super::rdtsc();
+    START.compare_and_swap(0, start as usize, Ordering::SeqCst);
+
+    unsafe {
+        if cpuid::has(Features::MONITOR) {
+            // `sti` only takes effect after the following instruction, so no interrupt can arrive
+            // between arming the monitor and waiting
+            asm!("monitor" :: "{rax}"(&WAKE as *const _ as usize), "{ecx}"(0), "{edx}"(0) :: "volatile");
+            asm!("sti
+                  mwait" :: "{eax}"(0), "{ecx}"(0) : "memory" : "volatile");
+        } else {
+            asm!("sti
+                  hlt" :::: "volatile");
+        }
+    }
+
+    let cycles = super::rdtsc().wrapping_sub(start);
+    IDLE_CYCLES.fetch_add(cycles as usize, Ordering::Relaxed);
+}
+
+/// Wakes a CPU waiting in `mwait` without an interrupt
+#[allow(dead_code)] // Part of API
+pub fn wake() {
+    WAKE.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Gets the time the CPU has spent idle
+#[allow(dead_code)] // Part of API
+pub fn stats() -> IdleStats {
+    let start = START.load(Ordering::SeqCst) as u64;
+
+    IdleStats {
+        idle_cycles: IDLE_CYCLES.load(Ordering::Relaxed) as u64,
+        total_cycles: if start == 0 { 0 } else { super::rdtsc().wrapping_sub(start) },
+    }
+}
@@ -0,0 +1,69 @@
+//! Architecture specific functionality for x86_64
+
+pub mod cpuid;
+pub mod fpu;
+pub mod gdt;
+pub mod idle;
+pub mod percpu;
+pub mod registers;
+
+pub use self::registers::Registers;
+
+/// Reads the CPU's time stamp counter
+pub fn rdtsc() -> u64 {
+    let (high, low): (u32, u32);
+    unsafe {
+        asm!("rdtsc" : "={edx}"(high), "={eax}"(low) ::: "volatile");
+    }
+    (high as u64) << 32 | low as u64
+}
+
+/// Reads the given model specific register
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (high, low): (u32, u32);
+    asm!("rdmsr" : "={edx}"(high), "={eax}"(low) : "{ecx}"(msr) :: "volatile");
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes to the given model specific register
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    let (high, low) = ((value >> 32) as u32, value as u32);
+    asm!("wrmsr" :: "{ecx}"(msr), "{edx}"(high), "{eax}"(low) :: "volatile");
+}
+
+/// The interrupt enable flag in RFLAGS
+const RFLAGS_INTERRUPT_ENABLE: u64 = 1 << 9;
+
+/// Enables maskable interrupts
+pub fn enable_interrupts() {
+    unsafe { asm!("sti" :::: "volatile") };
+}
+
+/// Disables maskable interrupts, returning `true` if they were enabled
+pub fn disable_interrupts() -> bool {
+    let enabled = interrupts_enabled();
+    unsafe { asm!("cli" :::: "volatile") };
+    enabled
+}
+
+/// Returns `true` if maskable interrupts are enabled
+pub fn interrupts_enabled() -> bool {
+    let rflags: u64;
+    unsafe { asm!("pushfq; popq $0" : "=r"(rflags) ::: "volatile") };
+    rflags & RFLAGS_INTERRUPT_ENABLE != 0
+}
+
+/// Runs the given closure with maskable interrupts disabled, restoring them afterwards if they were
+/// enabled. This prevents an interrupt handler from deadlocking on a lock held by the closure.
+pub fn without_interrupts<F, R>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    let enabled = disable_interrupts();
+    let result = f();
+
+    if enabled {
+        enable_interrupts();
+    }
+
+    result
+}
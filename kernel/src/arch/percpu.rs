@@ -0,0 +1,89 @@
+//! # Per-CPU data
+//!
+//! Each CPU has a [PerCpu] block, pointed to by its GS base, so that it can be found with a single
+//! `gs`-relative load regardless of which CPU is running. The first field of the block points to
+//! the block itself, so that a normal reference to it can be made.
+//!
+//! Only the bootstrap processor is brought up, so there is currently a single block. The block
+//! will hold the current thread and run queue once threads exist.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! percpu::init_bsp();
+//! debug!("running on cpu {}", percpu::current().id());
+//! ```
+
+use core::cell::UnsafeCell;
+use core::ptr;
+use super::wrmsr;
+
+/// The MSR holding the GS base
+const IA32_GS_BASE: u32 = 0xC000_0101;
+
+/// The amount of `u64`s of scratch space in each block
+pub const SCRATCH_SIZE: usize = 4;
+/// The offset of the scratch space from the GS base, for use from assembly
+#[allow(dead_code)] // Part of API
+pub const SCRATCH_OFFSET: usize = 16;
+
+/// The data owned by a single CPU
+#[repr(C)]
+pub struct PerCpu {
+    /// The address of this block, read through `gs:0`
+    this: *const PerCpu,
+    id: usize,
+    /// Space for saving registers where no stack is usable, such as on entry to a syscall
+    scratch: UnsafeCell<[u64; SCRATCH_SIZE]>,
+}
+
+impl PerCpu {
+    const fn new(id: usize) -> Self {
+        PerCpu {
+            this: ptr::null(),
+            id,
+            scratch: UnsafeCell::new([0; SCRATCH_SIZE]),
+        }
+    }
+
+    /// The index of this CPU, which is 0 for the bootstrap processor
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Gets a pointer to this CPU's scratch space
+    #[allow(dead_code)] // Part of API
+    pub fn scratch(&self) -> *mut [u64; SCRATCH_SIZE] {
+        self.scratch.get()
+    }
+}
+
+/// The block of the bootstrap processor
+static mut BSP: PerCpu = PerCpu::new(0);
+
+/// Points the GS base at the bootstrap processor's block. This must be called before `current`.
+pub fn init_bsp() {
+    unsafe {
+        BSP.this = &BSP as *const PerCpu;
+        wrmsr(IA32_GS_BASE, BSP.this as u64);
+    }
+
+    debug!("percpu: bsp block at {:p}", unsafe { BSP.this });
+}
+
+/// Gets the block of the CPU this is running on
+#[allow(dead_code)] // Part of API
+pub fn current() -> &'static PerCpu {
+    let this: *const PerCpu;
+
+    unsafe {
+        asm!("mov %gs:0, $0" : "=r"(this) ::: "volatile");
+        &*this
+    }
+}
+
+kernel_test!(fn current_is_bsp() {
+    test_assert_eq!(current() as *const PerCpu, unsafe { &BSP as *const PerCpu });
+    test_assert_eq!(current().id(), 0);
+    test_assert_eq!(current().scratch() as usize - current() as *const PerCpu as usize, SCRATCH_OFFSET);
+});
@@ -0,0 +1,126 @@
+//! Captures the state of the CPU's registers, and formats it for fault handlers and the debug
+//! monitor. Flags, segment selectors and control registers are decoded rather than printed as bare
+//! hex.
+
+use core::fmt;
+use symbols;
+use unwind;
+use x86_64::structures::idt::ExceptionStackFrame;
+
+/// The names of the single bit flags in RFLAGS, by bit
+const RFLAGS_NAMES: [(u64, &'static str); 15] = [
+    (0, "CF"), (2, "PF"), (4, "AF"), (6, "ZF"), (7, "SF"), (8, "TF"), (9, "IF"), (10, "DF"),
+    (11, "OF"), (14, "NT"), (16, "RF"), (17, "VM"), (18, "AC"), (19, "VIF"), (21, "ID"),
+];
+/// The shift of the IO privilege level in RFLAGS
+const RFLAGS_IOPL_SHIFT: u64 = 12;
+
+/// The mask of the physical address of the top level page table in CR3
+const CR3_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// The state of the CPU's registers
+#[derive(Debug)]
+pub struct Registers {
+    pub rip: u64,
+    pub rsp: u64,
+    pub rflags: u64,
+    pub cs: u16,
+    pub ss: u16,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    /// The registers used to unwind the stack
+    pub frame: unwind::Registers,
+}
+
+impl Registers {
+    /// Captures the current register values
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let (rip, rsp, rflags, cs, ss): (u64, u64, u64, u16, u16);
+
+        unsafe {
+            asm!("lea (%rip), $0" : "=r"(rip) ::: "volatile");
+            asm!("mov %rsp, $0" : "=r"(rsp) ::: "volatile");
+            asm!("pushfq; popq $0" : "=r"(rflags) ::: "volatile");
+            asm!("mov %cs, $0" : "=r"(cs) ::: "volatile");
+            asm!("mov %ss, $0" : "=r"(ss) ::: "volatile");
+        }
+
+        Registers::with_control_registers(rip, rsp, rflags, cs, ss, unwind::Registers::capture())
+    }
+
+    /// Gets the registers of the context an exception interrupted. The control registers are read
+    /// in the handler, so CR2 holds the faulting address of a page fault.
+    pub fn from_exception(frame: &ExceptionStackFrame) -> Self {
+        Registers::with_control_registers(
+            frame.instruction_pointer.0 as u64,
+            frame.stack_pointer.0 as u64,
+            frame.cpu_flags,
+            frame.code_segment as u16,
+            frame.stack_segment as u16,
+            unwind::Registers::from_exception(frame),
+        )
+    }
+
+    #[inline(always)]
+    fn with_control_registers(rip: u64, rsp: u64, rflags: u64, cs: u16, ss: u16,
+                              frame: unwind::Registers) -> Self {
+        let (cr0, cr2, cr3, cr4): (u64, u64, u64, u64);
+
+        unsafe {
+            asm!("mov %cr0, $0" : "=r"(cr0) ::: "volatile");
+            asm!("mov %cr2, $0" : "=r"(cr2) ::: "volatile");
+            asm!("mov %cr3, $0" : "=r"(cr3) ::: "volatile");
+            asm!("mov %cr4, $0" : "=r"(cr4) ::: "volatile");
+        }
+
+        Registers { rip, rsp, rflags, cs, ss, cr0, cr2, cr3, cr4, frame }
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rip    {:#018x}", self.rip)?;
+        if let Some((symbol, offset)) = symbols::resolve(self.rip as usize) {
+            write!(f, " {}+{:#x}", symbol.name, offset)?;
+        }
+
+        writeln!(f, "\nrsp    {:#018x}", self.rsp)?;
+        writeln!(f, "rflags {:#018x} {}", self.rflags, Rflags(self.rflags))?;
+        writeln!(f, "cs     {:#06x} {}  ss {:#06x} {}", self.cs, Selector(self.cs), self.ss, Selector(self.ss))?;
+        writeln!(f, "cr0    {:#018x}  cr4 {:#018x}", self.cr0, self.cr4)?;
+        writeln!(f, "cr2    {:#018x} (last page fault address)", self.cr2)?;
+        writeln!(f, "cr3    {:#018x} (page table at {:#x})", self.cr3, self.cr3 & CR3_ADDRESS_MASK)
+    }
+}
+
+/// Formats the flags set in RFLAGS, and the IO privilege level
+struct Rflags(u64);
+
+impl fmt::Display for Rflags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+
+        let mut first = true;
+        for &(bit, name) in RFLAGS_NAMES.iter() {
+            if self.0 & (1 << bit) != 0 {
+                write!(f, "{}{}", if first { "" } else { " " }, name)?;
+                first = false;
+            }
+        }
+
+        write!(f, "] iopl {}", (self.0 >> RFLAGS_IOPL_SHIFT) & 0b11)
+    }
+}
+
+/// Formats a segment selector as its table, index and requested privilege level
+struct Selector(u16);
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let table = if self.0 & 0b100 != 0 { "ldt" } else { "gdt" };
+        write!(f, "({} {}, ring {})", table, self.0 >> 3, self.0 & 0b11)
+    }
+}
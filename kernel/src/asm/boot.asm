@@ -5,16 +5,24 @@
 %define RESOLUTION_Y 25
 %define VGA_PTR 0xb8000
 
+; The kernel is linked in the higher half, at -2GiB. Until paging is set up, symbols outside of the
+; boot sections must be accessed through their physical address.
+%define KERNEL_OFFSET 0xFFFFFFFF80000000
+%define PHYS(address) ((address) - KERNEL_OFFSET)
+
 extern kmain
 global start
 
-section .text
+section .boot.text
 bits 32
 
 start:
     ; Disable interrupts
     cli
 
+    ; Keep the multiboot information pointer to pass to kmain
+    mov edi, ebx
+
     ; Checks
     call check_multiboot ; Check if booted correctly
     call check_cpuid  ; Check if cpuid supported
@@ -32,7 +40,8 @@ start:
     mov ds, ax ; set data segment register
     mov es, ax ; set extra segment register
     
-    jmp gdt64.code:long_mode_start
+    ; Enter long mode, still in the identity mapped boot sections
+    jmp gdt64.code:long_mode_trampoline
     
     hlt ; should never happen
     
@@ -92,17 +101,34 @@ error_print:
 
 ; Set up paging
 ; Thanks to https://intermezzos.github.io/book/paging.html
+;
+; The first 1GiB of physical memory is mapped three times:
+;  - Identity mapped (p4 entry 0), so that execution can continue after paging is enabled. This is
+;    removed once in the higher half.
+;  - At the physical memory offset (p4 entry 256), so that the kernel can access physical memory.
+;  - At -2GiB (p4 entry 511, p3 entry 510), where the kernel is linked.
 setup_paging:
 
-    ; Point entry #1 of page 4 to entry #1 of page 3
-    mov eax, p3_table + 0 ; set eax to 1st entry of p3 table
+    ; Point the identity and physical memory offset entries of the p4 table to the p3 table
+    mov eax, PHYS(p3_table)
     or eax, 0b11
-    mov [p4_table + 0], eax ; set 1st entry of p4 table to 1st entry of p3 table
+    mov [PHYS(p4_table) + 0 * 8], eax
+    mov [PHYS(p4_table) + 256 * 8], eax
+
+    ; Point the last entry of the p4 table to the kernel p3 table
+    mov eax, PHYS(p3_kernel_table)
+    or eax, 0b11
+    mov [PHYS(p4_table) + 511 * 8], eax
     
-    ; Point entry #1 of page 3 to entry #1 of page 2
-    mov eax, p2_table + 0 ; set eax to 1st entry of p3 table
+    ; Point entry #1 of the p3 table to the p2 table
+    mov eax, PHYS(p2_table)
+    or eax, 0b11
+    mov [PHYS(p3_table) + 0], eax
+
+    ; Point the second to last entry of the kernel p3 table to the kernel p2 table
+    mov eax, PHYS(p2_kernel_table)
     or eax, 0b11
-    mov [p3_table + 0], eax ; set 1st entry of p4 table to 1st entry of p3 table
+    mov [PHYS(p3_kernel_table) + 510 * 8], eax
     
     mov ecx, 0
     .map_p2_table_loop:
@@ -111,14 +137,15 @@ setup_paging:
         mul ecx ; multiply by counter
         or eax, 0b10000011 ; first 1 is huge page bit
         
-        mov [p2_table + ecx * 8], eax
+        mov [PHYS(p2_table) + ecx * 8], eax
+        mov [PHYS(p2_kernel_table) + ecx * 8], eax
         
         inc ecx
         cmp ecx, 512
         jne .map_p2_table_loop
     
     ; Set page table address to cr3
-    mov eax, p4_table ; cr3 must be mov'd to from another register
+    mov eax, PHYS(p4_table) ; cr3 must be mov'd to from another register
     mov cr3, eax 
     
     ; Enable Physical Address Extension
@@ -225,28 +252,49 @@ p3_table:
     resb 4096
 p2_table:
     resb 4096
+p3_kernel_table:
+    resb 4096
+p2_kernel_table:
+    resb 4096
 
 ; Stack grows the other way
 stack_bottom:
     resb 1024 * 64 ; 64 kilobytes
 stack_top:
 
-section .rodata
+section .boot.rodata
 
 ; Copied from intermezzos: https://intermezzos.github.io/book/setting-up-a-gdt.html
+; The accessed bit (40) is preset, as the CPU cannot set it once the GDT is mapped read-only
 gdt64:
     dq 0
 .code: equ $ - gdt64 ; offset from gdt
-    dq (1<<44) | (1<<47) | (1<<41) | (1<<43) | (1<<53)
+    dq (1<<40) | (1<<44) | (1<<47) | (1<<41) | (1<<43) | (1<<53)
 .data: equ $ - gdt64 ; offset from gdt
-    dq (1<<44) | (1<<47) | (1<<41)
+    dq (1<<40) | (1<<44) | (1<<47) | (1<<41)
+.end:
 .pointer:
-    dw $ - gdt64 - 1 ; length
+    dw .end - gdt64 - 1 ; length
     dq gdt64 ; address of table
+.pointer_high:
+    dw .end - gdt64 - 1 ; length
+    dq gdt64 + KERNEL_OFFSET ; address of table through the higher half
+
+section .boot.text
+bits 64
+long_mode_trampoline:
+
+    ; Jump to the higher half
+    mov rax, long_mode_start
+    jmp rax
 
 section .text
 bits 64
 long_mode_start:
+
+    ; Reload the GDT through the higher half, as the identity mapping is about to be removed
+    mov rax, gdt64.pointer_high + KERNEL_OFFSET
+    lgdt [rax]
     
     ; Set all data segment registers to 0
     mov ax, 0
@@ -257,7 +305,16 @@ long_mode_start:
     mov gs, ax
     
     ; Setup stack
-    mov esp, stack_top
+    mov rsp, stack_top
+
+    ; Remove the identity mapping
+    mov rax, p4_table
+    mov qword [rax], 0
+    mov rax, cr3
+    mov cr3, rax
+
+    ; Zero extend the multiboot information pointer, which is kmain's first argument
+    mov edi, edi
     
     call kmain
 
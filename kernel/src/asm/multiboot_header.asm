@@ -11,7 +11,18 @@ header_start:
 
     ; header checksum (0x100000000 - (magic number + mode + length))
     dd 0x100000000 - (0xe85250d6 + 0 + (header_end - header_start))
-    
+
+%ifdef GRAPHICS
+    ; framebuffer tag, asking for a linear framebuffer
+    dw 5 ; type
+    dw 0 ; flags
+    dd 20 ; size
+    dd 1024 ; width
+    dd 768 ; height
+    dd 32 ; depth
+    dd 0 ; padding, as tags are 8 byte aligned
+%endif
+
     ; end tag
     dw 0 ; type
     dw 0 ; flags
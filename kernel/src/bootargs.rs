@@ -0,0 +1,129 @@
+//! # Boot arguments
+//!
+//! Parses the kernel command line passed by the bootloader into a set of options, so that behaviour
+//! can be changed at boot without recompiling. The command line is a whitespace separated list of
+//! arguments, each of which is either a `key=value` pair or a bare flag such as `nosmp`.
+//!
+//! Arguments are parsed once, by `init`, and can then be read from anywhere with `get` and `has`.
+//! At most `MAX_ARGS` arguments are kept; any more are ignored with a warning.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! bootargs::init(&boot_info);
+//!
+//! if let Some(level) = bootargs::get("loglevel") {
+//!     println!("Log level: {}", level);
+//! }
+//!
+//! if bootargs::has("nosmp") {
+//!     println!("SMP disabled");
+//! }
+//! ```
+
+use multiboot::BootInfo;
+use spin::RwLock;
+
+/// The maximum amount of arguments that are kept
+pub const MAX_ARGS: usize = 32;
+
+/// The parsed boot arguments
+static ARGS: RwLock<BootArgs> = RwLock::new(BootArgs::new());
+
+/// A single boot argument
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BootArg {
+    /// The name of this argument
+    pub key: &'static str,
+    /// The value of this argument, or `None` if it is a flag
+    pub value: Option<&'static str>,
+}
+
+impl BootArg {
+    /// Parses a single `key=value` or `flag` argument
+    fn parse(arg: &'static str) -> Self {
+        match arg.find('=') {
+            Some(index) => BootArg {
+                key: &arg[..index],
+                value: Some(&arg[index + 1..]),
+            },
+            None => BootArg { key: arg, value: None },
+        }
+    }
+}
+
+/// A fixed-capacity set of boot arguments
+pub struct BootArgs {
+    args: [Option<BootArg>; MAX_ARGS],
+}
+
+impl BootArgs {
+    const fn new() -> Self {
+        BootArgs {
+            args: [None; MAX_ARGS],
+        }
+    }
+
+    /// Parses the given command line. Later arguments override earlier ones with the same key.
+    pub fn parse(command_line: &'static str) -> Self {
+        let mut args = BootArgs::new();
+
+        for arg in command_line.split_whitespace().map(BootArg::parse) {
+            if args.insert(arg).is_err() {
+                warn!("boot: too many arguments, ignoring {}", arg.key);
+            }
+        }
+
+        args
+    }
+
+    /// Gets the value of the argument with the given key. Flags have an empty value.
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        self.arg(key).map(|arg| arg.value.unwrap_or(""))
+    }
+
+    /// Returns `true` if an argument with the given key was passed, whether or not it has a value
+    pub fn has(&self, key: &str) -> bool {
+        self.arg(key).is_some()
+    }
+
+    fn arg(&self, key: &str) -> Option<BootArg> {
+        self.args.iter()
+            .filter_map(|slot| *slot)
+            .find(|arg| arg.key == key)
+    }
+
+    /// Inserts an argument, replacing any with the same key
+    fn insert(&mut self, arg: BootArg) -> Result<(), ()> {
+        // Arguments are never removed, so an existing argument always comes before the first gap
+        let slot = self.args.iter_mut()
+            .find(|slot| slot.map(|existing| existing.key == arg.key).unwrap_or(true))
+            .ok_or(())?;
+
+        *slot = Some(arg);
+        Ok(())
+    }
+}
+
+/// Parses the command line passed by the bootloader. This should be called as early as possible, so
+/// that arguments can change how the kernel initializes.
+pub fn init(boot_info: &BootInfo) {
+    let command_line = match boot_info.command_line() {
+        Some(command_line) => command_line,
+        None => return,
+    };
+
+    debug!("boot: command line \"{}\"", command_line);
+    *ARGS.write() = BootArgs::parse(command_line);
+}
+
+/// Gets the value of the boot argument with the given key. Flags have an empty value.
+pub fn get(key: &str) -> Option<&'static str> {
+    ARGS.read().get(key)
+}
+
+/// Returns `true` if a boot argument with the given key was passed
+#[allow(dead_code)] // Part of API
+pub fn has(key: &str) -> bool {
+    ARGS.read().has(key)
+}
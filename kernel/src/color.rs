@@ -30,7 +30,6 @@ pub struct ColorPair {
 }
 
 impl ColorPair {
-    #[allow(dead_code)] // Completeness
     pub const fn new(foreground: Color, background: Color) -> Self {
         ColorPair { foreground, background }
     }
@@ -0,0 +1,133 @@
+//! # High Precision Event Timer
+//!
+//! The HPET has a main counter which counts up at a fixed rate of at least 10 MHz, which the [time]
+//! module uses to measure the TSC's frequency, or as its clock if the TSC is not invariant. It is
+//! found through the ACPI HPET table. Its timers are not used, as there is no timer interrupt yet.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! if let Some(hpet) = hpet::get() {
+//!     println!("hpet: counting at {} Hz", hpet.frequency());
+//! }
+//! ```
+
+use acpi::{self, AcpiError};
+use memory::mmio::{self, MmioError, MmioRegion};
+use spin::Once;
+
+const HPET_SIGNATURE: &'static [u8] = b"HPET";
+/// The offset of the address space of the registers in the HPET table
+const TABLE_ADDRESS_SPACE: usize = 40;
+/// The offset of the address of the registers in the HPET table
+const TABLE_ADDRESS: usize = 44;
+/// The address space of memory mapped registers
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+
+const REGISTER_CAPABILITIES: usize = 0x000;
+const REGISTER_CONFIGURATION: usize = 0x010;
+const REGISTER_MAIN_COUNTER: usize = 0x0F0;
+const REGISTERS_SIZE: usize = 0x400;
+
+/// Set in the capabilities if the main counter is 64 bits wide
+const CAPABILITY_64_BIT: u64 = 1 << 13;
+/// The shift of the main counter's period, in femtoseconds, in the capabilities
+const CAPABILITY_PERIOD_SHIFT: u64 = 32;
+/// Set in the configuration to start the main counter
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+static HPET: Once<Option<Hpet>> = Once::new();
+
+/// An error returned when setting up the HPET
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum HpetError {
+    Acpi(AcpiError),
+    /// The registers are not memory mapped, or the counter period is invalid
+    InvalidTable,
+    Mmio(MmioError),
+}
+
+impl From<AcpiError> for HpetError {
+    fn from(error: AcpiError) -> Self {
+        HpetError::Acpi(error)
+    }
+}
+
+impl From<MmioError> for HpetError {
+    fn from(error: MmioError) -> Self {
+        HpetError::Mmio(error)
+    }
+}
+
+/// The HPET's main counter
+pub struct Hpet {
+    registers: MmioRegion,
+    /// The period of the counter, in femtoseconds
+    period: u64,
+    wide: bool,
+}
+
+impl Hpet {
+    fn new() -> Result<Self, HpetError> {
+        let table = acpi::find(HPET_SIGNATURE)?;
+        if table.len() < TABLE_ADDRESS + 8 || table[TABLE_ADDRESS_SPACE] != ADDRESS_SPACE_MEMORY {
+            return Err(HpetError::InvalidTable);
+        }
+
+        let registers = mmio::map(acpi::read_u64(table, TABLE_ADDRESS) as usize, REGISTERS_SIZE, "hpet")?;
+        let capabilities: u64 = registers.read(REGISTER_CAPABILITIES);
+
+        // The period is at most 100ns
+        let period = capabilities >> CAPABILITY_PERIOD_SHIFT;
+        if period == 0 || period > 100_000_000 {
+            return Err(HpetError::InvalidTable);
+        }
+
+        let configuration: u64 = registers.read(REGISTER_CONFIGURATION);
+        registers.write(REGISTER_CONFIGURATION, configuration | CONFIGURATION_ENABLE);
+
+        Ok(Hpet {
+            registers,
+            period,
+            wide: capabilities & CAPABILITY_64_BIT != 0,
+        })
+    }
+
+    /// Reads the main counter
+    pub fn counter(&self) -> u64 {
+        if self.wide {
+            self.registers.read(REGISTER_MAIN_COUNTER)
+        } else {
+            self.registers.read::<u32>(REGISTER_MAIN_COUNTER) as u64
+        }
+    }
+
+    /// The frequency of the main counter, in hertz
+    pub fn frequency(&self) -> u64 {
+        FEMTOSECONDS_PER_SECOND / self.period
+    }
+
+    /// Returns `true` if the main counter is 64 bits wide. A 32 bit counter wraps within minutes.
+    pub fn is_wide(&self) -> bool {
+        self.wide
+    }
+}
+
+/// Gets the HPET, setting it up if this is the first call. `acpi::init` must have been called.
+pub fn get() -> Option<&'static Hpet> {
+    HPET.call_once(|| {
+        match Hpet::new() {
+            Ok(hpet) => {
+                debug!("hpet: {} Hz, {} bit counter", hpet.frequency(), if hpet.wide { 64 } else { 32 });
+                Some(hpet)
+            }
+            Err(HpetError::Acpi(AcpiError::TableNotFound)) => None,
+            Err(error) => {
+                warn!("hpet: unable to set up: {:?}", error);
+                None
+            }
+        }
+    }).as_ref()
+}
@@ -0,0 +1,542 @@
+//! # IOMMU
+//!
+//! Confines the DMA of PCI devices to the buffers they are granted, using Intel VT-d DMA remapping.
+//! The remapping units are found in the ACPI DMAR table. Each device behind a unit is given its own
+//! domain, with second level page tables which map only what has been granted to it, at the same
+//! address as in physical memory so that drivers need not translate addresses. DMA to any other
+//! address is blocked by the unit.
+//!
+//! Memory which the firmware reports as used by a device's DMA, through RMRR structures, is granted
+//! to it when translation is enabled, as devices such as USB controllers keep using it.
+//!
+//! Translation can be disabled with the `noiommu` boot argument.
+//!
+//! # Note
+//!
+//! Only devices on PCI segment 0 whose scope is a single path entry are recognized, as the buses
+//! behind PCI bridges cannot be found without enumerating PCI.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let buffer = dma::alloc_contiguous(4096, PAGE_SIZE, true)?;
+//! iommu::grant(SourceId { bus: 0, device: 3, function: 0 }, &buffer)?;
+//! ```
+
+use acpi::{self, AcpiError};
+use bootargs;
+use memory::{self, PhysicalAddress, PAGE_SIZE};
+use memory::dma::DmaBuffer;
+use memory::frame::FrameAllocator;
+use memory::mmio::{self, MmioError, MmioRegion};
+use spin::Mutex;
+
+const DMAR_SIGNATURE: &'static [u8] = b"DMAR";
+/// The offset of the first remapping structure in the DMAR table
+const DMAR_STRUCTURES: usize = 48;
+
+const STRUCTURE_DRHD: u16 = 0;
+const STRUCTURE_RMRR: u16 = 1;
+/// Set in a DRHD's flags if it handles every device in its segment not handled by another unit
+const DRHD_INCLUDE_PCI_ALL: u8 = 1 << 0;
+/// The offset of the device scopes in a DRHD
+const DRHD_SCOPES: usize = 16;
+/// The offset of the device scopes in an RMRR
+const RMRR_SCOPES: usize = 24;
+const SCOPE_PCI_ENDPOINT: u8 = 1;
+
+/// The maximum amount of remapping units which can be used
+const MAX_UNITS: usize = 4;
+/// The maximum amount of devices which can be recognized in the scope of a unit
+const MAX_SCOPES: usize = 16;
+
+const REGISTER_CAPABILITY: usize = 0x08;
+const REGISTER_EXTENDED_CAPABILITY: usize = 0x10;
+const REGISTER_GLOBAL_COMMAND: usize = 0x18;
+const REGISTER_GLOBAL_STATUS: usize = 0x1C;
+const REGISTER_ROOT_TABLE: usize = 0x20;
+const REGISTER_CONTEXT_COMMAND: usize = 0x28;
+/// The offset of the IOTLB invalidate register from the IOTLB registers reported by the unit
+const REGISTER_IOTLB_INVALIDATE: usize = 0x08;
+const REGISTERS_SIZE: usize = 0x1000;
+
+const GLOBAL_TRANSLATION_ENABLE: u32 = 1 << 31;
+const GLOBAL_SET_ROOT_TABLE: u32 = 1 << 30;
+/// The global status bits which stay set, and must be written back with each global command
+const GLOBAL_PERSISTENT: u32 = 0x96FF_FFFF;
+
+const CONTEXT_INVALIDATE: u64 = 1 << 63;
+const CONTEXT_GLOBAL: u64 = 1 << 61;
+const IOTLB_INVALIDATE: u64 = 1 << 63;
+const IOTLB_GLOBAL: u64 = 1 << 60;
+
+/// The supported adjusted guest address widths, as a bitmap of supported page table levels
+const CAPABILITY_SAGAW_SHIFT: u64 = 8;
+const SAGAW_3_LEVEL: u64 = 1 << 1;
+const SAGAW_4_LEVEL: u64 = 1 << 2;
+/// Set if the unit snoops the CPU caches when walking tables
+const EXTENDED_COHERENT: u64 = 1 << 0;
+const EXTENDED_IOTLB_SHIFT: u64 = 8;
+const EXTENDED_IOTLB_MASK: u64 = 0x3FF;
+
+const ENTRY_PRESENT: u64 = 1 << 0;
+const PAGE_READ: u64 = 1 << 0;
+const PAGE_WRITE: u64 = 1 << 1;
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+const CONTEXT_DOMAIN_SHIFT: u64 = 8;
+
+/// The amount of status polls before a command is considered to have failed
+const COMMAND_TIMEOUT: usize = 1_000_000;
+
+/// The size of a cache line flushed by `clflush`
+const CACHE_LINE_SIZE: usize = 64;
+
+/// The remapping units in use
+static UNITS: Mutex<[Option<Unit>; MAX_UNITS]> = Mutex::new([None, None, None, None]);
+
+/// The PCI bus, device and function of a device, which identify it to the IOMMU
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SourceId {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl SourceId {
+    /// The index of this device's entry in its bus's context table
+    fn context_index(&self) -> usize {
+        ((self.device as usize) << 3) | self.function as usize
+    }
+}
+
+/// An error returned when setting up the IOMMU or granting memory to a device
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum IommuError {
+    Acpi(AcpiError),
+    /// The DMAR table or one of its structures is malformed
+    InvalidTable,
+    /// More than `MAX_UNITS` remapping units were found
+    TooManyUnits,
+    /// A unit supports neither 3 nor 4 level page tables
+    UnsupportedWidth,
+    /// The address lies above what a unit can translate
+    AddressTooHigh,
+    /// The frame allocator is not initialized
+    NoFrameAllocator,
+    OutOfFrames,
+    /// A unit did not complete a command
+    Timeout,
+    Mmio(MmioError),
+}
+
+impl From<AcpiError> for IommuError {
+    fn from(error: AcpiError) -> Self {
+        IommuError::Acpi(error)
+    }
+}
+
+impl From<MmioError> for IommuError {
+    fn from(error: MmioError) -> Self {
+        IommuError::Mmio(error)
+    }
+}
+
+/// A DMA remapping hardware unit
+struct Unit {
+    registers: MmioRegion,
+    /// Set if the unit handles every device not handled by another unit
+    include_all: bool,
+    scopes: [Option<SourceId>; MAX_SCOPES],
+    root_table: PhysicalAddress,
+    /// The amount of levels of second level page tables
+    levels: usize,
+    /// Set if the unit snoops the CPU caches, so tables need not be flushed from them
+    coherent: bool,
+    /// The offset of the IOTLB registers
+    iotlb: usize,
+    next_domain: u16,
+}
+
+impl Unit {
+    fn new<A>(registers: MmioRegion, include_all: bool, allocator: &mut A) -> Result<Self, IommuError>
+        where A: FrameAllocator
+    {
+        let capability: u64 = registers.read(REGISTER_CAPABILITY);
+        let extended: u64 = registers.read(REGISTER_EXTENDED_CAPABILITY);
+
+        let sagaw = capability >> CAPABILITY_SAGAW_SHIFT;
+        let levels = if sagaw & SAGAW_4_LEVEL != 0 {
+            4
+        } else if sagaw & SAGAW_3_LEVEL != 0 {
+            3
+        } else {
+            return Err(IommuError::UnsupportedWidth);
+        };
+
+        let coherent = extended & EXTENDED_COHERENT != 0;
+
+        Ok(Unit {
+            registers,
+            include_all,
+            scopes: [None; MAX_SCOPES],
+            root_table: allocate_table(allocator, coherent)?,
+            levels,
+            coherent,
+            iotlb: ((extended >> EXTENDED_IOTLB_SHIFT) & EXTENDED_IOTLB_MASK) as usize * 16,
+            next_domain: 1,
+        })
+    }
+
+    /// Returns `true` if this unit lists the given device in its scope
+    fn has_scope(&self, source: SourceId) -> bool {
+        self.scopes.iter().any(|&scope| scope == Some(source))
+    }
+
+    /// Gets the second level page tables of the given device, creating its context if it has none
+    fn context<A>(&mut self, source: SourceId, allocator: &mut A) -> Result<PhysicalAddress, IommuError>
+        where A: FrameAllocator
+    {
+        let root = table(self.root_table);
+        let root_index = source.bus as usize * 2;
+
+        if root[root_index] & ENTRY_PRESENT == 0 {
+            root[root_index] = allocate_table(allocator, self.coherent)? as u64 | ENTRY_PRESENT;
+            self.flush(&root[root_index]);
+        }
+
+        let context = table((root[root_index] & ADDRESS_MASK) as PhysicalAddress);
+        let context_index = source.context_index() * 2;
+
+        if context[context_index] & ENTRY_PRESENT == 0 {
+            let tables = allocate_table(allocator, self.coherent)?;
+            let width = match self.levels {
+                4 => 2,
+                _ => 1,
+            };
+
+            context[context_index + 1] = width | (self.next_domain as u64) << CONTEXT_DOMAIN_SHIFT;
+            context[context_index] = tables as u64 | ENTRY_PRESENT;
+            self.flush(&context[context_index]);
+            self.next_domain += 1;
+        }
+
+        Ok((context[context_index] & ADDRESS_MASK) as PhysicalAddress)
+    }
+
+    /// Maps the page at the given address to itself in the given tables, or unmaps it
+    fn set_page<A>(&mut self, tables: PhysicalAddress, address: PhysicalAddress, mapped: bool, allocator: &mut A)
+        -> Result<(), IommuError>
+        where A: FrameAllocator
+    {
+        if address >> (12 + 9 * self.levels) != 0 {
+            return Err(IommuError::AddressTooHigh);
+        }
+
+        let mut current = table(tables);
+        for level in (1..self.levels).rev() {
+            let index = (address >> (12 + 9 * level)) & 0x1FF;
+
+            if current[index] & (PAGE_READ | PAGE_WRITE) == 0 {
+                if !mapped {
+                    return Ok(());
+                }
+
+                current[index] = allocate_table(allocator, self.coherent)? as u64 | PAGE_READ | PAGE_WRITE;
+                self.flush(&current[index]);
+            }
+
+            current = table((current[index] & ADDRESS_MASK) as PhysicalAddress);
+        }
+
+        let index = (address >> 12) & 0x1FF;
+        current[index] = if mapped { address as u64 | PAGE_READ | PAGE_WRITE } else { 0 };
+        self.flush(&current[index]);
+
+        Ok(())
+    }
+
+    /// Flushes the given table entry from the CPU caches, if the unit does not snoop them
+    fn flush(&self, entry: &u64) {
+        if !self.coherent {
+            flush(entry as *const u64 as usize);
+        }
+    }
+
+    /// Invalidates every cached context entry and translation
+    fn invalidate(&self) -> Result<(), IommuError> {
+        self.registers.write(REGISTER_CONTEXT_COMMAND, CONTEXT_INVALIDATE | CONTEXT_GLOBAL);
+        self.wait(|unit| unit.registers.read::<u64>(REGISTER_CONTEXT_COMMAND) & CONTEXT_INVALIDATE == 0)?;
+
+        let iotlb = self.iotlb + REGISTER_IOTLB_INVALIDATE;
+        self.registers.write(iotlb, IOTLB_INVALIDATE | IOTLB_GLOBAL);
+        self.wait(|unit| unit.registers.read::<u64>(iotlb) & IOTLB_INVALIDATE == 0)
+    }
+
+    /// Sets the root table and enables translation
+    fn enable(&self) -> Result<(), IommuError> {
+        self.registers.write(REGISTER_ROOT_TABLE, self.root_table as u64);
+        self.command(GLOBAL_SET_ROOT_TABLE)?;
+        self.invalidate()?;
+        self.command(GLOBAL_TRANSLATION_ENABLE)
+    }
+
+    /// Sends the given global command, and waits for the unit to report it done
+    fn command(&self, command: u32) -> Result<(), IommuError> {
+        let status: u32 = self.registers.read(REGISTER_GLOBAL_STATUS);
+        self.registers.write(REGISTER_GLOBAL_COMMAND, (status & GLOBAL_PERSISTENT) | command);
+        self.wait(|unit| unit.registers.read::<u32>(REGISTER_GLOBAL_STATUS) & command != 0)
+    }
+
+    fn wait<F>(&self, done: F) -> Result<(), IommuError>
+        where F: Fn(&Unit) -> bool
+    {
+        for _ in 0..COMMAND_TIMEOUT {
+            if done(self) {
+                return Ok(());
+            }
+        }
+
+        Err(IommuError::Timeout)
+    }
+}
+
+/// Finds the remapping units in the DMAR table and enables translation. This must be called after
+/// `acpi::init`.
+pub fn init() {
+    if bootargs::has("noiommu") {
+        info!("iommu: disabled by boot argument");
+        return;
+    }
+
+    match init_units() {
+        Ok(0) => (),
+        Ok(count) => info!("iommu: translation enabled on {} units", count),
+        Err(IommuError::Acpi(AcpiError::TableNotFound)) => {
+            debug!("iommu: no dmar table");
+        }
+        Err(error) => warn!("iommu: unable to enable translation: {:?}", error),
+    }
+}
+
+fn init_units() -> Result<usize, IommuError> {
+    let dmar = acpi::find(DMAR_SIGNATURE)?;
+    if dmar.len() < DMAR_STRUCTURES {
+        return Err(IommuError::InvalidTable);
+    }
+
+    let mut units = UNITS.lock();
+    let mut count = 0;
+
+    for structure in Structures::new(dmar) {
+        let structure = structure?;
+        let segment = structure.get(6..8).map(|segment| acpi::read_u16(segment, 0));
+
+        if acpi::read_u16(structure, 0) != STRUCTURE_DRHD || segment != Some(0) {
+            continue;
+        }
+
+        if structure.len() < DRHD_SCOPES {
+            return Err(IommuError::InvalidTable);
+        }
+
+        if count == MAX_UNITS {
+            return Err(IommuError::TooManyUnits);
+        }
+
+        let base = acpi::read_u64(structure, 8) as PhysicalAddress;
+        let registers = mmio::map(base, REGISTERS_SIZE, "iommu")?;
+
+        let mut allocator = memory::FRAME_ALLOCATOR.lock();
+        let allocator = allocator.as_mut().ok_or(IommuError::NoFrameAllocator)?;
+        let mut unit = Unit::new(registers, structure[4] & DRHD_INCLUDE_PCI_ALL != 0, allocator)?;
+
+        for (slot, source) in unit.scopes.iter_mut().zip(endpoints(&structure[DRHD_SCOPES..])) {
+            *slot = Some(source);
+        }
+
+        debug!("iommu: unit at {:#x}, {} level tables", base, unit.levels);
+        units[count] = Some(unit);
+        count += 1;
+    }
+
+    // Devices keep using the memory reported by the firmware, so it must be granted before translation
+    // is enabled
+    for structure in Structures::new(dmar) {
+        let structure = structure?;
+        let segment = structure.get(6..8).map(|segment| acpi::read_u16(segment, 0));
+
+        if acpi::read_u16(structure, 0) != STRUCTURE_RMRR || segment != Some(0) {
+            continue;
+        }
+
+        if structure.len() < RMRR_SCOPES {
+            return Err(IommuError::InvalidTable);
+        }
+
+        let base = acpi::read_u64(structure, 8) as PhysicalAddress;
+        let limit = acpi::read_u64(structure, 16) as PhysicalAddress;
+        if limit < base {
+            return Err(IommuError::InvalidTable);
+        }
+
+        for source in endpoints(&structure[RMRR_SCOPES..]) {
+            set_range(&mut units, source, base, limit + 1 - base, true)?;
+        }
+    }
+
+    for unit in units.iter().filter_map(|unit| unit.as_ref()) {
+        unit.enable()?;
+    }
+
+    Ok(count)
+}
+
+/// Allows the given device to access the given buffer through DMA
+pub fn grant(source: SourceId, buffer: &DmaBuffer) -> Result<(), IommuError> {
+    grant_range(source, buffer.physical_address(), buffer.len())
+}
+
+/// Allows the given device to access the given physical range through DMA. Devices which are not
+/// behind a remapping unit are not confined, so granting to them does nothing.
+pub fn grant_range(source: SourceId, start: PhysicalAddress, len: usize) -> Result<(), IommuError> {
+    set_range(&mut UNITS.lock(), source, start, len, true)
+}
+
+/// Stops the given device from accessing the given buffer
+#[allow(dead_code)] // Part of API
+pub fn revoke(source: SourceId, buffer: &DmaBuffer) -> Result<(), IommuError> {
+    set_range(&mut UNITS.lock(), source, buffer.physical_address(), buffer.len(), false)
+}
+
+/// Maps or unmaps the given range in the tables of the given device
+fn set_range(units: &mut [Option<Unit>; MAX_UNITS], source: SourceId, start: PhysicalAddress, len: usize, mapped: bool)
+    -> Result<(), IommuError>
+{
+    // A unit which lists the device handles it before one which includes every device
+    let index = units.iter()
+        .position(|unit| unit.as_ref().map_or(false, |unit| unit.has_scope(source)))
+        .or_else(|| units.iter().position(|unit| unit.as_ref().map_or(false, |unit| unit.include_all)));
+
+    let unit = match index {
+        Some(index) => units[index].as_mut().expect("Unit should exist"),
+        None => return Ok(()),
+    };
+
+    let mut allocator = memory::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or(IommuError::NoFrameAllocator)?;
+    let tables = unit.context(source, allocator)?;
+
+    let mut address = start & !(PAGE_SIZE - 1);
+    while address < start + len {
+        unit.set_page(tables, address, mapped, allocator)?;
+        address += PAGE_SIZE;
+    }
+
+    unit.invalidate()
+}
+
+/// Allocates a zeroed table
+fn allocate_table<A>(allocator: &mut A, coherent: bool) -> Result<PhysicalAddress, IommuError>
+    where A: FrameAllocator
+{
+    let address = allocator.allocate_frame().ok_or(IommuError::OutOfFrames)?.start_address();
+
+    for entry in table(address).iter_mut() {
+        *entry = 0;
+    }
+
+    if !coherent {
+        let mut line = 0;
+        while line < PAGE_SIZE {
+            flush(memory::phys_to_virt(address) + line);
+            line += CACHE_LINE_SIZE;
+        }
+    }
+
+    Ok(address)
+}
+
+/// Gets the table at the given physical address, through the physical memory map
+fn table(address: PhysicalAddress) -> &'static mut [u64; 512] {
+    unsafe { &mut *(memory::phys_to_virt(address) as *mut [u64; 512]) }
+}
+
+/// Writes the cache line containing the given address back to memory
+fn flush(address: usize) {
+    unsafe { asm!("clflush ($0)" :: "r"(address) : "memory" : "volatile"); }
+}
+
+/// An iterator over the remapping structures of the DMAR table
+struct Structures {
+    dmar: &'static [u8],
+    offset: usize,
+}
+
+impl Structures {
+    fn new(dmar: &'static [u8]) -> Self {
+        Structures { dmar, offset: DMAR_STRUCTURES }
+    }
+}
+
+impl Iterator for Structures {
+    type Item = Result<&'static [u8], IommuError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset + 4 > self.dmar.len() {
+            return None;
+        }
+
+        let length = acpi::read_u16(self.dmar, self.offset + 2) as usize;
+        if length < 4 || self.offset + length > self.dmar.len() {
+            self.offset = self.dmar.len();
+            return Some(Err(IommuError::InvalidTable));
+        }
+
+        let structure = &self.dmar[self.offset..self.offset + length];
+        self.offset += length;
+        Some(Ok(structure))
+    }
+}
+
+/// An iterator over the PCI endpoints in a list of device scopes
+struct Endpoints<'a> {
+    scopes: &'a [u8],
+}
+
+fn endpoints(scopes: &[u8]) -> Endpoints {
+    Endpoints { scopes }
+}
+
+impl<'a> Iterator for Endpoints<'a> {
+    type Item = SourceId;
+
+    fn next(&mut self) -> Option<SourceId> {
+        loop {
+            let length = *self.scopes.get(1)? as usize;
+            if length < 6 || length > self.scopes.len() {
+                return None;
+            }
+
+            let scope = &self.scopes[..length];
+            self.scopes = &self.scopes[length..];
+
+            // The path of a device behind a bridge has more than one entry
+            if scope[0] == SCOPE_PCI_ENDPOINT && length == 8 {
+                return Some(SourceId { bus: scope[5], device: scope[6], function: scope[7] });
+            }
+        }
+    }
+}
+
+kernel_test!(fn reads_endpoint_scopes() {
+    // An endpoint at 00:02.0, a bridge, and an endpoint behind two path entries
+    let scopes = [
+        0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00,
+        0x02, 0x08, 0x00, 0x00, 0x00, 0x00, 0x1C, 0x00,
+        0x01, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x1C, 0x00, 0x00, 0x00,
+    ];
+
+    let mut endpoints = endpoints(&scopes);
+    test_assert_eq!(endpoints.next(), Some(SourceId { bus: 0, device: 2, function: 0 }));
+    test_assert_eq!(endpoints.next(), None);
+});
@@ -0,0 +1,182 @@
+//! # Chords
+//!
+//! Chords are global key combinations, such as `Ctrl+Alt+Del`, that are evaluated before a key
+//! event is delivered to the foreground consumer. When a registered chord is triggered, its handler
+//! is called and the event which completed it is swallowed.
+//!
+//! Chords are registered with the static `CHORDS` registry, which has a fixed capacity of
+//! `MAX_CHORDS`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! fn reboot(_chord: Chord) {
+//!     power::reboot();
+//! }
+//!
+//! let chord = Chord::new(ModifierFlags::CTRL | ModifierFlags::ALT, keymap::codes::DELETE);
+//! chord::CHORDS.lock().register(chord, reboot)?;
+//! ```
+
+use spin::Mutex;
+use super::{Keyboard, KeyEvent, KeyEventType, ModifierFlags};
+
+/// The maximum amount of chords that can be registered at once
+pub const MAX_CHORDS: usize = 16;
+
+/// The global chord registry
+pub static CHORDS: Mutex<ChordRegistry> = Mutex::new(ChordRegistry::new());
+
+/// A function called when a chord is triggered
+pub type ChordHandler = fn(Chord);
+
+/// An error returned when registering a chord
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ChordError {
+    /// A handler for this chord has already been registered
+    AlreadyRegistered,
+    /// The registry has no space for another chord
+    RegistryFull,
+}
+
+/// Represents a combination of modifiers and a single key that triggers a handler
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Chord {
+    /// The exact modifiers that must be held for this chord
+    pub modifiers: ModifierFlags,
+    /// The key that triggers this chord when pressed
+    pub keycode: u8,
+}
+
+impl Chord {
+    /// Creates a new chord from the given modifiers and trigger keycode
+    pub const fn new(modifiers: ModifierFlags, keycode: u8) -> Self {
+        Chord { modifiers, keycode }
+    }
+
+    /// Returns `true` if this chord is currently held on the given keyboard
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let chord = Chord::new(ModifierFlags::ALT, keymap::codes::F1);
+    /// if chord.held(&keyboard) {
+    ///     println!("Alt+F1 held");
+    /// }
+    /// ```
+    pub fn held<K: Keyboard>(&self, keyboard: &K) -> bool {
+        keyboard.pressed(self.keycode) && self.matches(ModifierFlags::from_keyboard(keyboard))
+    }
+
+    /// Returns `true` if the given modifiers trigger this chord. Right Alt is reported as `ALT_GR`,
+    /// but is also accepted where this chord requires `ALT`, so that chords such as `Ctrl+Alt+Del`
+    /// can be pressed with either Alt key.
+    pub fn matches(&self, modifiers: ModifierFlags) -> bool {
+        if modifiers == self.modifiers {
+            return true;
+        }
+
+        let requires_alt = self.modifiers.contains(ModifierFlags::ALT) && !self.modifiers.contains(ModifierFlags::ALT_GR);
+        if !requires_alt || !modifiers.contains(ModifierFlags::ALT_GR) {
+            return false;
+        }
+
+        let mut as_alt = modifiers;
+        as_alt.remove(ModifierFlags::ALT_GR);
+        as_alt.insert(ModifierFlags::ALT);
+        as_alt == self.modifiers
+    }
+}
+
+/// A fixed-capacity registry of chords and their handlers
+pub struct ChordRegistry {
+    chords: [Option<(Chord, ChordHandler)>; MAX_CHORDS],
+}
+
+impl ChordRegistry {
+    const fn new() -> Self {
+        ChordRegistry {
+            chords: [None; MAX_CHORDS],
+        }
+    }
+
+    /// Registers a handler for the given chord
+    pub fn register(&mut self, chord: Chord, handler: ChordHandler) -> Result<(), ChordError> {
+        if self.handler(chord).is_some() {
+            return Err(ChordError::AlreadyRegistered);
+        }
+
+        match self.chords.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((chord, handler));
+                Ok(())
+            }
+            None => Err(ChordError::RegistryFull),
+        }
+    }
+
+    /// Unregisters the handler for the given chord, returning `true` if one was registered
+    #[allow(dead_code)] // Part of API
+    pub fn unregister(&mut self, chord: Chord) -> bool {
+        for slot in self.chords.iter_mut() {
+            if slot.map(|(registered, _)| registered == chord).unwrap_or(false) {
+                *slot = None;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Gets the handler registered for the given chord
+    fn handler(&self, chord: Chord) -> Option<ChordHandler> {
+        self.chords.iter()
+            .filter_map(|slot| *slot)
+            .find(|&(registered, _)| registered == chord)
+            .map(|(_, handler)| handler)
+    }
+
+    /// Finds the registered chord triggered by the given key and modifiers, and its handler
+    fn find(&self, keycode: u8, modifiers: ModifierFlags) -> Option<(Chord, ChordHandler)> {
+        self.chords.iter()
+            .filter_map(|slot| *slot)
+            .find(|&(registered, _)| registered.keycode == keycode && registered.matches(modifiers))
+    }
+}
+
+/// Evaluates the given event against all registered chords, calling the matching handler. Returns
+/// `true` if the event triggered a chord, and should not be delivered further.
+///
+/// # Note
+///
+/// The keyboard's key states must already be updated for this event.
+pub fn dispatch<K: Keyboard>(keyboard: &K, event: &KeyEvent) -> bool {
+    if event.event_type != KeyEventType::Make {
+        return false;
+    }
+
+    let modifiers = ModifierFlags::from_keyboard(keyboard);
+
+    // Release the registry before calling the handler so that it may register chords itself
+    let found = CHORDS.lock().find(event.keycode, modifiers);
+
+    match found {
+        Some((chord, handler)) => {
+            handler(chord);
+            true
+        }
+        None => false,
+    }
+}
+
+kernel_test!(fn right_alt_matches_alt_chords() {
+    let reboot = Chord::new(ModifierFlags::CTRL | ModifierFlags::ALT, 0);
+    test_assert!(reboot.matches(ModifierFlags::CTRL | ModifierFlags::ALT));
+    test_assert!(reboot.matches(ModifierFlags::CTRL | ModifierFlags::ALT_GR));
+    test_assert!(!reboot.matches(ModifierFlags::ALT_GR));
+    test_assert!(!reboot.matches(ModifierFlags::CTRL | ModifierFlags::ALT | ModifierFlags::SHIFT));
+
+    let alt_gr = Chord::new(ModifierFlags::ALT_GR, 0);
+    test_assert!(alt_gr.matches(ModifierFlags::ALT_GR));
+    test_assert!(!alt_gr.matches(ModifierFlags::ALT));
+});
@@ -0,0 +1,121 @@
+//! # Compose
+//!
+//! Combines keys into accented characters. A dead key produces no character itself, and instead
+//! modifies the character of the next key, so that `´` followed by `e` types `é`. The compose key,
+//! `COMPOSE_KEY`, turns the next punctuation typed into a dead key, so that `Compose ' e` also
+//! types `é` on layouts without dead keys, such as US QWERTY.
+//!
+//! If the next key cannot be combined with the dead key, its own character is typed instead.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let mut composer = Composer::new();
+//! composer.start_dead(DeadKey::Acute);
+//! assert_eq!(composer.feed('e'), Composed::Char('é'));
+//! ```
+
+use super::keymap::codes;
+
+/// The key which starts a compose sequence. This is not a modifier, so that chords are never
+/// mistaken for compose sequences.
+pub const COMPOSE_KEY: u8 = codes::MENU;
+
+/// An accent which a dead key adds to the next character
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DeadKey {
+    Acute,
+    Grave,
+    Circumflex,
+    Diaeresis,
+    Tilde,
+    Cedilla,
+}
+
+impl DeadKey {
+    /// Gets the dead key typed by the given character after the compose key
+    pub fn from_char(character: char) -> Option<DeadKey> {
+        match character {
+            '\'' | '´' => Some(DeadKey::Acute),
+            '`' => Some(DeadKey::Grave),
+            '^' => Some(DeadKey::Circumflex),
+            '"' | '¨' => Some(DeadKey::Diaeresis),
+            '~' => Some(DeadKey::Tilde),
+            ',' | '¸' => Some(DeadKey::Cedilla),
+            _ => None,
+        }
+    }
+
+    /// Gets the given character with this dead key's accent, if there is one
+    pub fn apply(&self, base: char) -> Option<char> {
+        let (from, to) = match *self {
+            DeadKey::Acute => ("aeiouyAEIOUY ", "áéíóúýÁÉÍÓÚÝ´"),
+            DeadKey::Grave => ("aeiouAEIOU ", "àèìòùÀÈÌÒÙ`"),
+            DeadKey::Circumflex => ("aeiouAEIOU ", "âêîôûÂÊÎÔÛ^"),
+            DeadKey::Diaeresis => ("aeiouyAEIOU ", "äëïöüÿÄËÏÖÜ¨"),
+            DeadKey::Tilde => ("anoANO ", "ãñõÃÑÕ~"),
+            DeadKey::Cedilla => ("cC ", "çÇ¸"),
+        };
+
+        from.chars().position(|character| character == base)
+            .and_then(|index| to.chars().nth(index))
+    }
+}
+
+/// The result of feeding a character to a [Composer]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Composed {
+    /// The character was consumed by the sequence, and nothing should be typed
+    Pending,
+    /// The given character should be typed
+    Char(char),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// The compose key was pressed, and the next character selects the dead key
+    Compose,
+    /// The dead key applies to the next character
+    Dead(DeadKey),
+}
+
+/// Tracks dead key and compose sequences across key presses
+pub struct Composer {
+    state: State,
+}
+
+impl Composer {
+    pub const fn new() -> Self {
+        Composer { state: State::Idle }
+    }
+
+    /// Starts a compose sequence, as the compose key was pressed
+    pub fn start_compose(&mut self) {
+        self.state = State::Compose;
+    }
+
+    /// Starts a dead key sequence, as a dead key was pressed
+    #[allow(dead_code)] // Part of API
+    pub fn start_dead(&mut self, dead_key: DeadKey) {
+        self.state = State::Dead(dead_key);
+    }
+
+    /// Feeds the character of the next key pressed through the current sequence
+    pub fn feed(&mut self, character: char) -> Composed {
+        match self.state {
+            State::Idle => Composed::Char(character),
+            State::Compose => {
+                self.state = match DeadKey::from_char(character) {
+                    Some(dead_key) => State::Dead(dead_key),
+                    None => State::Idle,
+                };
+                Composed::Pending
+            }
+            State::Dead(dead_key) => {
+                self.state = State::Idle;
+                Composed::Char(dead_key.apply(character).unwrap_or(character))
+            }
+        }
+    }
+}
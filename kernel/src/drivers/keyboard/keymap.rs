@@ -110,6 +110,7 @@ pub mod codes {
     pub const NUM_PAD_0: u8 = code(11, 5);
     pub const NUM_PAD_DELETE: u8 = code(12, 5);
     pub const NUM_PAD_ENTER: u8 = code(13, 5);
+    pub const MENU: u8 = code(14, 5);
 
     /// Gets the Flower keycode for a key based on its row and column.
     const fn code(column: u8, row: u8) -> u8 {
@@ -175,6 +176,49 @@ pub fn get_us_qwerty_char(keycode: u8) -> Option<(char, char)> {
     }
 }
 
+/// Gets the third and fourth level characters typed with ALT GR for the given Flower keycode, following the US
+/// International layout. The first element is typed with ALT GR, and the second with ALT GR and SHIFT.
+pub fn get_us_international_alt_gr_char(keycode: u8) -> Option<(char, char)> {
+    match keycode {
+        codes::KEY_1 => Some(('¡', '¹')),
+        codes::KEY_2 => Some(('²', '²')),
+        codes::KEY_3 => Some(('³', '³')),
+        codes::KEY_4 => Some(('¤', '£')),
+        codes::KEY_5 => Some(('€', '€')),
+        codes::KEY_6 => Some(('¼', '¼')),
+        codes::KEY_7 => Some(('½', '½')),
+        codes::KEY_8 => Some(('¾', '¾')),
+        codes::MINUS => Some(('¥', '¥')),
+        codes::EQUALS => Some(('×', '÷')),
+        codes::Q => Some(('ä', 'Ä')),
+        codes::W => Some(('å', 'Å')),
+        codes::E => Some(('é', 'É')),
+        codes::R => Some(('®', '®')),
+        codes::T => Some(('þ', 'Þ')),
+        codes::Y => Some(('ü', 'Ü')),
+        codes::U => Some(('ú', 'Ú')),
+        codes::I => Some(('í', 'Í')),
+        codes::O => Some(('ó', 'Ó')),
+        codes::P => Some(('ö', 'Ö')),
+        codes::SQUARE_BRACKET_OPEN => Some(('«', '«')),
+        codes::SQUARE_BRACKET_CLOSE => Some(('»', '»')),
+        codes::A => Some(('á', 'Á')),
+        codes::S => Some(('ß', '§')),
+        codes::D => Some(('ð', 'Ð')),
+        codes::L => Some(('ø', 'Ø')),
+        codes::SEMI_COLON => Some(('¶', '°')),
+        codes::SINGLE_QUOTE => Some(('´', '¨')),
+        codes::BACK_SLASH => Some(('¬', '¦')),
+        codes::Z => Some(('æ', 'Æ')),
+        codes::C => Some(('©', '¢')),
+        codes::N => Some(('ñ', 'Ñ')),
+        codes::M => Some(('µ', 'µ')),
+        codes::COMMA => Some(('ç', 'Ç')),
+        codes::FORWARD_SLASH => Some(('¿', '¿')),
+        _ => None,
+    }
+}
+
 /// Gets the Flower keycode for the given PS/2 scanset 2 scancode
 pub fn get_code_ps2_set_2(scancode: u8) -> Option<u8> {
     match scancode {
@@ -272,14 +316,59 @@ pub fn get_extended_code_ps2_set_2(extended_code: u8) -> Option<u8> {
     match extended_code {
         0x11 => Some(codes::RIGHT_ALT),
         0x14 => Some(codes::RIGHT_CONTROL),
+        0x1F => Some(codes::LEFT_WIN),
+        0x27 => Some(codes::RIGHT_WIN),
+        0x2F => Some(codes::MENU),
         0x4A => Some(codes::NUM_PAD_FORWARD_SLASH),
         0x5A => Some(codes::NUM_PAD_ENTER),
         0x69 => Some(codes::END),
         0x6C => Some(codes::HOME),
         0x70 => Some(codes::INSERT),
         0x71 => Some(codes::DELETE),
+        // Sent by no key, and decoded from the Pause sequence
+        super::scancode::PAUSE_CODE => Some(codes::PAUSE),
         0x7A => Some(codes::PAGE_DOWN),
+        0x7C => Some(codes::PRINT_SCREEN),
         0x7D => Some(codes::PAGE_UP),
         _ => None,
     }
 }
+
+kernel_test!(fn letters_have_both_cases() {
+    test_assert_eq!(get_us_qwerty_char(codes::Q), Some(('q', 'Q')));
+    test_assert_eq!(get_us_qwerty_char(codes::KEY_1), Some(('1', '!')));
+    test_assert_eq!(get_us_qwerty_char(codes::LEFT_SHIFT), None);
+});
+
+kernel_test!(fn alt_gr_has_both_levels() {
+    test_assert_eq!(get_us_international_alt_gr_char(codes::E), Some(('é', 'É')));
+    test_assert_eq!(get_us_international_alt_gr_char(codes::F), None);
+});
+
+kernel_test!(fn alt_gr_is_right_alt_only() {
+    use drivers::keyboard::{ModifierFlags, Ps2Keyboard, Ps2Scancode};
+    use drivers::ps2::{Device, DevicePort, DeviceState};
+
+    let mut device = Device { state: DeviceState::Unavailable, port: DevicePort::Keyboard };
+    let mut keyboard = Ps2Keyboard::new(&mut device);
+
+    // Right Alt selects the AltGr layer
+    keyboard.process_scancode(&Ps2Scancode::new(0x11, true, true));
+    let event = keyboard.process_scancode(&Ps2Scancode::new(0x24, false, true)).ok_or("no event")?;
+    test_assert_eq!(event.modifiers, ModifierFlags::ALT_GR);
+    test_assert_eq!(event.char, Some('é'));
+    keyboard.process_scancode(&Ps2Scancode::new(0x24, false, false));
+    keyboard.process_scancode(&Ps2Scancode::new(0x11, true, false));
+
+    // Ctrl and left Alt are not treated as AltGr
+    keyboard.process_scancode(&Ps2Scancode::new(0x14, false, true));
+    keyboard.process_scancode(&Ps2Scancode::new(0x11, false, true));
+    let event = keyboard.process_scancode(&Ps2Scancode::new(0x24, false, true)).ok_or("no event")?;
+    test_assert_eq!(event.modifiers, ModifierFlags::CTRL | ModifierFlags::ALT);
+    test_assert_eq!(event.char, Some('e'));
+});
+
+kernel_test!(fn extended_codes_are_distinct() {
+    test_assert_eq!(get_code_ps2_set_2(0x14), Some(codes::LEFT_CONTROL));
+    test_assert_eq!(get_extended_code_ps2_set_2(0x14), Some(codes::RIGHT_CONTROL));
+});
@@ -6,11 +6,14 @@
 //! The driver is event based, and events are received through the `read_event` method, which blocks until an event is received.
 //! The event contains the keycode pressed, which can be compared to `keymap::codes`, an optional `char`, the type of press, and various modifier flags.
 //!
+//! Global key chords (such as `Ctrl+Alt+Del`) can be registered through the `chord` module. These are evaluated before an event is returned
+//! from `read_event`, and events which trigger a chord are not delivered.
+//!
 //! # Examples
 //!
 //! ```rust,no_run
-//! let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-//! let mut keyboard = Ps2Keyboard::new(device);
+//! let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+//! let mut keyboard = Ps2Keyboard::new(&mut device);
 //!
 //! keyboard.enable()?;
 //! loop {
@@ -20,12 +23,20 @@
 //! ```
 
 pub mod keymap;
+pub mod chord;
+pub mod compose;
+pub mod scancode;
+#[cfg(feature = "integration-test")]
+pub mod tests;
 
 use core::convert::From;
 
-use drivers::ps2::{self, Device, DeviceState};
+use drivers::mouse;
+use drivers::ps2::{self, Device, DevicePort, DeviceState};
 use drivers::ps2::io::Ps2Error;
 use drivers::ps2::io::commands::{DeviceCommand, DeviceDataCommand};
+use self::compose::{Composed, Composer};
+use self::scancode::ScancodeDecoder;
 
 bitflags! {
     pub struct ModifierFlags: u8 {
@@ -35,6 +46,10 @@ bitflags! {
         const ALT = 1 << 1;
         /// If a SHIFT modifier is active
         const SHIFT = 1 << 2;
+        /// If the ALT GR (right ALT) modifier is active, selecting the third and fourth character levels
+        const ALT_GR = 1 << 3;
+        /// If a META (Windows) modifier is active
+        const META = 1 << 4;
     }
 }
 
@@ -44,16 +59,28 @@ impl ModifierFlags {
     /// # Examples
     ///
     /// ```rust
-    /// let modifiers = ModifierFlags::from_modifiers(true, true, true);
+    /// let modifiers = ModifierFlags::from_modifiers(true, true, true, false, false);
     /// assert_eq!(modifiers, ModifierFlags::CTRL | ModifierFlags::ALT | ModifierFlags::SHIFT);
     /// ```
-    fn from_modifiers(ctrl: bool, alt: bool, shift: bool) -> Self {
+    fn from_modifiers(ctrl: bool, alt: bool, shift: bool, alt_gr: bool, meta: bool) -> Self {
         let mut flags = ModifierFlags::empty();
         flags.set(ModifierFlags::CTRL, ctrl);
         flags.set(ModifierFlags::ALT, alt);
         flags.set(ModifierFlags::SHIFT, shift);
+        flags.set(ModifierFlags::ALT_GR, alt_gr);
+        flags.set(ModifierFlags::META, meta);
         flags
     }
+
+    /// Creates `ModifierFlags` from the modifier keys currently pressed on the given keyboard
+    pub fn from_keyboard<K: Keyboard + ?Sized>(keyboard: &K) -> Self {
+        let ctrl = keyboard.pressed(keymap::codes::LEFT_CONTROL) || keyboard.pressed(keymap::codes::RIGHT_CONTROL);
+        let alt = keyboard.pressed(keymap::codes::LEFT_ALT);
+        let shift = keyboard.pressed(keymap::codes::LEFT_SHIFT) || keyboard.pressed(keymap::codes::RIGHT_SHIFT);
+        let alt_gr = keyboard.pressed(keymap::codes::RIGHT_ALT);
+        let meta = keyboard.pressed(keymap::codes::LEFT_WIN) || keyboard.pressed(keymap::codes::RIGHT_WIN);
+        ModifierFlags::from_modifiers(ctrl, alt, shift, alt_gr, meta)
+    }
 }
 
 /// Contains data relating to a key press event
@@ -105,8 +132,8 @@ pub trait Keyboard {
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-    /// let mut keyboard = Ps2Keyboard::new(device);
+    /// let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+    /// let mut keyboard = Ps2Keyboard::new(&mut device);
     ///
     /// match keyboard.enable() {
     ///     Ok(_) => println!("Keyboard successfully enabled"),
@@ -124,8 +151,8 @@ pub trait Keyboard {
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-    /// let mut keyboard = Ps2Keyboard::new(device);
+    /// let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+    /// let mut keyboard = Ps2Keyboard::new(&mut device);
     ///
     /// match keyboard.disable() {
     ///     Ok(_) => println!("Keyboard successfully disabled"),
@@ -139,8 +166,8 @@ pub trait Keyboard {
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-    /// let mut keyboard = Ps2Keyboard::new(device);
+    /// let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+    /// let mut keyboard = Ps2Keyboard::new(&mut device);
     ///
     /// if let Some(event) = keyboard.read_event()? {
     ///     println!("Event occurred for char: {}", event.char.unwrap_or(' '));
@@ -152,8 +179,8 @@ pub trait Keyboard {
     /// Returns `true` if the given keycode is currently being pressed
     ///
     /// ```rust,no_run
-    /// let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-    /// let mut keyboard = Ps2Keyboard::new(device);
+    /// let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+    /// let mut keyboard = Ps2Keyboard::new(&mut device);
     ///
     /// if keyboard.pressed(keymap::codes::LEFT_SHIFT) {
     ///     println!("Left shift pressed");
@@ -168,6 +195,8 @@ pub trait Keyboard {
 pub struct Ps2Keyboard<'a> {
     device: &'a mut Device,
     key_states: [bool; 0xFF],
+    decoder: ScancodeDecoder,
+    composer: Composer,
 }
 
 impl<'a> Ps2Keyboard<'a> {
@@ -176,13 +205,15 @@ impl<'a> Ps2Keyboard<'a> {
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-    /// let mut keyboard = Ps2Keyboard::new(device);
+    /// let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+    /// let mut keyboard = Ps2Keyboard::new(&mut device);
     /// ```
     pub fn new(device: &'a mut Device) -> Self {
         Ps2Keyboard {
             device,
             key_states: [false; 0xFF],
+            decoder: ScancodeDecoder::new(),
+            composer: Composer::new(),
         }
     }
 
@@ -191,41 +222,47 @@ impl<'a> Ps2Keyboard<'a> {
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let device = drivers::ps2::CONTROLLER.device(drivers::ps2::DevicePort::Keyboard);
-    /// let mut keyboard = Ps2Keyboard::new(device);
+    /// let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Keyboard).lock();
+    /// let mut keyboard = Ps2Keyboard::new(&mut device);
     ///
     /// if let Some(scancode) = keyboard.read_scancode()? {
     ///     print!(scancode);
     /// }
     /// ```
-    fn read_scancode(&self) -> Result<Option<Ps2Scancode>, Ps2KeyboardError> {
+    fn read_scancode(&mut self) -> Result<Option<Ps2Scancode>, Ps2KeyboardError> {
         use ps2::io;
 
         if self.device.state == DeviceState::Enabled {
+            if let Some(scancode) = self.decoder.take_pending() {
+                return Ok(Some(scancode));
+            }
+
             if io::can_read()? && io::can_read_keyboard()? {
-                let mut make = true;
-                let mut extended = false;
+                let decoder = &mut self.decoder;
 
-                // Get all scancode modifiers, and return when the actual scancode is received
+                // Read until a sequence is complete, as its remaining bytes follow immediately
                 let scancode = (io::DATA_PORT.with_lock(|mut data_port| {
                     loop {
-                        let data = io::read(&mut data_port)?;
-                        match data {
-                            0xE0 ... 0xE1 => extended = true,
-                            0xF0 => make = false,
-                            _ => {
-                                break Ok(data);
-                            }
+                        let (data, source) = io::read_with_source(&mut data_port)?;
+
+                        // Mouse bytes share the data port, and may arrive between the bytes of a sequence
+                        if source == DevicePort::Mouse {
+                            mouse::receive(data);
+                            continue;
+                        }
+
+                        let scancode = decoder.feed(data);
+
+                        if scancode.is_some() || decoder.is_idle() {
+                            break Ok(scancode);
                         }
                     }
-                }): Result<u8, io::Ps2Error>)?;
+                }): Result<Option<Ps2Scancode>, io::Ps2Error>)?;
 
-                // If scancode is present, return it with modifiers
-                return Ok(if scancode != 0 {
-                    Some(Ps2Scancode::new(scancode, extended, make))
-                } else {
-                    None
-                });
+                // Key presses happen at unpredictable times
+                ::rand::add_timing_entropy();
+
+                return Ok(scancode);
             }
             Ok(None)
         } else {
@@ -245,13 +282,18 @@ impl<'a> Ps2Keyboard<'a> {
     /// assert_eq!(event.event_type, KeyEventType::Make);
     /// ```
     fn create_event(&self, scancode: &Ps2Scancode) -> Option<KeyEvent> {
-        let ctrl = self.pressed(keymap::codes::LEFT_CONTROL) || self.pressed(keymap::codes::RIGHT_CONTROL);
-        let alt = self.pressed(keymap::codes::LEFT_ALT) || self.pressed(keymap::codes::RIGHT_ALT);
-        let shift = self.pressed(keymap::codes::LEFT_SHIFT) || self.pressed(keymap::codes::RIGHT_SHIFT);
-        let modifiers = ModifierFlags::from_modifiers(ctrl, alt, shift);
+        let modifiers = ModifierFlags::from_keyboard(self);
+        let shift = modifiers.contains(ModifierFlags::SHIFT);
 
         if let Some(keycode) = scancode.keycode() {
-            let char = keymap::get_us_qwerty_char(keycode)
+            // Keys without a third level character type their usual character with ALT GR held
+            let alt_gr_chars = if modifiers.contains(ModifierFlags::ALT_GR) {
+                keymap::get_us_international_alt_gr_char(keycode)
+            } else {
+                None
+            };
+
+            let char = alt_gr_chars.or_else(|| keymap::get_us_qwerty_char(keycode))
                 .map(|chars| if shift {
                     chars.1
                 } else {
@@ -270,6 +312,31 @@ impl<'a> Ps2Keyboard<'a> {
 
         None
     }
+
+    /// Updates the key state for the given scancode, and creates the event to deliver for it.
+    /// Events which trigger a chord are consumed and not delivered, and the characters of presses
+    /// are passed through compose and dead key sequences.
+    fn process_scancode(&mut self, scancode: &Ps2Scancode) -> Option<KeyEvent> {
+        let mut event = self.create_event(scancode)?;
+        self.key_states[event.keycode as usize] = scancode.make;
+
+        if chord::dispatch(&*self, &event) {
+            return None;
+        }
+
+        if event.event_type != KeyEventType::Break {
+            if event.keycode == compose::COMPOSE_KEY {
+                self.composer.start_compose();
+            } else if let Some(character) = event.char {
+                event.char = match self.composer.feed(character) {
+                    Composed::Pending => None,
+                    Composed::Char(character) => Some(character),
+                };
+            }
+        }
+
+        Some(event)
+    }
 }
 
 impl<'a> Keyboard for Ps2Keyboard<'a> {
@@ -300,14 +367,7 @@ impl<'a> Keyboard for Ps2Keyboard<'a> {
     }
 
     fn read_event(&mut self) -> Result<Option<KeyEvent>, Self::Error> {
-        let event = self.read_scancode()?.and_then(|scancode| {
-            let event = self.create_event(&scancode);
-            if event.is_some() {
-                self.key_states[event.unwrap().keycode as usize] = scancode.make;
-            }
-            event
-        });
-        Ok(event)
+        Ok(self.read_scancode()?.and_then(|scancode| self.process_scancode(&scancode)))
     }
 
     fn pressed(&self, keycode: u8) -> bool {
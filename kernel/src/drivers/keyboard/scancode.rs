@@ -0,0 +1,102 @@
+//! # Scancode Decoder
+//!
+//! Decodes the bytes sent by a PS/2 keyboard in scancode set 2 into [Ps2Scancode]s, one byte at a
+//! time. Most keys send a single code, preceded by `0xE0` if they are extended and `0xF0` when
+//! released. Two keys send longer sequences:
+//!
+//! - Pause sends `E1 14 77 E1 F0 14 F0 77` when pressed, and nothing when released. It is decoded
+//!   as a press and release of the extended code `PAUSE_CODE`, which no key sends.
+//! - Print Screen is wrapped in a fake shift, sending `E0 12 E0 7C` when pressed and
+//!   `E0 F0 7C E0 F0 12` when released. The fake shifts are dropped.
+
+use super::Ps2Scancode;
+
+/// The extended code the Pause sequence is decoded as
+pub const PAUSE_CODE: u8 = 0x77;
+
+const EXTENDED: u8 = 0xE0;
+const PAUSE: u8 = 0xE1;
+const BREAK: u8 = 0xF0;
+
+/// The amount of bytes following the first of the Pause sequence
+const PAUSE_LENGTH: u8 = 7;
+
+/// The extended codes of the fake shifts sent around some keys, such as Print Screen
+const FAKE_SHIFTS: [u8; 2] = [0x12, 0x59];
+
+/// Bytes which may be read from the keyboard which are responses rather than scancodes
+const NON_SCANCODES: [u8; 6] = [0x00, 0xAA, 0xEE, 0xFA, 0xFE, 0xFF];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum State {
+    /// Waiting for the start of a sequence
+    Idle,
+    /// After an extended prefix
+    Extended,
+    /// After a break prefix
+    Break,
+    /// After an extended prefix and a break prefix
+    ExtendedBreak,
+    /// Within the Pause sequence, with the given amount of bytes left
+    Pause(u8),
+}
+
+/// Decodes PS/2 scancode set 2 byte sequences into scancodes
+pub struct ScancodeDecoder {
+    state: State,
+    /// Set when the Pause sequence ends, so that its release is returned on the next call
+    pause_release: bool,
+}
+
+impl ScancodeDecoder {
+    pub const fn new() -> Self {
+        ScancodeDecoder { state: State::Idle, pause_release: false }
+    }
+
+    /// Decodes the next byte, returning a scancode if it completed one
+    pub fn feed(&mut self, byte: u8) -> Option<Ps2Scancode> {
+        let (state, scancode) = match (self.state, byte) {
+            (State::Pause(1), _) => {
+                self.pause_release = true;
+                (State::Idle, Some(Ps2Scancode::new(PAUSE_CODE, true, true)))
+            }
+            (State::Pause(remaining), _) => (State::Pause(remaining - 1), None),
+            (State::Idle, EXTENDED) => (State::Extended, None),
+            (State::Idle, PAUSE) => (State::Pause(PAUSE_LENGTH), None),
+            (State::Idle, BREAK) => (State::Break, None),
+            (State::Idle, code) if NON_SCANCODES.contains(&code) => (State::Idle, None),
+            (State::Idle, code) => (State::Idle, Some(Ps2Scancode::new(code, false, true))),
+            (State::Extended, BREAK) => (State::ExtendedBreak, None),
+            (State::Extended, code) => (State::Idle, extended(code, true)),
+            (State::Break, code) => (State::Idle, Some(Ps2Scancode::new(code, false, false))),
+            (State::ExtendedBreak, code) => (State::Idle, extended(code, false)),
+        };
+
+        self.state = state;
+        scancode
+    }
+
+    /// Returns the release of a decoded Pause key, which is not sent by the keyboard
+    pub fn take_pending(&mut self) -> Option<Ps2Scancode> {
+        if self.pause_release {
+            self.pause_release = false;
+            Some(Ps2Scancode::new(PAUSE_CODE, true, false))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the decoder is not within a sequence
+    pub fn is_idle(&self) -> bool {
+        self.state == State::Idle
+    }
+}
+
+/// Creates the scancode for an extended code, dropping fake shifts
+fn extended(code: u8, make: bool) -> Option<Ps2Scancode> {
+    if FAKE_SHIFTS.contains(&code) {
+        None
+    } else {
+        Some(Ps2Scancode::new(code, true, make))
+    }
+}
@@ -0,0 +1,144 @@
+//! Integration tests for the PS/2 keyboard state machine. Scancodes are fed in directly, so no
+//! device needs to be attached.
+
+use drivers::ps2::{Device, DevicePort, DeviceState};
+use integration::{Suite, TestResult};
+use super::{Keyboard, KeyEventType, ModifierFlags, Ps2Keyboard, Ps2Scancode};
+use super::keymap::codes;
+use super::scancode::ScancodeDecoder;
+
+pub const SUITE: Suite = Suite {
+    name: "ps2",
+    tests: &[
+        test_case!(translates_scancodes),
+        test_case!(make_repeat_break),
+        test_case!(shift_changes_char),
+        test_case!(extended_modifiers),
+        test_case!(decodes_sequences),
+        test_case!(composes_accents),
+        test_case!(right_win_does_not_compose),
+    ],
+};
+
+fn device() -> Device {
+    Device { state: DeviceState::Unavailable, port: DevicePort::Keyboard }
+}
+
+fn translates_scancodes() -> TestResult {
+    test_assert_eq!(Ps2Scancode::new(0x15, false, true).keycode(), Some(codes::Q));
+    test_assert_eq!(Ps2Scancode::new(0x71, true, true).keycode(), Some(codes::DELETE));
+    test_assert_eq!(Ps2Scancode::new(0x02, false, true).keycode(), None);
+    Ok(())
+}
+
+fn make_repeat_break() -> TestResult {
+    let mut device = device();
+    let mut keyboard = Ps2Keyboard::new(&mut device);
+
+    let make = keyboard.process_scancode(&Ps2Scancode::new(0x1C, false, true)).ok_or("no make event")?;
+    test_assert_eq!(make.keycode, codes::A);
+    test_assert_eq!(make.event_type, KeyEventType::Make);
+    test_assert!(keyboard.pressed(codes::A));
+
+    let repeat = keyboard.process_scancode(&Ps2Scancode::new(0x1C, false, true)).ok_or("no repeat event")?;
+    test_assert_eq!(repeat.event_type, KeyEventType::Repeat);
+
+    let release = keyboard.process_scancode(&Ps2Scancode::new(0x1C, false, false)).ok_or("no break event")?;
+    test_assert_eq!(release.event_type, KeyEventType::Break);
+    test_assert!(!keyboard.pressed(codes::A));
+    Ok(())
+}
+
+fn shift_changes_char() -> TestResult {
+    let mut device = device();
+    let mut keyboard = Ps2Keyboard::new(&mut device);
+
+    let lower = keyboard.process_scancode(&Ps2Scancode::new(0x15, false, true)).ok_or("no event")?;
+    test_assert_eq!(lower.char, Some('q'));
+    keyboard.process_scancode(&Ps2Scancode::new(0x15, false, false));
+
+    keyboard.process_scancode(&Ps2Scancode::new(0x12, false, true));
+    let upper = keyboard.process_scancode(&Ps2Scancode::new(0x15, false, true)).ok_or("no event")?;
+    test_assert_eq!(upper.char, Some('Q'));
+    test_assert_eq!(upper.modifiers, ModifierFlags::SHIFT);
+    Ok(())
+}
+
+fn extended_modifiers() -> TestResult {
+    let mut device = device();
+    let mut keyboard = Ps2Keyboard::new(&mut device);
+
+    keyboard.process_scancode(&Ps2Scancode::new(0x14, true, true));
+    test_assert!(keyboard.pressed(codes::RIGHT_CONTROL));
+    test_assert_eq!(ModifierFlags::from_keyboard(&keyboard), ModifierFlags::CTRL);
+
+    keyboard.process_scancode(&Ps2Scancode::new(0x1F, true, true));
+    let event = keyboard.process_scancode(&Ps2Scancode::new(0x2E, false, true)).ok_or("no event")?;
+    test_assert_eq!(event.modifiers, ModifierFlags::CTRL | ModifierFlags::META);
+    Ok(())
+}
+
+/// Feeds the given bytes to the decoder, returning the keycode and make of the last scancode
+fn decode(decoder: &mut ScancodeDecoder, bytes: &[u8]) -> Option<(Option<u8>, bool)> {
+    let mut last = None;
+    for &byte in bytes {
+        if let Some(scancode) = decoder.feed(byte) {
+            last = Some((scancode.keycode(), scancode.make));
+        }
+    }
+    last
+}
+
+fn decodes_sequences() -> TestResult {
+    let mut decoder = ScancodeDecoder::new();
+
+    test_assert_eq!(decode(&mut decoder, &[0xE0, 0xF0, 0x71]), Some((Some(codes::DELETE), false)));
+    test_assert_eq!(decode(&mut decoder, &[0xE0, 0x12, 0xE0, 0x7C]), Some((Some(codes::PRINT_SCREEN), true)));
+    test_assert_eq!(decode(&mut decoder, &[0xE0, 0xF0, 0x7C, 0xE0, 0xF0, 0x12]), Some((Some(codes::PRINT_SCREEN), false)));
+    test_assert!(decoder.is_idle());
+
+    test_assert_eq!(decode(&mut decoder, &[0xE1, 0x14, 0x77, 0xE1, 0xF0, 0x14, 0xF0, 0x77]), Some((Some(codes::PAUSE), true)));
+    test_assert_eq!(decoder.take_pending().map(|scancode| (scancode.keycode(), scancode.make)), Some((Some(codes::PAUSE), false)));
+
+    // The Pause sequence's 0x77 must not be decoded as Num Lock
+    test_assert!(decoder.is_idle());
+    test_assert!(decoder.take_pending().is_none());
+    Ok(())
+}
+
+fn composes_accents() -> TestResult {
+    let mut device = device();
+    let mut keyboard = Ps2Keyboard::new(&mut device);
+
+    // Compose, then ' and e
+    keyboard.process_scancode(&Ps2Scancode::new(0x2F, true, true));
+    keyboard.process_scancode(&Ps2Scancode::new(0x2F, true, false));
+    let quote = keyboard.process_scancode(&Ps2Scancode::new(0x52, false, true)).ok_or("no event")?;
+    test_assert_eq!(quote.char, None);
+    keyboard.process_scancode(&Ps2Scancode::new(0x52, false, false));
+
+    let accented = keyboard.process_scancode(&Ps2Scancode::new(0x24, false, true)).ok_or("no event")?;
+    test_assert_eq!(accented.char, Some('é'));
+    keyboard.process_scancode(&Ps2Scancode::new(0x24, false, false));
+
+    let plain = keyboard.process_scancode(&Ps2Scancode::new(0x24, false, true)).ok_or("no event")?;
+    test_assert_eq!(plain.char, Some('e'));
+    Ok(())
+}
+
+fn right_win_does_not_compose() -> TestResult {
+    let mut device = device();
+    let mut keyboard = Ps2Keyboard::new(&mut device);
+
+    // Meta with the right Windows key, then ' and e
+    keyboard.process_scancode(&Ps2Scancode::new(0x27, true, true));
+    let quote = keyboard.process_scancode(&Ps2Scancode::new(0x52, false, true)).ok_or("no event")?;
+    test_assert!(quote.modifiers.contains(ModifierFlags::META));
+    test_assert_eq!(quote.char, Some('\''));
+    keyboard.process_scancode(&Ps2Scancode::new(0x52, false, false));
+    keyboard.process_scancode(&Ps2Scancode::new(0x27, true, false));
+
+    let plain = keyboard.process_scancode(&Ps2Scancode::new(0x24, false, true)).ok_or("no event")?;
+    test_assert_eq!(plain.char, Some('e'));
+    Ok(())
+}
@@ -1,3 +1,10 @@
 pub mod vga;
+pub mod serial;
 pub mod ps2;
 pub mod keyboard;
+pub mod mouse;
+pub mod pit;
+pub mod rtc;
+pub mod hpet;
+pub mod speaker;
+pub mod iommu;
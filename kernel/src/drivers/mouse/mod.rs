@@ -0,0 +1,584 @@
+//! # Mouse Driver
+//!
+//! The mouse driver reads movement and button state from a PS/2 mouse through the PS/2 driver. Like the keyboard,
+//! it is event based, and events are polled through `read_event`. Each packet from the mouse is turned into
+//! [MouseEvent]s for its movement, scrolling, and each button pressed or released.
+//!
+//! Packets are received on IRQ 12 rather than polled. The interrupt handler assembles the bytes into packets,
+//! resynchronizing if a byte is lost, and queues complete packets in `INPUT` for `read_event` to decode. Up to
+//! `PACKET_QUEUE_SIZE` packets are queued, and packets arriving while it is full are dropped.
+//!
+//! When enabled, the mouse is asked to use the 4 byte IntelliMouse Explorer protocol, which reports a scroll wheel
+//! and buttons 4 and 5. Mice which do not support it fall back to the wheel-only IntelliMouse protocol, or to the
+//! standard 3 byte protocol.
+//!
+//! How the mouse moves is set through a [MouseConfig]. The sample rate and resolution are sent to the device, while
+//! the sensitivity and acceleration are applied to each movement by the driver.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let mut device = drivers::ps2::device(drivers::ps2::DevicePort::Mouse).lock();
+//! let mut mouse = Ps2Mouse::new(&mut device);
+//!
+//! mouse.enable()?;
+//! mouse.configure(MouseConfig { sensitivity: 150, ..MouseConfig::DEFAULT })?;
+//! match mouse.read_event()? {
+//!     Some(MouseEvent::Move { dx, dy }) => println!("Moved by {}, {}", dx, dy),
+//!     Some(MouseEvent::Press(MouseButton::Left)) => println!("Clicked"),
+//!     _ => (),
+//! }
+//! ```
+
+use arch;
+use drivers::ps2::{self, Device, DevicePort, DeviceState};
+use drivers::ps2::io::{self, Ps2Error};
+use drivers::ps2::io::commands::{DeviceCommand, DeviceDataCommand};
+use interrupts::{self, IrqError};
+use sync::IrqLock;
+
+/// The IRQ raised by the PS/2 controller when the mouse sends data
+const MOUSE_IRQ: u8 = 12;
+
+/// The maximum amount of packets waiting to be decoded
+pub const PACKET_QUEUE_SIZE: usize = 32;
+
+/// Set in the first byte of every packet, used to find the start of a packet
+const PACKET_ALWAYS_SET: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+
+/// The bits of the fourth byte of an IntelliMouse Explorer packet holding the scroll movement
+const PACKET_SCROLL: u8 = 0x0F;
+/// The bit of the fourth byte of an IntelliMouse Explorer packet set while button 4 is held
+const PACKET_BUTTON_4: u8 = 1 << 4;
+/// The bit of the fourth byte of an IntelliMouse Explorer packet set while button 5 is held
+const PACKET_BUTTON_5: u8 = 1 << 5;
+
+/// The sample rates, in samples per second, which a PS/2 mouse accepts
+pub const SAMPLE_RATES: [u8; 7] = [10, 20, 40, 60, 80, 100, 200];
+
+/// The sample rates which enable the IntelliMouse protocol
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+/// The sample rates which enable the IntelliMouse Explorer protocol
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+
+/// The largest amount of events a single packet can produce
+const MAX_PACKET_EVENTS: usize = 7;
+
+/// Packets assembled by the interrupt handler
+static INPUT: IrqLock<PacketQueue> = IrqLock::new(PacketQueue::new());
+
+bitflags! {
+    pub struct MouseButtons: u8 {
+        const LEFT = 1 << 0;
+        const RIGHT = 1 << 1;
+        const MIDDLE = 1 << 2;
+        const BACK = 1 << 3;
+        const FORWARD = 1 << 4;
+    }
+}
+
+/// A button on a mouse
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    /// Button 4, the back side button
+    Back,
+    /// Button 5, the forward side button
+    Forward,
+}
+
+impl MouseButton {
+    const ALL: [MouseButton; 5] = [
+        MouseButton::Left, MouseButton::Right, MouseButton::Middle, MouseButton::Back, MouseButton::Forward,
+    ];
+
+    /// Gets the flag for this button in [MouseButtons]
+    pub fn flag(&self) -> MouseButtons {
+        match *self {
+            MouseButton::Left => MouseButtons::LEFT,
+            MouseButton::Right => MouseButtons::RIGHT,
+            MouseButton::Middle => MouseButtons::MIDDLE,
+            MouseButton::Back => MouseButtons::BACK,
+            MouseButton::Forward => MouseButtons::FORWARD,
+        }
+    }
+}
+
+/// The packet format a mouse reports in, identified by its device ID
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Protocol {
+    /// 3 byte packets with three buttons
+    Standard,
+    /// 4 byte packets with three buttons and a scroll wheel
+    Wheel,
+    /// 4 byte packets with five buttons and a scroll wheel
+    FiveButton,
+}
+
+impl Protocol {
+    /// The size of each packet in this protocol
+    fn packet_size(&self) -> usize {
+        if *self == Protocol::Standard { 3 } else { 4 }
+    }
+}
+
+/// The resolution at which the mouse reports movement
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Resolution {
+    CountsPerMm1 = 0,
+    CountsPerMm2 = 1,
+    CountsPerMm4 = 2,
+    CountsPerMm8 = 3,
+}
+
+/// Scales movement faster than a threshold, so that fast movements travel further than slow ones
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Acceleration {
+    /// The amount of movement in a single event before acceleration applies
+    pub threshold: u16,
+    /// The percentage movement beyond the threshold is scaled by
+    pub percent: u16,
+}
+
+impl Acceleration {
+    /// No acceleration
+    pub const NONE: Acceleration = Acceleration { threshold: 0, percent: 100 };
+
+    /// Applies this acceleration to a movement
+    fn apply(&self, delta: i32) -> i32 {
+        let magnitude = delta.abs();
+        let threshold = self.threshold as i32;
+
+        if magnitude <= threshold {
+            return delta;
+        }
+
+        let accelerated = threshold + (magnitude - threshold) * self.percent as i32 / 100;
+        accelerated * delta.signum()
+    }
+}
+
+/// The settings of a mouse
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MouseConfig {
+    /// The amount of samples per second, which must be one of `SAMPLE_RATES`
+    pub sample_rate: u8,
+    pub resolution: Resolution,
+    /// The percentage all movement is scaled by
+    pub sensitivity: u16,
+    pub acceleration: Acceleration,
+}
+
+impl MouseConfig {
+    /// The settings a PS/2 mouse uses after being reset, with no scaling
+    pub const DEFAULT: MouseConfig = MouseConfig {
+        sample_rate: 100,
+        resolution: Resolution::CountsPerMm4,
+        sensitivity: 100,
+        acceleration: Acceleration::NONE,
+    };
+
+    /// Applies the sensitivity and acceleration of this config to a movement
+    fn scale(&self, delta: i32) -> i16 {
+        let scaled = self.acceleration.apply(delta * self.sensitivity as i32 / 100);
+
+        if scaled > i16::max_value() as i32 {
+            i16::max_value()
+        } else if scaled < i16::min_value() as i32 {
+            i16::min_value()
+        } else {
+            scaled as i16
+        }
+    }
+}
+
+/// An event reported by a mouse
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MouseEvent {
+    /// The mouse moved, positive to the right and upwards
+    Move { dx: i16, dy: i16 },
+    /// The scroll wheel moved, positive downwards
+    Scroll { dz: i8 },
+    /// A button was pressed
+    Press(MouseButton),
+    /// A button was released
+    Release(MouseButton),
+}
+
+/// An error for a PS/2 mouse
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Ps2MouseError {
+    /// If an error occurred while communicating over PS/2
+    ReadError(Ps2Error),
+    /// If the mouse is disabled and cannot be used
+    MouseDisabled,
+    /// If enabling the mouse fails
+    MouseEnableFailed,
+    /// If the config contains a sample rate the mouse does not support
+    InvalidSampleRate,
+    /// If the mouse did not accept a setting
+    ConfigFailed,
+    /// If enabling data reporting fails
+    ReportingEnableFailed,
+    /// If the mouse interrupt could not be registered
+    IrqUnavailable(IrqError),
+}
+
+/// Assembles bytes from the mouse into packets, and queues them until they are decoded
+struct PacketQueue {
+    packet: [u8; 4],
+    received: usize,
+    packet_size: usize,
+    packets: [[u8; 4]; PACKET_QUEUE_SIZE],
+    head: usize,
+    length: usize,
+}
+
+impl PacketQueue {
+    const fn new() -> Self {
+        PacketQueue {
+            packet: [0; 4],
+            received: 0,
+            packet_size: 3,
+            packets: [[0; 4]; PACKET_QUEUE_SIZE],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    /// Discards all queued data, and starts assembling packets of the given size
+    fn reset(&mut self, packet_size: usize) {
+        self.received = 0;
+        self.packet_size = packet_size;
+        self.length = 0;
+    }
+
+    /// Adds a byte to the current packet, queueing it if it is complete
+    fn receive(&mut self, data: u8) {
+        // A first byte without the always set bit means a byte was lost, so wait for the next packet start
+        if self.received == 0 && data & PACKET_ALWAYS_SET == 0 {
+            return;
+        }
+
+        self.packet[self.received] = data;
+        self.received += 1;
+
+        if self.received == self.packet_size {
+            self.received = 0;
+
+            if self.length < PACKET_QUEUE_SIZE {
+                self.packets[(self.head + self.length) % PACKET_QUEUE_SIZE] = self.packet;
+                self.length += 1;
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<[u8; 4]> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let packet = self.packets[self.head];
+        self.head = (self.head + 1) % PACKET_QUEUE_SIZE;
+        self.length -= 1;
+        Some(packet)
+    }
+}
+
+/// Queues a byte from the mouse which was read by another driver, such as one which arrived in the
+/// middle of a keyboard scancode sequence
+pub fn receive(data: u8) {
+    INPUT.lock().receive(data);
+}
+
+/// Receives a byte from the mouse into the packet queue
+fn mouse_irq(_irq: u8) -> bool {
+    match io::read_mouse_from_irq() {
+        Some(data) => {
+            INPUT.lock().receive(data);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Handles interface to a PS/2 mouse, if available
+pub struct Ps2Mouse<'a> {
+    device: &'a mut Device,
+    config: MouseConfig,
+    protocol: Protocol,
+    buttons: MouseButtons,
+    /// Events from the last packet which have not been returned yet
+    pending: [Option<MouseEvent>; MAX_PACKET_EVENTS],
+}
+
+impl<'a> Ps2Mouse<'a> {
+    /// Creates a new Ps2Mouse from the given PS/2 device
+    pub fn new(device: &'a mut Device) -> Self {
+        Ps2Mouse {
+            device,
+            config: MouseConfig::DEFAULT,
+            protocol: Protocol::Standard,
+            buttons: MouseButtons::empty(),
+            pending: [None; MAX_PACKET_EVENTS],
+        }
+    }
+
+    /// Enables this mouse with the default config and the most capable protocol it supports, and starts it
+    /// reporting movement
+    pub fn enable(&mut self) -> Result<(), Ps2MouseError> {
+        self.device.enable()?;
+
+        if self.device.state != DeviceState::Enabled {
+            return Err(Ps2MouseError::MouseEnableFailed);
+        }
+
+        self.protocol = self.negotiate_protocol()?;
+        debug!("mouse: using {:?} protocol", self.protocol);
+
+        self.configure(MouseConfig::DEFAULT)?;
+
+        INPUT.lock().reset(self.protocol.packet_size());
+
+        match interrupts::register(MOUSE_IRQ, mouse_irq) {
+            Ok(_) | Err(IrqError::AlreadyRegistered) => (),
+            Err(error) => return Err(Ps2MouseError::IrqUnavailable(error)),
+        }
+
+        // Responses to commands would be taken by the interrupt handler, so it is only enabled once they are sent
+        if self.device.command(DeviceCommand::EnableScanning)? != ps2::ACK {
+            return Err(Ps2MouseError::ReportingEnableFailed);
+        }
+
+        ps2::CONTROLLER.lock().set_interrupt(DevicePort::Mouse, true)?;
+
+        Ok(())
+    }
+
+    /// Disables this mouse
+    #[allow(dead_code)] // Part of API
+    pub fn disable(&mut self) -> Result<(), Ps2MouseError> {
+        ps2::CONTROLLER.lock().set_interrupt(DevicePort::Mouse, false)?;
+        interrupts::unregister(MOUSE_IRQ, mouse_irq);
+        self.device.disable()?;
+
+        Ok(())
+    }
+
+    /// Gets the packet format this mouse reports in
+    #[allow(dead_code)] // Part of API
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Gets the buttons currently held
+    #[allow(dead_code)] // Part of API
+    pub fn buttons(&self) -> MouseButtons {
+        self.buttons
+    }
+
+    /// Enables the IntelliMouse Explorer or IntelliMouse protocol if the mouse supports it, by sending the magic
+    /// sample rate sequences and checking the device ID they change the mouse to
+    fn negotiate_protocol(&mut self) -> Result<Protocol, Ps2MouseError> {
+        if self.read_id()? == 0 {
+            self.send_sample_rates(&WHEEL_SEQUENCE)?;
+            if self.read_id()? != 3 {
+                return Ok(Protocol::Standard);
+            }
+        }
+
+        self.send_sample_rates(&FIVE_BUTTON_SEQUENCE)?;
+        Ok(if self.read_id()? == 4 { Protocol::FiveButton } else { Protocol::Wheel })
+    }
+
+    /// Sends each of the given sample rates to the mouse
+    fn send_sample_rates(&mut self, rates: &[u8]) -> Result<(), Ps2MouseError> {
+        for &rate in rates {
+            if self.device.command_data(DeviceDataCommand::SetSampleRate, rate)? != ps2::ACK {
+                return Err(Ps2MouseError::ConfigFailed);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the device ID of the mouse
+    fn read_id(&mut self) -> Result<u8, Ps2MouseError> {
+        if self.device.command(DeviceCommand::GetId)? != ps2::ACK {
+            return Err(Ps2MouseError::ConfigFailed);
+        }
+
+        Ok(io::read(&mut io::DATA_PORT.lock())?)
+    }
+
+    /// Gets the current settings of this mouse
+    #[allow(dead_code)] // Part of API
+    pub fn config(&self) -> MouseConfig {
+        self.config
+    }
+
+    /// Applies the given settings to this mouse. A packet being received may be dropped.
+    pub fn configure(&mut self, config: MouseConfig) -> Result<(), Ps2MouseError> {
+        if !SAMPLE_RATES.contains(&config.sample_rate) {
+            return Err(Ps2MouseError::InvalidSampleRate);
+        }
+
+        // Interrupts are disabled so that the mouse interrupt does not take the responses
+        arch::without_interrupts(|| self.send_config(config))
+    }
+
+    /// Sends the given settings to the mouse
+    fn send_config(&mut self, config: MouseConfig) -> Result<(), Ps2MouseError> {
+        if self.device.command_data(DeviceDataCommand::SetSampleRate, config.sample_rate)? != ps2::ACK {
+            return Err(Ps2MouseError::ConfigFailed);
+        }
+
+        if self.device.command_data(DeviceDataCommand::SetResolution, config.resolution as u8)? != ps2::ACK {
+            return Err(Ps2MouseError::ConfigFailed);
+        }
+
+        self.config = config;
+        Ok(())
+    }
+
+    /// Polls the device for a new mouse event, or returns `None` if no complete packet has been received
+    pub fn read_event(&mut self) -> Result<Option<MouseEvent>, Ps2MouseError> {
+        if self.device.state != DeviceState::Enabled {
+            return Err(Ps2MouseError::MouseDisabled);
+        }
+
+        if let Some(event) = self.next_pending() {
+            return Ok(Some(event));
+        }
+
+        // The lock is released before decoding, so that the interrupt handler is not held up
+        let packet = INPUT.lock().pop();
+        if let Some(packet) = packet {
+            self.decode(&packet);
+        }
+
+        Ok(self.next_pending())
+    }
+
+    /// Removes the first pending event
+    fn next_pending(&mut self) -> Option<MouseEvent> {
+        let event = self.pending[0].take()?;
+        self.pending.rotate_left(1);
+        Some(event)
+    }
+
+    /// Queues the events for the given complete packet
+    fn decode(&mut self, packet: &[u8; 4]) {
+        let flags = packet[0];
+        let mut events = 0;
+
+        // Movement which overflowed cannot be trusted
+        let dx = if flags & PACKET_X_OVERFLOW != 0 {
+            0
+        } else {
+            sign_extend(packet[1], flags & PACKET_X_SIGN != 0)
+        };
+        let dy = if flags & PACKET_Y_OVERFLOW != 0 {
+            0
+        } else {
+            sign_extend(packet[2], flags & PACKET_Y_SIGN != 0)
+        };
+
+        if dx != 0 || dy != 0 {
+            self.pending[events] = Some(MouseEvent::Move { dx: self.config.scale(dx), dy: self.config.scale(dy) });
+            events += 1;
+        }
+
+        let mut buttons = MouseButtons::from_bits_truncate(flags & 0x7);
+
+        match self.protocol {
+            Protocol::Standard => (),
+            Protocol::Wheel => self.pending[events] = scroll(packet[3] as i8),
+            Protocol::FiveButton => {
+                let extra = packet[3];
+                buttons.set(MouseButtons::BACK, extra & PACKET_BUTTON_4 != 0);
+                buttons.set(MouseButtons::FORWARD, extra & PACKET_BUTTON_5 != 0);
+
+                // The scroll movement is a 4 bit two's complement number
+                let dz = ((extra & PACKET_SCROLL) << 4) as i8 >> 4;
+                self.pending[events] = scroll(dz);
+            }
+        }
+
+        if self.pending[events].is_some() {
+            events += 1;
+        }
+
+        for button in MouseButton::ALL.iter() {
+            let (was_held, held) = (self.buttons.contains(button.flag()), buttons.contains(button.flag()));
+
+            if held != was_held {
+                self.pending[events] = Some(if held { MouseEvent::Press(*button) } else { MouseEvent::Release(*button) });
+                events += 1;
+            }
+        }
+
+        self.buttons = buttons;
+    }
+}
+
+/// Creates a scroll event for the given movement, if it moved
+fn scroll(dz: i8) -> Option<MouseEvent> {
+    if dz != 0 { Some(MouseEvent::Scroll { dz }) } else { None }
+}
+
+/// Creates a 9 bit two's complement movement from its low byte and sign bit
+fn sign_extend(low: u8, negative: bool) -> i32 {
+    if negative {
+        low as i32 - 0x100
+    } else {
+        low as i32
+    }
+}
+
+impl From<Ps2Error> for Ps2MouseError {
+    fn from(error: Ps2Error) -> Self {
+        Ps2MouseError::ReadError(error)
+    }
+}
+
+kernel_test!(fn scales_movement() {
+    let config = MouseConfig {
+        sensitivity: 200,
+        acceleration: Acceleration { threshold: 10, percent: 150 },
+        ..MouseConfig::DEFAULT
+    };
+
+    test_assert_eq!(config.scale(3), 6);
+    test_assert_eq!(config.scale(-10), -25);
+    test_assert_eq!(sign_extend(0xFF, true), -1);
+});
+
+kernel_test!(fn decodes_five_button_packets() {
+    let mut device = Device { state: DeviceState::Enabled, port: ps2::DevicePort::Mouse };
+    let mut mouse = Ps2Mouse::new(&mut device);
+    mouse.protocol = Protocol::FiveButton;
+
+    // A lost byte, then left and button 4 pressed, scrolled up by one, without movement
+    let mut queue = PacketQueue::new();
+    queue.reset(4);
+    for &byte in [0x00, 0x09, 0x00, 0x00, 0x1F].iter() {
+        queue.receive(byte);
+    }
+
+    let packet = queue.pop();
+    test_assert!(queue.pop().is_none());
+    mouse.decode(&packet.unwrap_or([0; 4]));
+
+    test_assert_eq!(mouse.next_pending(), Some(MouseEvent::Scroll { dz: -1 }));
+    test_assert_eq!(mouse.next_pending(), Some(MouseEvent::Press(MouseButton::Left)));
+    test_assert_eq!(mouse.next_pending(), Some(MouseEvent::Press(MouseButton::Back)));
+    test_assert_eq!(mouse.next_pending(), None);
+});
@@ -0,0 +1,52 @@
+//! # Programmable Interval Timer
+//!
+//! The PIT has three channels counting down from a 1.193182 MHz clock. There is no timer interrupt
+//! yet, so channel 0 is used to busy-wait for short durations by polling its output. Channel 2
+//! drives the PC speaker; see the [speaker] module.
+//!
+//! The PIT is accessed without locking, so that it can be used while panicking. Only one CPU is
+//! running, and nothing else programs channel 0.
+
+use io::Port;
+
+/// The frequency of the PIT's input clock, in hertz
+pub const FREQUENCY: u32 = 1_193_182;
+
+pub(super) const CHANNEL_0_PORT: u16 = 0x40;
+pub(super) const CHANNEL_2_PORT: u16 = 0x42;
+pub(super) const COMMAND_PORT: u16 = 0x43;
+
+/// Selects channel 0, lobyte/hibyte access, and mode 0 (interrupt on terminal count)
+const CHANNEL_0_ONE_SHOT: u8 = 0b0011_0000;
+/// Reads back the status of channel 0 without latching its count
+const READ_BACK_CHANNEL_0_STATUS: u8 = 0b1110_0010;
+/// The bit of a read back status which holds the channel's output
+const STATUS_OUTPUT: u8 = 1 << 7;
+
+/// Busy-waits for at least the given amount of milliseconds
+pub fn sleep_ms(milliseconds: u32) {
+    let mut remaining = milliseconds as u64 * FREQUENCY as u64 / 1000;
+
+    while remaining > 0 {
+        let count = if remaining > 0xFFFF { 0xFFFF } else { remaining as u16 };
+        wait_ticks(count);
+        remaining -= count as u64;
+    }
+}
+
+/// Busy-waits for the given amount of PIT ticks, using channel 0 in one shot mode
+fn wait_ticks(count: u16) {
+    let (mut command, mut channel_0) = unsafe { (Port::<u8>::new(COMMAND_PORT), Port::<u8>::new(CHANNEL_0_PORT)) };
+
+    command.write(CHANNEL_0_ONE_SHOT);
+    channel_0.write(count as u8);
+    channel_0.write((count >> 8) as u8);
+
+    // The output goes high once the count reaches zero
+    loop {
+        command.write(READ_BACK_CHANNEL_0_STATUS);
+        if channel_0.read() & STATUS_OUTPUT != 0 {
+            break;
+        }
+    }
+}
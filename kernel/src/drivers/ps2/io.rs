@@ -11,6 +11,7 @@ pub mod commands {
         DisablePort1 = 0xAD,
         EnablePort1 = 0xAE,
         WriteInputPort2 = 0xD4,
+        PulseReset = 0xFE,
     }
 
     /// Represents a PS2 controller command with a return value
@@ -38,6 +39,7 @@ pub mod commands {
     #[derive(Copy, Clone, Debug)]
     #[repr(u8)]
     pub enum DeviceCommand {
+        GetId = 0xF2,
         EnableScanning = 0xF4,
         DisableScanning = 0xF5,
         SetDefaults = 0xF6,
@@ -49,7 +51,9 @@ pub mod commands {
     #[derive(Copy, Clone, Debug)]
     #[repr(u8)]
     pub enum DeviceDataCommand {
+        SetResolution = 0xE8,
         SetScancode = 0xF0,
+        SetSampleRate = 0xF3,
     }
 
     /// Sends a controller command without a return
@@ -78,6 +82,7 @@ pub mod commands {
     }
 }
 
+use drivers::ps2::DevicePort;
 use io::{Port, SynchronizedPort};
 
 pub static DATA_PORT: SynchronizedPort<u8> = unsafe { SynchronizedPort::new(0x60) };
@@ -86,6 +91,8 @@ pub static COMMAND_PORT: SynchronizedPort<u8> = unsafe { SynchronizedPort::new(0
 
 /// The number of iterations before assuming no data to be read. Should be changed to a timeout as of #26
 pub const WAIT_TIMEOUT: u32 = 1000000;
+/// The maximum number of bytes discarded when flushing the output buffer
+pub const FLUSH_LIMIT: u32 = 64;
 
 bitflags! {
     pub struct StatusFlags: u8 {
@@ -103,19 +110,25 @@ bitflags! {
 pub enum Ps2Error {
     NoData,
     DeviceUnavailable,
+    /// The controller did not accept a write in time
+    WriteTimeout,
+    /// No controller is present
+    ControllerUnavailable,
+    /// The controller failed its self test
+    ControllerTestFailed,
 }
 
-/// Writes to the given port, or waits until available
+/// Writes to the given port, or waits until available. `WriteTimeout` returned if the controller never became ready
 pub fn write(port: &mut Port<u8>, value: u8) -> Result<(), Ps2Error> {
-    loop {
+    for _ in 0..WAIT_TIMEOUT {
         // Check if the input status bit is empty
         if can_write()? {
             port.write(value);
-            break
+            return Ok(());
         }
     }
 
-    Ok(())
+    Err(Ps2Error::WriteTimeout)
 }
 
 /// Reads from the given port, returning an optional value. `NoData` returned if nothing could be read
@@ -130,18 +143,44 @@ pub fn read(port: &mut Port<u8>) -> Result<u8, Ps2Error> {
     Err(Ps2Error::NoData)
 }
 
-/// Flushes the controller's output buffer
+/// Reads from the given port like `read`, also returning the port of the device the value came from
+pub fn read_with_source(port: &mut Port<u8>) -> Result<(u8, DevicePort), Ps2Error> {
+    for _ in 0..WAIT_TIMEOUT {
+        // The source must be read from the same status as the output status bit
+        let status = read_status()?;
+
+        if status.contains(StatusFlags::OUTPUT_FULL) {
+            let source = if status.contains(StatusFlags::OUTPUT_PORT_2) {
+                DevicePort::Mouse
+            } else {
+                DevicePort::Keyboard
+            };
+            return Ok((port.read(), source));
+        }
+    }
+
+    Err(Ps2Error::NoData)
+}
+
+/// Flushes the controller's output buffer. `NoData` returned if the buffer never emptied
 pub fn flush_output() -> Result<(), Ps2Error> {
     // Read until the output status bit is empty
     DATA_PORT.with_lock(|mut data_port| {
-        while can_read()? {
+        for _ in 0..FLUSH_LIMIT {
+            if !can_read()? {
+                return Ok(());
+            }
+
             data_port.read();
         }
 
-        Ok(())
-    })?;
+        Err(Ps2Error::NoData)
+    })
+}
 
-    Ok(())
+/// Returns true if a controller appears to be present. Reads from an absent controller's status port float high.
+pub fn controller_present() -> bool {
+    STATUS_PORT.read() != 0xFF
 }
 
 /// Reads from the status port and returns the flags
@@ -165,6 +204,19 @@ pub fn can_read_keyboard() -> Result<bool, Ps2Error> {
     read_status().map(|status| !status.contains(StatusFlags::OUTPUT_PORT_2))
 }
 
+/// Reads a byte from the mouse for an interrupt handler, or returns `None` if the controller has none. The ports
+/// are accessed without locking, as the interrupted code may hold their locks.
+pub fn read_mouse_from_irq() -> Option<u8> {
+    let (mut status, mut data) = unsafe { (Port::<u8>::new(0x64), Port::<u8>::new(0x60)) };
+    let status = StatusFlags::from_bits_truncate(status.read());
+
+    if status.contains(StatusFlags::OUTPUT_FULL | StatusFlags::OUTPUT_PORT_2) {
+        Some(data.read())
+    } else {
+        None
+    }
+}
+
 /// Returns true if output port bit is 1, meaning the next data will be read from the mouse
 #[allow(dead_code)] // To be used by drivers interfacing with PS/2
 pub fn can_read_mouse() -> Result<bool, Ps2Error> {
@@ -3,17 +3,21 @@
 //! The PS/2 driver provides interface into the PS/2 controller, allowing access to devices using this protocol.
 //! The controller is accessed through the static `CONTROLLER` field.
 //!
-//! The [Controller] handles all interface with the controller for devices.
+//! The [Controller] handles controller-level configuration and commands.
 //! For it to be initialized, `initialize` must be called on it. This sets up all attached devices.
 //!
 //! The [Device] handles interface to a single PS/2 device. Its state can be checked and toggled through `enable` and `disable`.
-//! Devices can be obtained from the controller through `device(DevicePort)` or `devices`.
+//! Each device has its own lock, obtained through `device(DevicePort)`, so that the keyboard and mouse can be used
+//! independently without holding the controller lock.
+//!
+//! Not every machine has a working PS/2 controller, or one with two ports. If initialization fails, or a device
+//! fails to reset, the affected devices are left `Unavailable` so that boot can continue without them.
 
 pub mod io;
 
 use drivers::ps2::io::Ps2Error;
 use drivers::ps2::io::commands::{self, ControllerCommand, ControllerReturnCommand, ControllerDataCommand, DeviceCommand, DeviceDataCommand};
-use spin::Mutex;
+use sync::Mutex;
 
 pub const RESEND: u8 = 0xFE;
 pub const ACK: u8 = 0xFA;
@@ -22,6 +26,17 @@ lazy_static! {
     pub static ref CONTROLLER: Mutex<Controller> = Mutex::new(Controller::new());
 }
 
+static KEYBOARD: Mutex<Device> = Mutex::new(Device::new(DevicePort::Keyboard));
+static MOUSE: Mutex<Device> = Mutex::new(Device::new(DevicePort::Mouse));
+
+/// Gets the lock of the device in the given port
+pub fn device(port: DevicePort) -> &'static Mutex<Device> {
+    match port {
+        DevicePort::Keyboard => &KEYBOARD,
+        DevicePort::Mouse => &MOUSE,
+    }
+}
+
 bitflags! {
     pub struct ConfigFlags: u8 {
         /// If interrupts for Port 1 are enabled
@@ -59,17 +74,12 @@ pub enum DevicePort {
 
 /// Represents the PS2 master controller
 pub struct Controller {
-    pub devices: (Device, Device),
     pub config: ConfigFlags,
 }
 
 impl Controller {
     fn new() -> Self {
         Controller {
-            devices: (
-                Device::new(DevicePort::Keyboard),
-                Device::new(DevicePort::Mouse),
-            ),
             config: ConfigFlags::empty(),
         }
     }
@@ -78,6 +88,10 @@ impl Controller {
     pub fn initialize(&mut self) -> Result<(), Ps2Error> {
         info!("ps2c: initializing");
 
+        if !io::controller_present() {
+            return Err(Ps2Error::ControllerUnavailable);
+        }
+
         self.prepare_devices()?;
         debug!("ps2c: disabled devices");
 
@@ -86,14 +100,16 @@ impl Controller {
         self.initialize_config()?;
 
         if !self.test_controller()? {
-            error!("ps2c: controller test failed");
+            return Err(Ps2Error::ControllerTestFailed);
         }
 
         debug!("ps2c: testing devices");
-        match self.test_devices()? {
-            (false, _) => warn!("ps2c: first device not supported"),
-            (_, false) => warn!("ps2c: second device not supported"),
-            _ => (),
+        let (first_supported, second_supported) = self.test_devices()?;
+        if !first_supported {
+            warn!("ps2c: first device not supported");
+        }
+        if !second_supported {
+            warn!("ps2c: second device not supported");
         }
 
         // Check if any devices are available
@@ -120,23 +136,25 @@ impl Controller {
         Ok(ConfigFlags::from_bits_truncate(read))
     }
 
-    /// Gets the device for the given port
-    #[allow(dead_code)] // To be used by drivers interfacing with PS/2
-    pub fn device(&mut self, port: DevicePort) -> &mut Device {
-        if port == DevicePort::Keyboard {
-            &mut self.devices.0
-        } else {
-            &mut self.devices.1
-        }
+    /// Enables or disables the interrupt the controller raises when the given device sends data
+    pub fn set_interrupt(&mut self, port: DevicePort, enabled: bool) -> Result<(), Ps2Error> {
+        let flag = match port {
+            DevicePort::Keyboard => ConfigFlags::PORT_INTERRUPT_1,
+            DevicePort::Mouse => ConfigFlags::PORT_INTERRUPT_2,
+        };
+
+        self.config = self.read_config()?;
+        self.config.set(flag, enabled);
+        self.write_config(self.config)
     }
 
     /// Resets this controller's devices and prepares them for initialization
     fn prepare_devices(&mut self) -> Result<(), Ps2Error> {
-        self.devices.0.disable()?;
-        self.devices.1.disable()?;
-
-        self.devices.0.state = DeviceState::Unavailable;
-        self.devices.1.state = DeviceState::Unavailable;
+        for device in [&KEYBOARD, &MOUSE].iter() {
+            let mut device = device.lock();
+            device.disable()?;
+            device.state = DeviceState::Unavailable;
+        }
 
         Ok(())
     }
@@ -166,32 +184,45 @@ impl Controller {
 
     /// Tests all of this controller's devices
     fn test_devices(&mut self) -> Result<(bool, bool), Ps2Error> {
+        let mut mouse = MOUSE.lock();
+
         // Check if controller supports the second device
         if self.config.contains(ConfigFlags::PORT_CLOCK_2) {
-            self.devices.1.enable()?;
+            mouse.enable()?;
             self.config = self.read_config()?;
-            self.devices.1.disable()?;
+            mouse.disable()?;
         }
 
         // Test both devices
-        let first_supported = self.devices.0.test()?;
-        let second_supported = !self.config.contains(ConfigFlags::PORT_CLOCK_2) && self.devices.1.test()?;
+        let first_supported = KEYBOARD.lock().test()?;
+        let second_supported = if self.config.contains(ConfigFlags::PORT_CLOCK_2) {
+            // The second clock could not be enabled, so this is a single channel controller
+            debug!("ps2c: single channel controller");
+            mouse.state = DeviceState::Unavailable;
+            false
+        } else {
+            mouse.test()?
+        };
 
         Ok((first_supported, second_supported))
     }
 
-    /// Resets all devices and returns the count available
+    /// Resets all devices and returns the count available. Devices which fail to reset are made unavailable
     fn reset_devices(&mut self) -> Result<u8, Ps2Error> {
         let mut available_count = 0;
 
-        if self.devices.0.state == DeviceState::Available {
-            self.devices.0.reset()?;
-            available_count += 1;
-        }
+        for device in [&KEYBOARD, &MOUSE].iter() {
+            let mut device = device.lock();
 
-        if self.devices.1.state == DeviceState::Available {
-            self.devices.1.reset()?;
-            available_count += 1;
+            if device.state == DeviceState::Available {
+                match device.reset() {
+                    Ok(_) => available_count += 1,
+                    Err(error) => {
+                        warn!("ps2c: device reset failed: {:?}", error);
+                        device.state = DeviceState::Unavailable;
+                    }
+                }
+            }
         }
 
         Ok(available_count)
@@ -273,12 +304,7 @@ impl Device {
     pub fn command_data(&mut self, cmd: DeviceDataCommand, data: u8) -> Result<u8, Ps2Error> {
         if self.state != DeviceState::Unavailable {
             self.command_raw(cmd as u8).and_then(|result| match result {
-                ACK => {
-                    io::DATA_PORT.with_lock(|mut data_port| {
-                        io::write(&mut data_port, data as u8)?;
-                        io::read(&mut data_port)
-                    })
-                }
+                ACK => self.send_byte(data),
                 _ => Ok(result)
             })
         } else {
@@ -289,24 +315,31 @@ impl Device {
     /// Sends a raw command code to this device
     fn command_raw(&mut self, cmd: u8) -> Result<u8, Ps2Error> {
         if self.state != DeviceState::Unavailable {
-            // If second PS2 port, send context switch command
-            if self.port == DevicePort::Mouse {
-                commands::send(ControllerCommand::WriteInputPort2)?;
-            }
-
-            io::DATA_PORT.with_lock(|mut data_port| {
-                for _ in 0..4 {
-                    io::write(&mut data_port, cmd)?;
-                    match io::read(&mut data_port) {
-                        Ok(RESEND) => continue,
-                        result => return result,
-                    }
+            for _ in 0..4 {
+                match self.send_byte(cmd) {
+                    Ok(RESEND) => continue,
+                    result => return result,
                 }
+            }
 
-                Err(Ps2Error::NoData)
-            })
+            Err(Ps2Error::NoData)
         } else {
             Err(Ps2Error::DeviceUnavailable)
         }
     }
+
+    /// Sends a byte to this device and reads its response. Both ports are held throughout, so that
+    /// a byte for the other device cannot be written between the port selection and the byte.
+    fn send_byte(&mut self, byte: u8) -> Result<u8, Ps2Error> {
+        let mut command_port = io::COMMAND_PORT.lock();
+        let mut data_port = io::DATA_PORT.lock();
+
+        // If second PS2 port, send context switch command
+        if self.port == DevicePort::Mouse {
+            io::write(&mut command_port, ControllerCommand::WriteInputPort2 as u8)?;
+        }
+
+        io::write(&mut data_port, byte)?;
+        io::read(&mut data_port)
+    }
 }
@@ -0,0 +1,176 @@
+//! # Real Time Clock
+//!
+//! Reads the date and time from the CMOS real time clock, which keeps the wall clock while the
+//! machine is off. It only counts whole seconds, so the [time] module reads it once at boot and
+//! keeps the wall clock with a faster clock from then on.
+//!
+//! The RTC may count in BCD or binary, and in 12 or 24 hour time, as reported by its status B
+//! register. The century is read from the register named by the ACPI FADT if there is one, and is
+//! otherwise assumed to be the 21st.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let now = rtc::read();
+//! println!("{}-{:02}-{:02}", now.year, now.month, now.day);
+//! ```
+
+use acpi;
+use io::Port;
+use spin::Mutex;
+
+const INDEX_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+
+/// Set in the index written to the index port to keep NMIs disabled while reading. They are
+/// enabled again once reading is done by selecting status D without it.
+const DISABLE_NMI: u8 = 1 << 7;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+const REGISTER_STATUS_D: u8 = 0x0D;
+
+/// Set in status A while the RTC is updating its registers
+const STATUS_A_UPDATING: u8 = 1 << 7;
+/// Set in status B if the hours are in 24 hour time
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Set in status B if the registers are binary rather than BCD
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register for PM in 12 hour time
+const HOURS_PM: u8 = 1 << 7;
+
+/// The offset of the century register index in the FADT
+const FADT_CENTURY: usize = 108;
+
+/// The CMOS index and data ports
+static CMOS: Mutex<(Port<u8>, Port<u8>)> = Mutex::new(unsafe { (Port::new(INDEX_PORT), Port::new(DATA_PORT)) });
+
+/// A date and time, in UTC as the RTC is assumed to keep
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct DateTime {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    /// The amount of seconds since the Unix epoch
+    pub fn unix_seconds(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let seconds = self.hours as i64 * 3600 + self.minutes as i64 * 60 + self.seconds as i64;
+
+        (days * 86400 + seconds) as u64
+    }
+}
+
+/// The raw register values read from the RTC
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+/// Reads the current date and time
+pub fn read() -> DateTime {
+    let century_register = acpi::find(b"FACP").ok()
+        .and_then(|fadt| fadt.get(FADT_CENTURY).cloned())
+        .unwrap_or(0);
+
+    let mut cmos = CMOS.lock();
+    let (ref mut index, ref mut data) = *cmos;
+
+    // Registers are read until the same values are read twice, so that an update is not read halfway
+    let mut registers = read_registers(index, data, century_register);
+    loop {
+        let again = read_registers(index, data, century_register);
+        if again == registers {
+            break;
+        }
+        registers = again;
+    }
+
+    let status_b = read_register(index, data, REGISTER_STATUS_B);
+
+    // Enables NMIs again, leaving status D selected as it is read only
+    index.write(REGISTER_STATUS_D);
+
+    let decode = |value: u8| if status_b & STATUS_B_BINARY != 0 { value } else { from_bcd(value) };
+
+    let mut hours = decode(registers.hours & !HOURS_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight, and 12 PM is noon
+        hours %= 12;
+        if registers.hours & HOURS_PM != 0 {
+            hours += 12;
+        }
+    }
+
+    let century = if century_register != 0 { decode(registers.century) as u32 } else { 20 };
+
+    DateTime {
+        year: century * 100 + decode(registers.year) as u32,
+        month: decode(registers.month),
+        day: decode(registers.day),
+        hours,
+        minutes: decode(registers.minutes),
+        seconds: decode(registers.seconds),
+    }
+}
+
+fn read_registers(index: &mut Port<u8>, data: &mut Port<u8>, century_register: u8) -> Registers {
+    while read_register(index, data, REGISTER_STATUS_A) & STATUS_A_UPDATING != 0 {}
+
+    Registers {
+        seconds: read_register(index, data, REGISTER_SECONDS),
+        minutes: read_register(index, data, REGISTER_MINUTES),
+        hours: read_register(index, data, REGISTER_HOURS),
+        day: read_register(index, data, REGISTER_DAY),
+        month: read_register(index, data, REGISTER_MONTH),
+        year: read_register(index, data, REGISTER_YEAR),
+        century: if century_register != 0 { read_register(index, data, century_register) } else { 0 },
+    }
+}
+
+fn read_register(index: &mut Port<u8>, data: &mut Port<u8>, register: u8) -> u8 {
+    index.write(register | DISABLE_NMI);
+    data.read()
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0xF)
+}
+
+/// Gets the amount of days from the Unix epoch to the given date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years are counted from March, so that the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+kernel_test!(fn converts_to_unix_time() {
+    test_assert_eq!(days_from_civil(1970, 1, 1), 0);
+    test_assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+
+    let date = DateTime { year: 2018, month: 2, day: 28, hours: 23, minutes: 59, seconds: 59 };
+    test_assert_eq!(date.unix_seconds(), 1_519_862_399);
+    test_assert_eq!(from_bcd(0x59), 59);
+});
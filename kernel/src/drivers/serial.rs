@@ -0,0 +1,118 @@
+//! # Serial Driver
+//!
+//! A driver for 16550 UART serial ports, with polled input and output. The first port is accessed
+//! through the static `COM1` field, and must be initialized with `init` before use.
+//!
+//! Under QEMU, output to `COM1` can be read on the host by passing `-serial stdio`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use core::fmt::Write;
+//!
+//! let mut com1 = drivers::serial::COM1.lock();
+//! com1.init();
+//! writeln!(com1, "Hello from flower").unwrap();
+//! ```
+
+use core::fmt;
+use io::Port;
+use spin::Mutex;
+
+/// The base IO port of the first serial port
+pub const COM1_BASE: u16 = 0x3F8;
+
+/// The first serial port
+pub static COM1: Mutex<SerialPort> = Mutex::new(unsafe { SerialPort::new(COM1_BASE) });
+
+/// Divides the UART's 115200 baud clock down to 38400 baud
+const BAUD_DIVISOR: u16 = 3;
+
+bitflags! {
+    struct LineStatus: u8 {
+        /// If a byte has been received and can be read
+        const DATA_READY = 1 << 0;
+        /// If the transmit buffer is empty, and a byte can be written
+        const TRANSMIT_EMPTY = 1 << 5;
+    }
+}
+
+/// A 16550 UART serial port
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: Port<u8>,
+}
+
+impl SerialPort {
+    /// Creates a serial port with the given base IO port
+    ///
+    /// # Safety
+    ///
+    /// There must be a serial port at the given base, and no other instance may access it
+    pub const unsafe fn new(base: u16) -> Self {
+        SerialPort {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: Port::new(base + 5),
+        }
+    }
+
+    /// Initializes this port for output at 38400 baud, with 8 data bits, no parity and 1 stop bit
+    pub fn init(&mut self) {
+        // Disable interrupts, as output is polled
+        self.interrupt_enable.write(0x00);
+
+        // Set the baud divisor, which is accessed while the divisor latch bit is set
+        self.line_control.write(0x80);
+        self.data.write(BAUD_DIVISOR as u8);
+        self.interrupt_enable.write((BAUD_DIVISOR >> 8) as u8);
+
+        // 8 data bits, no parity, 1 stop bit, and clear the divisor latch bit
+        self.line_control.write(0x03);
+        // Enable and clear the FIFOs, with a 14 byte threshold
+        self.fifo_control.write(0xC7);
+        // Set data terminal ready and request to send
+        self.modem_control.write(0x03);
+    }
+
+    /// Writes a single byte, waiting until the port can accept it
+    pub fn write_byte(&mut self, byte: u8) {
+        while !self.line_status().contains(LineStatus::TRANSMIT_EMPTY) {}
+        self.data.write(byte);
+    }
+
+    /// Reads a single byte, or returns `None` if none has been received
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.line_status().contains(LineStatus::DATA_READY) {
+            Some(self.data.read())
+        } else {
+            None
+        }
+    }
+
+    fn line_status(&mut self) -> LineStatus {
+        LineStatus::from_bits_truncate(self.line_status.read())
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for byte in string.bytes() {
+            // Terminals on the other end expect carriage returns
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
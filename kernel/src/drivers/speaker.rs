@@ -0,0 +1,63 @@
+//! # PC Speaker
+//!
+//! Plays square wave tones through the PC speaker, which is driven by channel 2 of the [pit]. It is
+//! used for the terminal bell character and when the kernel panics.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! // Play an A4 for half a second
+//! drivers::speaker::beep(440, 500);
+//! ```
+
+use io::Port;
+use super::pit;
+
+/// The port controlling whether channel 2 drives the speaker
+const CONTROL_PORT: u16 = 0x61;
+/// Enables channel 2's gate, and connects its output to the speaker
+const SPEAKER_ENABLE: u8 = 0b11;
+
+/// Selects channel 2, lobyte/hibyte access, and mode 3 (square wave)
+const CHANNEL_2_SQUARE_WAVE: u8 = 0b1011_0110;
+
+/// Plays a tone of the given frequency in hertz until `stop` is called
+pub fn play(frequency: u32) {
+    if frequency == 0 {
+        stop();
+        return;
+    }
+
+    let divisor = pit::FREQUENCY / frequency;
+    let divisor = if divisor > 0xFFFF { 0xFFFF } else if divisor == 0 { 1 } else { divisor };
+
+    unsafe {
+        let mut command = Port::<u8>::new(pit::COMMAND_PORT);
+        let mut channel_2 = Port::<u8>::new(pit::CHANNEL_2_PORT);
+        let mut control = Port::<u8>::new(CONTROL_PORT);
+
+        command.write(CHANNEL_2_SQUARE_WAVE);
+        channel_2.write(divisor as u8);
+        channel_2.write((divisor >> 8) as u8);
+
+        let value = control.read();
+        control.write(value | SPEAKER_ENABLE);
+    }
+}
+
+/// Silences the speaker
+pub fn stop() {
+    unsafe {
+        let mut control = Port::<u8>::new(CONTROL_PORT);
+        let value = control.read();
+        control.write(value & !SPEAKER_ENABLE);
+    }
+}
+
+/// Plays a tone of the given frequency in hertz for the given amount of milliseconds, blocking
+/// until it finishes
+pub fn beep(frequency: u32, milliseconds: u32) {
+    play(frequency);
+    pit::sleep_ms(milliseconds);
+    stop();
+}
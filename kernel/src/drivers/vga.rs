@@ -5,6 +5,7 @@ use core::ptr::Unique;
 use core::result::Result;
 use spin::RwLock;
 
+use memory;
 use util::{self, FromDiscriminator};
 use color::{Color, ColorPair};
 use terminal::*;
@@ -14,11 +15,38 @@ pub static WRITER: RwLock<VgaWriter> = RwLock::new(VgaWriter::new());
 /// The resolution of VGA
 pub const RESOLUTION: Resolution = Resolution::new(80, 25);
 
+/// The physical address of the VGA text buffer
+const BUFFER_ADDRESS: usize = 0xb8000;
+
+/// The code page 437 byte drawn for characters which it cannot represent, a filled square
+const REPLACEMENT_CHARACTER: u8 = 0xFE;
+
+/// The characters drawn for the bytes 0x01 to 0x1F in code page 437
+const CP437_LOW: [char; 31] = [
+    '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// The characters drawn for the bytes 0x80 to 0xFF in code page 437
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
 /// Interface to VGA, allowing write
 pub struct VgaWriter {
     buffer: Unique<VgaBuffer>,
     cursor: Point,
     color: ColorPair,
+    scroll_region: Option<ScrollRegion>,
+    /// The cursor position and color saved by `save_cursor`
+    saved_cursor: (Point, ColorPair),
 }
 
 impl fmt::Debug for VgaWriter {
@@ -30,9 +58,13 @@ impl fmt::Debug for VgaWriter {
 impl VgaWriter {
     pub const fn new() -> Self {
         VgaWriter {
-            buffer: unsafe { Unique::new_unchecked(0xb8000 as *mut _) },
+            buffer: unsafe {
+                Unique::new_unchecked((memory::PHYSICAL_MEMORY_OFFSET + BUFFER_ADDRESS) as *mut _)
+            },
             cursor: Point::new(0, RESOLUTION.y - 1),
             color: color!(White on Black),
+            scroll_region: None,
+            saved_cursor: (Point::new(0, RESOLUTION.y - 1), color!(White on Black)),
         }
     }
 
@@ -121,7 +153,7 @@ impl TerminalOutput<()> for VgaWriter {
             point.y,
             VgaChar::new(
                 VgaColor::from(char.color),
-                char.character as u8
+                encode_cp437(char.character)
             )
         );
         Ok(())
@@ -151,6 +183,68 @@ impl TerminalOutput<()> for VgaWriter {
 
         Ok(())
     }
+
+    fn scroll_lines(&mut self, region: ScrollRegion, direction: ScrollDirection, lines: usize)
+        -> Result<(), TerminalOutputError<()>>
+    {
+        if !region.fits(RESOLUTION) {
+            return Err(TerminalOutputError::OutOfBounds(Point::new(0, region.top())));
+        }
+
+        let background = self.color.background;
+        let top = RESOLUTION.y - 1 - region.top();
+
+        match direction {
+            ScrollDirection::Down => self.buffer().scroll_rows(top, region.height, lines, background),
+            ScrollDirection::Up => self.buffer().reverse_scroll_rows(top, region.height, lines, background),
+        }
+
+        Ok(())
+    }
+
+    fn scroll_region(&self) -> Option<ScrollRegion> {
+        self.scroll_region
+    }
+
+    fn set_scroll_region(&mut self, region: Option<ScrollRegion>) -> Result<(), TerminalOutputError<()>> {
+        match region {
+            Some(region) if !region.fits(RESOLUTION) => {
+                Err(TerminalOutputError::OutOfBounds(Point::new(0, region.top())))
+            }
+            _ => {
+                self.scroll_region = region;
+                Ok(())
+            }
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = (self.cursor, self.color);
+    }
+
+    fn restore_cursor(&mut self) -> Result<(), TerminalOutputError<()>> {
+        let (cursor, color) = self.saved_cursor;
+        self.set_cursor_pos(cursor)?;
+        self.set_color(color)
+    }
+}
+
+/// Encodes a character in code page 437, the character set of the VGA text mode font. Characters
+/// which cannot be represented are encoded as a filled square.
+fn encode_cp437(character: char) -> u8 {
+    match character {
+        ' '...'~' => character as u8,
+        '⌂' => 0x7F,
+        _ => {
+            if let Some(index) = CP437_LOW.iter().position(|&low| low == character) {
+                index as u8 + 0x01
+            } else if let Some(index) = CP437_HIGH.iter().position(|&high| high == character) {
+                index as u8 + 0x80
+            } else {
+                REPLACEMENT_CHARACTER
+            }
+        }
+    }
 }
 
 /// Represents the complete VGA character buffer, containing a 2D array of VgaChar
@@ -163,15 +257,36 @@ impl VgaBuffer {
     }
 
     pub fn scroll_down(&mut self, amount: usize, background_color: Color) {
-        // Shift lines left (up) by amount only if amount < Y resolution
+        self.scroll_rows(0, RESOLUTION.y, amount, background_color);
+    }
+
+    /// Scrolls the given range of rows, counted from the top, independently of the others
+    pub fn scroll_rows(&mut self, top: usize, height: usize, amount: usize, background_color: Color) {
+        let amount = cmp::min(amount, height);
+
+        // Shift lines left (up) by amount only if amount < height
         // If amount is any more then the data will be cleared anyway
-        if cmp::min(amount, RESOLUTION.y) < RESOLUTION.y {
-            self.0.rotate_left(amount);
+        if amount < height {
+            self.0[top..top + height].rotate_left(amount);
         }
 
         // Clear rows up to the amount
         for row in 0..amount {
-            self.clear_row((RESOLUTION.y - 1) - row, background_color);
+            self.clear_row(top + height - 1 - row, background_color);
+        }
+    }
+
+    /// Scrolls the given range of rows, counted from the top, the other way, so that rows move down
+    /// and blank rows appear at the top
+    pub fn reverse_scroll_rows(&mut self, top: usize, height: usize, amount: usize, background_color: Color) {
+        let amount = cmp::min(amount, height);
+
+        if amount < height {
+            self.0[top..top + height].rotate_right(amount);
+        }
+
+        for row in 0..amount {
+            self.clear_row(top + row, background_color);
         }
     }
 
@@ -231,3 +346,18 @@ impl TryFrom<VgaColor> for (Color, Color) {
         ))
     }
 }
+
+kernel_test!(fn encodes_cp437() {
+    test_assert_eq!(encode_cp437('A'), b'A');
+    test_assert_eq!(encode_cp437('é'), 0x82);
+    test_assert_eq!(encode_cp437('┌'), 0xDA);
+    test_assert_eq!(encode_cp437('☺'), 0x01);
+    test_assert_eq!(encode_cp437('\u{1F33C}'), REPLACEMENT_CHARACTER);
+    test_assert_eq!(encode_cp437('\t'), REPLACEMENT_CHARACTER);
+});
+
+kernel_test!(fn vga_color_round_trips() {
+    let color = VgaColor::from(color!(Green on Blue));
+    test_assert_eq!(color.0, 0x12);
+    test_assert_eq!(<(Color, Color) as TryFrom<VgaColor>>::try_from(color).ok(), Some((Color::Blue, Color::Green)));
+});
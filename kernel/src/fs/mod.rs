@@ -0,0 +1,55 @@
+//! # Filesystems
+//!
+//! The initial ramdisk is a ustar archive loaded as a multiboot module, and is read in place from
+//! the physical memory map. There is no VFS to mount it into yet, so it is exposed directly through
+//! `root`.
+//!
+//! The module named `initrd` is used if present, and otherwise the first module is used. With
+//! GRUB, this is given by `module2 /boot/initrd.tar initrd`.
+
+use multiboot::BootInfo;
+use spin::RwLock;
+
+pub mod tar;
+
+/// The name of the module used as the initial ramdisk
+const INITRD_MODULE: &'static str = "initrd";
+
+static ROOT: RwLock<Option<tar::Archive<'static>>> = RwLock::new(None);
+
+/// Loads the initial ramdisk from the boot modules, if one was loaded by the bootloader. This must
+/// be called after the physical memory map is set up.
+pub fn init(boot_info: &BootInfo) {
+    let module = boot_info.modules()
+        .find(|module| module.command_line() == INITRD_MODULE)
+        .or_else(|| boot_info.modules().next());
+
+    let module = match module {
+        Some(module) => module,
+        None => {
+            info!("fs: no initrd module");
+            return;
+        }
+    };
+
+    let archive = tar::Archive::new(module.data());
+    let mut entries = 0;
+
+    for entry in archive.entries() {
+        match entry {
+            Ok(_) => entries += 1,
+            Err(error) => {
+                error!("fs: initrd is malformed after {} entries: {:?}", entries, error);
+                return;
+            }
+        }
+    }
+
+    info!("fs: initrd loaded with {} entries", entries);
+    *ROOT.write() = Some(archive);
+}
+
+/// Gets the archive loaded as the initial ramdisk, if any
+pub fn root() -> Option<tar::Archive<'static>> {
+    *ROOT.read()
+}
@@ -0,0 +1,289 @@
+//! # Tar archives
+//!
+//! A read-only parser for ustar archives, as produced by `tar --format=ustar`. Archives are read in
+//! place, so entries borrow their names and contents from the archive's data.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let archive = Archive::new(data);
+//!
+//! if let Some(entry) = archive.find("etc/motd") {
+//!     println!("{}", str::from_utf8(entry.data()).unwrap_or("<binary>"));
+//! }
+//! ```
+
+use core::str;
+
+/// The size of a header or data block
+pub const BLOCK_SIZE: usize = 512;
+
+const NAME: (usize, usize) = (0, 100);
+const SIZE: (usize, usize) = (124, 12);
+const CHECKSUM: (usize, usize) = (148, 8);
+const TYPE_FLAG: usize = 156;
+const MAGIC: (usize, usize) = (257, 5);
+const PREFIX: (usize, usize) = (345, 155);
+
+/// The kind of an archive entry
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// Hard links, devices, FIFOs and other entries which are not supported
+    Other,
+}
+
+/// An error encountered while reading an archive
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TarError {
+    /// A header did not have the ustar magic
+    InvalidMagic,
+    /// A header's checksum did not match its contents
+    InvalidChecksum,
+    /// A header field was not valid
+    InvalidHeader,
+    /// An entry's data extends past the end of the archive
+    Truncated,
+}
+
+/// A ustar archive
+#[derive(Copy, Clone, Debug)]
+pub struct Archive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Archive { data }
+    }
+
+    /// Iterates over the entries of the archive, stopping after the first error
+    pub fn entries(&self) -> Entries<'a> {
+        Entries { data: self.data, offset: 0, done: false }
+    }
+
+    /// Finds the entry with the given path. Leading `/` and `./` are ignored in both the path and
+    /// the entry names.
+    pub fn find(&self, path: &str) -> Option<Entry<'a>> {
+        let path = normalize(path);
+
+        self.entries()
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.matches(path))
+    }
+}
+
+/// A file, directory or other entry in an archive
+#[derive(Copy, Clone, Debug)]
+pub struct Entry<'a> {
+    prefix: &'a str,
+    name: &'a str,
+    kind: EntryKind,
+    data: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    /// The path prefix of the entry, which is empty unless the path was longer than 100 bytes
+    pub fn prefix(&self) -> &'a str {
+        self.prefix
+    }
+
+    /// The name of the entry, which is its full path if the prefix is empty
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The contents of the entry, which are empty for anything but files
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns `true` if this entry's path, joined from its prefix and name, equals the given
+    /// normalized path
+    fn matches(&self, path: &str) -> bool {
+        let name = normalize(self.name).trim_right_matches('/');
+
+        if self.prefix.is_empty() {
+            return name == path;
+        }
+
+        let prefix = normalize(self.prefix).trim_right_matches('/');
+
+        path.len() == prefix.len() + 1 + name.len() &&
+            path.starts_with(prefix) &&
+            path[prefix.len()..].starts_with('/') &&
+            path.ends_with(name)
+    }
+}
+
+/// An iterator over the entries of an archive
+pub struct Entries<'a> {
+    data: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, TarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.offset + BLOCK_SIZE > self.data.len() {
+            return None;
+        }
+
+        let header = &self.data[self.offset..self.offset + BLOCK_SIZE];
+
+        // The archive ends with two zero blocks, but the first is enough to know it has ended
+        if header.iter().all(|&byte| byte == 0) {
+            self.done = true;
+            return None;
+        }
+
+        let result = self.parse(header);
+        if result.is_err() {
+            self.done = true;
+        }
+
+        Some(result)
+    }
+}
+
+impl<'a> Entries<'a> {
+    fn parse(&mut self, header: &'a [u8]) -> Result<Entry<'a>, TarError> {
+        if field(header, MAGIC) != b"ustar" {
+            return Err(TarError::InvalidMagic);
+        }
+
+        if checksum(header) != parse_octal(field(header, CHECKSUM))? {
+            return Err(TarError::InvalidChecksum);
+        }
+
+        let size = parse_octal(field(header, SIZE))?;
+        let kind = match header[TYPE_FLAG] {
+            b'0' | 0 => EntryKind::File,
+            b'2' => EntryKind::Symlink,
+            b'5' => EntryKind::Directory,
+            _ => EntryKind::Other,
+        };
+
+        let start = self.offset + BLOCK_SIZE;
+        let end = start.checked_add(size).ok_or(TarError::Truncated)?;
+        if end > self.data.len() {
+            return Err(TarError::Truncated);
+        }
+
+        // Data is padded to a whole number of blocks
+        self.offset = start + (size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+
+        Ok(Entry {
+            prefix: parse_string(field(header, PREFIX))?,
+            name: parse_string(field(header, NAME))?,
+            kind,
+            data: &self.data[start..end],
+        })
+    }
+}
+
+fn field(header: &[u8], (offset, length): (usize, usize)) -> &[u8] {
+    &header[offset..offset + length]
+}
+
+/// Parses a null terminated string field
+fn parse_string(field: &[u8]) -> Result<&str, TarError> {
+    let length = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..length]).map_err(|_| TarError::InvalidHeader)
+}
+
+/// Parses an octal number field, which may be padded with spaces or nulls
+fn parse_octal(field: &[u8]) -> Result<usize, TarError> {
+    let string = parse_string(field)?.trim_matches(' ');
+
+    if string.is_empty() {
+        return Ok(0);
+    }
+
+    usize::from_str_radix(string, 8).map_err(|_| TarError::InvalidHeader)
+}
+
+/// Calculates the checksum of a header, which is the sum of its bytes with the checksum field
+/// taken to be spaces
+fn checksum(header: &[u8]) -> usize {
+    let (start, length) = CHECKSUM;
+
+    header.iter()
+        .enumerate()
+        .map(|(index, &byte)| if index >= start && index < start + length { b' ' } else { byte })
+        .map(|byte| byte as usize)
+        .sum()
+}
+
+/// Strips the leading `/` and `./` from a path
+fn normalize(path: &str) -> &str {
+    let mut path = path;
+
+    loop {
+        if path.starts_with("./") {
+            path = &path[2..];
+        } else if path.starts_with('/') {
+            path = &path[1..];
+        } else {
+            return path;
+        }
+    }
+}
+
+/// Writes a number into an octal field, leaving its last byte as a null terminator
+#[cfg(feature = "integration-test")]
+fn write_test_octal(field: &mut [u8], mut value: usize) {
+    let digits = field.len() - 1;
+    for index in (0..digits).rev() {
+        field[index] = b'0' + (value % 8) as u8;
+        value /= 8;
+    }
+    field[digits] = 0;
+}
+
+/// Writes a header for a file with the given name and size into the block
+#[cfg(feature = "integration-test")]
+fn write_test_header(block: &mut [u8], name: &str, size: usize) {
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    write_test_octal(&mut block[SIZE.0..SIZE.0 + SIZE.1], size);
+    block[TYPE_FLAG] = b'0';
+    block[MAGIC.0..MAGIC.0 + MAGIC.1].copy_from_slice(b"ustar");
+
+    let sum = checksum(block);
+    write_test_octal(&mut block[CHECKSUM.0..CHECKSUM.0 + CHECKSUM.1], sum);
+}
+
+kernel_test!(fn finds_file() {
+    let mut data = [0u8; BLOCK_SIZE * 4];
+    write_test_header(&mut data[..BLOCK_SIZE], "./etc/motd", 5);
+    data[BLOCK_SIZE..BLOCK_SIZE + 5].copy_from_slice(b"hello");
+
+    let archive = Archive::new(&data);
+    test_assert_eq!(archive.entries().count(), 1);
+
+    let entry = archive.find("/etc/motd");
+    test_assert!(entry.is_some());
+    test_assert_eq!(entry.unwrap().data(), &b"hello"[..]);
+    test_assert!(archive.find("etc/mot").is_none());
+});
+
+kernel_test!(fn rejects_bad_checksum() {
+    let mut data = [0u8; BLOCK_SIZE * 3];
+    write_test_header(&mut data[..BLOCK_SIZE], "file", 0);
+    data[0] = b'F';
+
+    let archive = Archive::new(&data);
+    test_assert_eq!(archive.entries().next().map(|entry| entry.err()), Some(Some(TarError::InvalidChecksum)));
+});
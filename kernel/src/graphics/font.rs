@@ -0,0 +1,333 @@
+//! # Fonts
+//!
+//! Loads PC Screen Fonts (PSF), the bitmap console font format used by Linux, in both version 1 and
+//! version 2. Fonts are read in place, so a [Font] borrows the data it was loaded from.
+//!
+//! Glyphs are looked up by their Unicode mapping table if the font has one, and by codepoint
+//! otherwise. Latin-1 and the box drawing and block element ranges are indexed when the font is
+//! loaded; any other character is found by searching the table. Characters without a glyph are drawn
+//! with the replacement glyph, which is `U+FFFD` or `?`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let font = Font::load(data)?;
+//! display.draw_text(0, 0, &font, "Flower ┌─┐", foreground, background, TextAttributes::BOLD);
+//! ```
+
+use core::str;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// PSF1 mode bit set if the font has 512 glyphs rather than 256
+const PSF1_MODE_512: u8 = 0x01;
+/// PSF1 mode bits set if the font has a Unicode table
+const PSF1_MODE_HAS_TABLE: u8 = 0x02 | 0x04;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_SEQUENCE_START: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+/// PSF2 flag set if the font has a Unicode table
+const PSF2_HAS_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_SEQUENCE_START: u8 = 0xFE;
+
+/// The first codepoint of the box drawing and block element ranges
+const BOX_DRAWING_START: u32 = 0x2500;
+/// The amount of codepoints in the box drawing and block element ranges
+const BOX_DRAWING_COUNT: usize = 0xA0;
+
+/// Marks a codepoint with no glyph in the lookup tables
+const NO_GLYPH: u16 = 0xFFFF;
+
+bitflags! {
+    pub struct TextAttributes: u8 {
+        /// Glyphs are drawn a pixel wider
+        const BOLD = 1 << 0;
+        /// A line is drawn under glyphs
+        const UNDERLINE = 1 << 1;
+    }
+}
+
+/// An error returned when loading a font
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FontError {
+    /// The data is not a PSF1 or PSF2 font
+    InvalidMagic,
+    /// The header describes glyphs which do not fit in the data
+    Truncated,
+    /// The glyphs are empty, or wider than 32 pixels
+    UnsupportedSize,
+}
+
+/// The format of a font's Unicode table
+#[derive(Copy, Clone, Debug)]
+enum Table<'a> {
+    None,
+    /// Little endian UCS-2 codepoints
+    Psf1(&'a [u8]),
+    /// UTF-8 strings
+    Psf2(&'a [u8]),
+}
+
+/// A bitmap font
+pub struct Font<'a> {
+    glyphs: &'a [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    bytes_per_row: usize,
+    width: usize,
+    height: usize,
+    table: Table<'a>,
+    latin1: [u16; 256],
+    box_drawing: [u16; BOX_DRAWING_COUNT],
+    replacement: usize,
+}
+
+impl<'a> Font<'a> {
+    /// Loads a PSF1 or PSF2 font from the given data
+    pub fn load(data: &'a [u8]) -> Result<Self, FontError> {
+        let (header_size, glyph_count, bytes_per_glyph, width, height, has_table, psf2) =
+            if data.starts_with(&PSF1_MAGIC) && data.len() >= PSF1_HEADER_SIZE {
+                let mode = data[2];
+                let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+                let height = data[3] as usize;
+
+                (PSF1_HEADER_SIZE, count, height, 8, height, mode & PSF1_MODE_HAS_TABLE != 0, false)
+            } else if data.starts_with(&PSF2_MAGIC) && data.len() >= PSF2_HEADER_SIZE {
+                let header_size = read_u32(data, 8) as usize;
+                let flags = read_u32(data, 12);
+                let count = read_u32(data, 16) as usize;
+                let bytes_per_glyph = read_u32(data, 20) as usize;
+                let height = read_u32(data, 24) as usize;
+                let width = read_u32(data, 28) as usize;
+
+                (header_size, count, bytes_per_glyph, width, height, flags & PSF2_HAS_TABLE != 0, true)
+            } else {
+                return Err(FontError::InvalidMagic);
+            };
+
+        if width == 0 || width > 32 || height == 0 || glyph_count == 0 {
+            return Err(FontError::UnsupportedSize);
+        }
+
+        let bytes_per_row = (width + 7) / 8;
+        if bytes_per_glyph < bytes_per_row * height {
+            return Err(FontError::Truncated);
+        }
+
+        let glyphs_end = glyph_count.checked_mul(bytes_per_glyph)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(FontError::Truncated)?;
+        if glyphs_end > data.len() {
+            return Err(FontError::Truncated);
+        }
+
+        let table = match (has_table, psf2) {
+            (false, _) => Table::None,
+            (true, false) => Table::Psf1(&data[glyphs_end..]),
+            (true, true) => Table::Psf2(&data[glyphs_end..]),
+        };
+
+        let mut font = Font {
+            glyphs: &data[header_size..glyphs_end],
+            glyph_count,
+            bytes_per_glyph,
+            bytes_per_row,
+            width,
+            height,
+            table,
+            latin1: [NO_GLYPH; 256],
+            box_drawing: [NO_GLYPH; BOX_DRAWING_COUNT],
+            replacement: 0,
+        };
+
+        font.build_lookup();
+        Ok(font)
+    }
+
+    /// The width of each glyph in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of each glyph in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns `true` if the font has a glyph for the given character
+    #[allow(dead_code)] // Part of API
+    pub fn has_glyph(&self, character: char) -> bool {
+        self.find(character).is_some()
+    }
+
+    /// Returns `true` if the pixel at the given position of the given character's glyph is set
+    pub fn pixel(&self, character: char, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+
+        let index = self.find(character).unwrap_or(self.replacement);
+        let glyph = &self.glyphs[index * self.bytes_per_glyph..];
+
+        glyph[y * self.bytes_per_row + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Finds the index of the glyph for the given character
+    fn find(&self, character: char) -> Option<usize> {
+        let codepoint = character as u32;
+
+        let indexed = if codepoint < 0x100 {
+            Some(self.latin1[codepoint as usize])
+        } else if codepoint >= BOX_DRAWING_START && codepoint < BOX_DRAWING_START + BOX_DRAWING_COUNT as u32 {
+            Some(self.box_drawing[(codepoint - BOX_DRAWING_START) as usize])
+        } else {
+            None
+        };
+
+        match indexed {
+            Some(NO_GLYPH) => None,
+            Some(index) => Some(index as usize),
+            None => self.search(character),
+        }
+    }
+
+    /// Searches the Unicode table for the given character
+    fn search(&self, character: char) -> Option<usize> {
+        let mut found = None;
+
+        self.for_each_mapping(|index, mapped| {
+            if mapped == character && found.is_none() {
+                found = Some(index);
+            }
+        });
+
+        found
+    }
+
+    /// Indexes the Latin-1 and box drawing ranges, and finds the replacement glyph
+    fn build_lookup(&mut self) {
+        let mut latin1 = [NO_GLYPH; 256];
+        let mut box_drawing = [NO_GLYPH; BOX_DRAWING_COUNT];
+        let mut replacement = None;
+        let mut question_mark = None;
+
+        self.for_each_mapping(|index, character| {
+            let codepoint = character as u32;
+            let slot = if codepoint < 0x100 {
+                Some(&mut latin1[codepoint as usize])
+            } else if codepoint >= BOX_DRAWING_START && codepoint < BOX_DRAWING_START + BOX_DRAWING_COUNT as u32 {
+                Some(&mut box_drawing[(codepoint - BOX_DRAWING_START) as usize])
+            } else {
+                None
+            };
+
+            // The first glyph mapped to a character is used
+            if let Some(slot) = slot {
+                if *slot == NO_GLYPH {
+                    *slot = index as u16;
+                }
+            }
+
+            match character {
+                '\u{FFFD}' if replacement.is_none() => replacement = Some(index),
+                '?' if question_mark.is_none() => question_mark = Some(index),
+                _ => (),
+            }
+        });
+
+        self.latin1 = latin1;
+        self.box_drawing = box_drawing;
+        self.replacement = replacement.or(question_mark).unwrap_or(0);
+    }
+
+    /// Calls the given function with each glyph index and single character mapped to it. Without a
+    /// Unicode table, each glyph is mapped to the character with its index as a codepoint.
+    fn for_each_mapping<F>(&self, mut f: F)
+        where F: FnMut(usize, char)
+    {
+        match self.table {
+            Table::None => {
+                for index in 0..self.glyph_count {
+                    if let Some(character) = ::core::char::from_u32(index as u32) {
+                        f(index, character);
+                    }
+                }
+            }
+            Table::Psf1(table) => {
+                let mut index = 0;
+                let mut in_sequence = false;
+
+                for entry in table.chunks(2).filter(|entry| entry.len() == 2) {
+                    if index >= self.glyph_count {
+                        break;
+                    }
+
+                    match entry[0] as u16 | (entry[1] as u16) << 8 {
+                        PSF1_SEPARATOR => {
+                            index += 1;
+                            in_sequence = false;
+                        }
+                        PSF1_SEQUENCE_START => in_sequence = true,
+                        codepoint if !in_sequence => {
+                            if let Some(character) = ::core::char::from_u32(codepoint as u32) {
+                                f(index, character);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            Table::Psf2(table) => {
+                for (index, entry) in table.split(|&byte| byte == PSF2_SEPARATOR).enumerate() {
+                    if index >= self.glyph_count {
+                        break;
+                    }
+
+                    // Sequences of several characters drawn as one glyph are not supported
+                    let singles = entry.split(|&byte| byte == PSF2_SEQUENCE_START).next().unwrap_or(&[]);
+
+                    if let Ok(string) = str::from_utf8(singles) {
+                        for character in string.chars() {
+                            f(index, character);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data[offset] as u32 |
+        (data[offset + 1] as u32) << 8 |
+        (data[offset + 2] as u32) << 16 |
+        (data[offset + 3] as u32) << 24
+}
+
+kernel_test!(fn loads_psf1() {
+    // A font with 256 glyphs of 8x2 pixels, where glyph 'A' has its top row set
+    let mut data = [0u8; PSF1_HEADER_SIZE + 256 * 2];
+    data[..2].copy_from_slice(&PSF1_MAGIC);
+    data[3] = 2;
+    data[PSF1_HEADER_SIZE + 'A' as usize * 2] = 0xFF;
+
+    let font = Font::load(&data);
+    test_assert!(font.is_ok());
+
+    let font = font.unwrap();
+    test_assert_eq!((font.width(), font.height()), (8, 2));
+    test_assert!(font.pixel('A', 7, 0));
+    test_assert!(!font.pixel('A', 0, 1));
+    test_assert!(!font.has_glyph('┌'));
+});
+
+kernel_test!(fn rejects_truncated() {
+    let mut data = [0u8; PSF1_HEADER_SIZE + 16];
+    data[..2].copy_from_slice(&PSF1_MAGIC);
+    data[3] = 16;
+
+    test_assert_eq!(Font::load(&data).err(), Some(FontError::Truncated));
+});
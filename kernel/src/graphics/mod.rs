@@ -0,0 +1,344 @@
+//! # Graphics
+//!
+//! Drawing to a linear framebuffer set up by the bootloader through VBE. Drawing is done to a
+//! software back buffer, which is copied to the framebuffer by `present`, so that partially drawn
+//! frames are never shown and the slow uncached framebuffer is only ever written to.
+//!
+//! The kernel only asks the bootloader for a framebuffer when built with `make graphics=1`, as the
+//! terminal is written to VGA text memory and is not visible in a graphics mode. Without one,
+//! `DISPLAY` is `None`.
+//!
+//! Text is drawn with a PSF font loaded from `FONT_PATH` in the initrd, which is kept in `FONT`. See
+//! the [font] module.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! if let Some(ref mut display) = *graphics::DISPLAY.lock() {
+//!     display.clear(Rgb::new(0, 0, 0));
+//!     display.fill_rect(10, 10, 100, 50, Rgb::new(0xFF, 0, 0));
+//!     display.present();
+//! }
+//! ```
+
+use core::{cmp, slice};
+use fs;
+use memory::{self, PAGE_SIZE};
+use memory::mmio::{self, MmioError, MmioRegion};
+use memory::paging::{self, EntryFlags, MapError};
+use multiboot::{BootInfo, ColorField, FramebufferInfo};
+use spin::{Mutex, RwLock};
+
+pub mod font;
+
+pub use self::font::{Font, TextAttributes};
+
+/// The path of the console font in the initrd
+pub const FONT_PATH: &'static str = "fonts/console.psf";
+
+/// The virtual address the back buffer is mapped at
+const BACK_BUFFER_ADDRESS: usize = 0xFFFF_FE80_4000_0000;
+
+/// The display, if the bootloader set up a framebuffer
+pub static DISPLAY: Mutex<Option<Display>> = Mutex::new(None);
+
+/// The console font, if one was found in the initrd
+pub static FONT: RwLock<Option<Font<'static>>> = RwLock::new(None);
+
+/// A 24-bit color
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Rgb { red, green, blue }
+    }
+}
+
+/// An image which can be drawn with `blit`, stored row by row
+#[derive(Copy, Clone, Debug)]
+pub struct Image<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [Rgb],
+}
+
+/// An error returned when setting up the display
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum GraphicsError {
+    /// The framebuffer has a pixel depth which is not 16, 24 or 32 bits
+    UnsupportedDepth(u8),
+    /// The frame allocator is not initialized
+    NoFrameAllocator,
+    Map(MapError),
+    /// The framebuffer could not be mapped
+    Mmio(MmioError),
+}
+
+impl From<MapError> for GraphicsError {
+    fn from(error: MapError) -> Self {
+        GraphicsError::Map(error)
+    }
+}
+
+impl From<MmioError> for GraphicsError {
+    fn from(error: MmioError) -> Self {
+        GraphicsError::Mmio(error)
+    }
+}
+
+/// A framebuffer and the back buffer drawn to
+pub struct Display {
+    info: FramebufferInfo,
+    front: MmioRegion,
+    back: &'static mut [Rgb],
+}
+
+impl Display {
+    /// Maps the given framebuffer and allocates a back buffer for it
+    fn new(info: FramebufferInfo) -> Result<Self, GraphicsError> {
+        match info.bpp {
+            16 | 24 | 32 => (),
+            bpp => return Err(GraphicsError::UnsupportedDepth(bpp)),
+        }
+
+        let front = mmio::map(info.address, info.pitch * info.height, "graphics")?;
+
+        let mut table = paging::ACTIVE_TABLE.lock();
+        let mut allocator = memory::FRAME_ALLOCATOR.lock();
+        let allocator = allocator.as_mut().ok_or(GraphicsError::NoFrameAllocator)?;
+
+        let pixels = info.width * info.height;
+        let back_size = round_up(pixels * ::core::mem::size_of::<Rgb>());
+        table.map_range(BACK_BUFFER_ADDRESS, back_size, EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE, allocator)?;
+
+        let mut display = Display {
+            info,
+            front,
+            back: unsafe { slice::from_raw_parts_mut(BACK_BUFFER_ADDRESS as *mut Rgb, pixels) },
+        };
+
+        // Newly allocated frames are not zeroed
+        display.clear(Rgb::new(0, 0, 0));
+
+        Ok(display)
+    }
+
+    #[allow(dead_code)] // Part of API
+    pub fn width(&self) -> usize {
+        self.info.width
+    }
+
+    #[allow(dead_code)] // Part of API
+    pub fn height(&self) -> usize {
+        self.info.height
+    }
+
+    /// Fills the back buffer with the given color
+    pub fn clear(&mut self, color: Rgb) {
+        for pixel in self.back.iter_mut() {
+            *pixel = color;
+        }
+    }
+
+    /// Sets the pixel at the given position, if it is on the display
+    #[allow(dead_code)] // Part of API
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.info.width && y < self.info.height {
+            self.back[y * self.info.width + x] = color;
+        }
+    }
+
+    /// Fills the given rectangle, clipped to the display
+    #[allow(dead_code)] // Part of API
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+
+        let end_x = cmp::min(x.saturating_add(width), self.info.width);
+        let end_y = cmp::min(y.saturating_add(height), self.info.height);
+
+        for row in y..end_y {
+            let start = row * self.info.width;
+
+            for pixel in &mut self.back[start + x..start + end_x] {
+                *pixel = color;
+            }
+        }
+    }
+
+    /// Draws the given image with its top left corner at the given position, clipped to the display
+    #[allow(dead_code)] // Part of API
+    pub fn blit(&mut self, x: usize, y: usize, image: &Image) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+
+        let width = cmp::min(image.width, self.info.width - x);
+        let height = cmp::min(image.height, self.info.height - y);
+
+        for row in 0..height {
+            let source = &image.pixels[row * image.width..row * image.width + width];
+            let start = (y + row) * self.info.width + x;
+
+            self.back[start..start + width].copy_from_slice(source);
+        }
+    }
+
+    /// Draws a character with its top left corner at the given position, clipped to the display
+    #[allow(dead_code)] // Part of API
+    pub fn draw_char(
+        &mut self,
+        x: usize,
+        y: usize,
+        font: &Font,
+        character: char,
+        foreground: Rgb,
+        background: Rgb,
+        attributes: TextAttributes
+    ) {
+        let bold = attributes.contains(TextAttributes::BOLD);
+        let underline_row = if attributes.contains(TextAttributes::UNDERLINE) {
+            Some(font.height() - 1)
+        } else {
+            None
+        };
+
+        for row in 0..font.height() {
+            for column in 0..font.width() {
+                // Bold glyphs are smeared a pixel to the right
+                let set = font.pixel(character, column, row) ||
+                    (bold && column > 0 && font.pixel(character, column - 1, row)) ||
+                    underline_row == Some(row);
+
+                let color = if set { foreground } else { background };
+                self.set_pixel(x + column, y + row, color);
+            }
+        }
+    }
+
+    /// Draws a string from left to right with its top left corner at the given position, clipped to
+    /// the display. Returns the x position after the last character.
+    #[allow(dead_code)] // Part of API
+    pub fn draw_text(
+        &mut self,
+        x: usize,
+        y: usize,
+        font: &Font,
+        text: &str,
+        foreground: Rgb,
+        background: Rgb,
+        attributes: TextAttributes
+    ) -> usize {
+        let mut x = x;
+
+        for character in text.chars() {
+            self.draw_char(x, y, font, character, foreground, background, attributes);
+            x += font.width();
+        }
+
+        x
+    }
+
+    /// Copies the back buffer to the framebuffer
+    pub fn present(&mut self) {
+        let bytes_per_pixel = self.info.bpp as usize / 8;
+
+        for y in 0..self.info.height {
+            let row = unsafe { self.front.as_ptr().offset((y * self.info.pitch) as isize) };
+            let pixels = &self.back[y * self.info.width..(y + 1) * self.info.width];
+
+            for (x, &pixel) in pixels.iter().enumerate() {
+                let value = self.encode(pixel);
+
+                unsafe {
+                    let address = row.offset((x * bytes_per_pixel) as isize);
+
+                    match bytes_per_pixel {
+                        4 => (address as *mut u32).write_volatile(value),
+                        2 => (address as *mut u16).write_volatile(value as u16),
+                        _ => {
+                            address.write_volatile(value as u8);
+                            address.offset(1).write_volatile((value >> 8) as u8);
+                            address.offset(2).write_volatile((value >> 16) as u8);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Converts a color to the framebuffer's pixel format
+    fn encode(&self, color: Rgb) -> u32 {
+        encode_channel(color.red, self.info.red) |
+            encode_channel(color.green, self.info.green) |
+            encode_channel(color.blue, self.info.blue)
+    }
+}
+
+/// Scales an 8-bit channel to the field's size and shifts it into position
+fn encode_channel(value: u8, field: ColorField) -> u32 {
+    match field.size {
+        0 => 0,
+        size => ((value >> (8 - cmp::min(size, 8))) as u32) << field.position,
+    }
+}
+
+/// Rounds the given size up to a whole number of pages
+fn round_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+/// Sets up the display if the bootloader set up a framebuffer, and loads the console font. This must
+/// be called after the frame allocator and initrd are initialized.
+pub fn init(boot_info: &BootInfo) {
+    load_font();
+
+    let info = match boot_info.framebuffer() {
+        Some(info) => info,
+        None => {
+            info!("graphics: no framebuffer, staying in text mode");
+            return;
+        }
+    };
+
+    match Display::new(info) {
+        Ok(mut display) => {
+            display.present();
+            info!("graphics: {}x{}x{} framebuffer", info.width, info.height, info.bpp);
+            *DISPLAY.lock() = Some(display);
+        }
+        Err(error) => error!("graphics: framebuffer setup failed: {:?}", error),
+    }
+}
+
+/// Loads the console font from the initrd, if it has one
+fn load_font() {
+    let entry = match fs::root().and_then(|root| root.find(FONT_PATH)) {
+        Some(entry) => entry,
+        None => {
+            debug!("graphics: no console font at {}", FONT_PATH);
+            return;
+        }
+    };
+
+    match Font::load(entry.data()) {
+        Ok(font) => {
+            info!("graphics: loaded {}x{} console font", font.width(), font.height());
+            *FONT.write() = Some(font);
+        }
+        Err(error) => error!("graphics: console font load failed: {:?}", error),
+    }
+}
+
+kernel_test!(fn encodes_channels() {
+    let field = ColorField { position: 11, size: 5 };
+    test_assert_eq!(encode_channel(0xFF, field), 0x1F << 11);
+    test_assert_eq!(encode_channel(0x80, ColorField { position: 16, size: 8 }), 0x80 << 16);
+});
@@ -0,0 +1,40 @@
+use bootargs::BootArgs;
+use super::{Suite, TestResult};
+
+pub const SUITE: Suite = Suite {
+    name: "bootargs",
+    tests: &[
+        test_case!(parses_values),
+        test_case!(parses_flags),
+        test_case!(later_arguments_override),
+        test_case!(missing_arguments),
+    ],
+};
+
+fn parses_values() -> TestResult {
+    let args = BootArgs::parse("loglevel=warn console=serial");
+    test_assert_eq!(args.get("loglevel"), Some("warn"));
+    test_assert_eq!(args.get("console"), Some("serial"));
+    Ok(())
+}
+
+fn parses_flags() -> TestResult {
+    let args = BootArgs::parse("  nosmp\tempty= ");
+    test_assert!(args.has("nosmp"));
+    test_assert_eq!(args.get("nosmp"), Some(""));
+    test_assert_eq!(args.get("empty"), Some(""));
+    Ok(())
+}
+
+fn later_arguments_override() -> TestResult {
+    let args = BootArgs::parse("loglevel=info loglevel=trace");
+    test_assert_eq!(args.get("loglevel"), Some("trace"));
+    Ok(())
+}
+
+fn missing_arguments() -> TestResult {
+    let args = BootArgs::parse("");
+    test_assert!(!args.has("nosmp"));
+    test_assert_eq!(args.get("loglevel"), None);
+    Ok(())
+}
@@ -0,0 +1,9 @@
+/// Creates a `TestCase` from a test function, named after the function
+macro_rules! test_case {
+    ($test:path) => {
+        ::integration::TestCase {
+            name: stringify!($test),
+            run: $test,
+        }
+    };
+}
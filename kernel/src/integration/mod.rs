@@ -0,0 +1,162 @@
+//! # Integration tests
+//!
+//! When built with the `integration-test` feature, the kernel runs its test suites after
+//! initialization instead of starting normally, and then exits QEMU with a status code through the
+//! isa-debug-exit device. `make test` builds the kernel this way and checks the exit status.
+//!
+//! A single suite can be run by passing its name with the `test` boot argument, e.g.
+//! `test=paging`. Otherwise, every suite is run. Results are printed to the terminal, and also
+//! written to the `COM1` serial port so that they can be read on the host.
+//!
+//! Tests are plain functions which return `Err` with a message on failure, usually through
+//! `test_assert!` and `test_assert_eq!`. They are either grouped into a [Suite] listed in `SUITES`,
+//! or declared next to the code they test with `kernel_test!`. Tests declared with `kernel_test!`
+//! are collected by the linker into the `.kernel_tests` section, and run as the `unit` suite.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! fn addition_works() -> TestResult {
+//!     test_assert_eq!(1 + 1, 2);
+//!     Ok(())
+//! }
+//!
+//! pub const SUITE: Suite = Suite {
+//!     name: "arithmetic",
+//!     tests: &[test_case!(addition_works)],
+//! };
+//!
+//! kernel_test!(fn subtraction_works() {
+//!     test_assert_eq!(2 - 1, 1);
+//! });
+//! ```
+
+#[macro_use]
+mod macros;
+mod bootargs;
+mod paging;
+
+use color::ColorPair;
+use core::fmt::{self, Write};
+use core::mem;
+use core::slice;
+use drivers::serial;
+use qemu::{self, ExitCode};
+use terminal::TerminalOutput;
+
+/// Prints to both the terminal and the serial port
+macro_rules! report {
+    ($($arg:tt)*) => ({
+        print!($($arg)*);
+        report_serial(format_args!($($arg)*));
+    });
+}
+
+/// The name of the suite of tests declared with `kernel_test!`
+const UNIT_SUITE: &str = "unit";
+
+extern {
+    /// The start of the tests registered with `kernel_test!`, provided by the linker
+    static kernel_tests_start: u8;
+    /// The end of the tests registered with `kernel_test!`, provided by the linker
+    static kernel_tests_end: u8;
+}
+
+/// The result of a single test
+pub type TestResult = Result<(), &'static str>;
+
+/// A single named test
+pub struct TestCase {
+    pub name: &'static str,
+    pub run: fn() -> TestResult,
+}
+
+/// A named group of tests for a single subsystem
+pub struct Suite {
+    pub name: &'static str,
+    pub tests: &'static [TestCase],
+}
+
+/// Every test suite in the kernel
+const SUITES: &[Suite] = &[
+    bootargs::SUITE,
+    paging::SUITE,
+    ::drivers::keyboard::tests::SUITE,
+];
+
+/// Runs the suite with the given name, or every suite if `None`, and exits QEMU with whether all
+/// tests passed
+pub fn run(name: Option<&str>) -> ! {
+    let mut results = Results { passed: 0, failed: 0 };
+    let selected = |suite: &str| name.map(|name| name == suite).unwrap_or(true);
+
+    for suite in SUITES.iter().filter(|suite| selected(suite.name)) {
+        run_suite(suite.name, suite.tests, &mut results);
+    }
+
+    if selected(UNIT_SUITE) {
+        run_suite(UNIT_SUITE, unit_tests(), &mut results);
+    }
+
+    report!("test: {} passed, {} failed\n", results.passed, results.failed);
+
+    if results.passed + results.failed == 0 {
+        error!("test: no tests matched {:?}", name);
+        qemu::exit(ExitCode::Failure)
+    } else if results.failed > 0 {
+        qemu::exit(ExitCode::Failure)
+    } else {
+        qemu::exit(ExitCode::Success)
+    }
+}
+
+/// The amount of tests which passed and failed
+struct Results {
+    passed: usize,
+    failed: usize,
+}
+
+fn run_suite(suite: &str, tests: &[TestCase], results: &mut Results) {
+    report!("test: running suite {}\n", suite);
+
+    for test in tests {
+        // Tests declared with `kernel_test!` are named by their module path
+        let name = test.name.trim_left_matches("flower_kernel::");
+
+        match (test.run)() {
+            Ok(()) => {
+                results.passed += 1;
+                print_status("[ok]   ", color!(Green on Black));
+                report!("{}::{}\n", suite, name);
+            }
+            Err(message) => {
+                results.failed += 1;
+                print_status("[fail] ", color!(Red on Black));
+                report!("{}::{}: {}\n", suite, name, message);
+            }
+        }
+    }
+}
+
+/// Gets the tests registered with `kernel_test!`
+fn unit_tests() -> &'static [TestCase] {
+    unsafe {
+        let start = &kernel_tests_start as *const u8 as usize;
+        let end = &kernel_tests_end as *const u8 as usize;
+        let count = (end - start) / mem::size_of::<TestCase>();
+
+        slice::from_raw_parts(start as *const TestCase, count)
+    }
+}
+
+fn print_status(status: &str, color: ColorPair) {
+    ::terminal::STDOUT.write().write_string_colored(status, color)
+        .expect("Error logging");
+
+    report_serial(format_args!("{}", status));
+}
+
+fn report_serial(args: fmt::Arguments) {
+    // Ignore error, as the terminal output is still available
+    let _ = serial::COM1.lock().write_fmt(args);
+}
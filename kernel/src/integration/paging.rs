@@ -0,0 +1,136 @@
+use memory::{self, PAGE_SIZE, PHYSICAL_MEMORY_OFFSET};
+use memory::paging::{self, EntryFlags, MapError, Page, PageSize, HUGE_PAGE_1GIB};
+use super::{Suite, TestResult};
+
+/// An address in an otherwise unused P4 entry, for test mappings
+const TEST_ADDRESS: usize = 0xFFFF_FF00_0000_0000;
+
+pub const SUITE: Suite = Suite {
+    name: "paging",
+    tests: &[
+        test_case!(translates_physical_map),
+        test_case!(kernel_text_is_read_only),
+        test_case!(map_and_unmap),
+        test_case!(map_twice_fails),
+        test_case!(unmap_unmapped_fails),
+        test_case!(unaligned_range_fails),
+        test_case!(set_flags_splits_2mib_page),
+        test_case!(set_flags_splits_1gib_page),
+    ],
+};
+
+fn translates_physical_map() -> TestResult {
+    let table = paging::ACTIVE_TABLE.lock();
+    test_assert_eq!(table.translate(PHYSICAL_MEMORY_OFFSET + 0xB8123), Some(0xB8123));
+    Ok(())
+}
+
+fn kernel_text_is_read_only() -> TestResult {
+    let table = paging::ACTIVE_TABLE.lock();
+    let (_, flags) = table.translate_with_flags(kernel_text_is_read_only as usize)
+        .ok_or("kernel text not mapped")?;
+
+    test_assert!(!flags.contains(EntryFlags::WRITABLE));
+    test_assert!(!flags.contains(EntryFlags::NO_EXECUTE));
+    Ok(())
+}
+
+fn map_and_unmap() -> TestResult {
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = memory::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or("no frame allocator")?;
+
+    let page = Page::containing_address(TEST_ADDRESS);
+    test_assert!(table.map(page, EntryFlags::WRITABLE, allocator).is_ok());
+    test_assert!(table.translate(TEST_ADDRESS).is_some());
+
+    unsafe {
+        let pointer = TEST_ADDRESS as *mut u64;
+        pointer.write_volatile(0xF10E_F10E);
+        test_assert_eq!(pointer.read_volatile(), 0xF10E_F10E);
+
+        test_assert!(table.unmap(page, allocator).is_ok());
+    }
+
+    test_assert_eq!(table.translate(TEST_ADDRESS), None);
+    Ok(())
+}
+
+fn map_twice_fails() -> TestResult {
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = memory::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or("no frame allocator")?;
+
+    let page = Page::containing_address(TEST_ADDRESS);
+    test_assert!(table.map(page, EntryFlags::empty(), allocator).is_ok());
+    test_assert_eq!(table.map(page, EntryFlags::empty(), allocator), Err(MapError::AlreadyMapped));
+
+    test_assert!(unsafe { table.unmap(page, allocator) }.is_ok());
+    Ok(())
+}
+
+fn unmap_unmapped_fails() -> TestResult {
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = memory::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or("no frame allocator")?;
+    let page = Page::containing_address(TEST_ADDRESS + PAGE_SIZE);
+
+    test_assert_eq!(unsafe { table.unmap(page, allocator) }, Err(MapError::NotMapped));
+    Ok(())
+}
+
+fn unaligned_range_fails() -> TestResult {
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = memory::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or("no frame allocator")?;
+
+    let result = table.map_range(TEST_ADDRESS + 1, PAGE_SIZE, EntryFlags::WRITABLE, allocator);
+    test_assert_eq!(result, Err(MapError::Unaligned));
+    Ok(())
+}
+
+fn set_flags_splits_2mib_page() -> TestResult {
+    set_flags_splits_huge_page(TEST_ADDRESS + HUGE_PAGE_1GIB, PageSize::Size2MiB)
+}
+
+fn set_flags_splits_1gib_page() -> TestResult {
+    if !PageSize::Size1GiB.supported() {
+        return Ok(());
+    }
+
+    set_flags_splits_huge_page(TEST_ADDRESS + 2 * HUGE_PAGE_1GIB, PageSize::Size1GiB)
+}
+
+/// Maps a huge page of the given size read-only at the given address, which must be in an unused
+/// 1GiB region, and makes one 4KiB page within it writable
+fn set_flags_splits_huge_page(start: usize, size: PageSize) -> TestResult {
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = memory::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or("no frame allocator")?;
+
+    // Alias the start of physical memory read-only, which is never written through this mapping
+    let result = unsafe { table.map_huge_to(start, 0, size, EntryFlags::NO_EXECUTE, allocator) };
+    test_assert!(result.is_ok());
+
+    let address = start + PAGE_SIZE;
+    test_assert!(table.set_flags(address, PAGE_SIZE, EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE, allocator).is_ok());
+
+    let (physical, flags) = table.translate_with_flags(address).ok_or("page unmapped by split")?;
+    test_assert_eq!(physical, PAGE_SIZE);
+    test_assert!(flags.contains(EntryFlags::WRITABLE));
+
+    // The rest of the huge page keeps its mapping and flags
+    for &offset in [0, 2 * PAGE_SIZE, size.bytes() - PAGE_SIZE].iter() {
+        let (physical, flags) = table.translate_with_flags(start + offset).ok_or("page unmapped by split")?;
+        test_assert_eq!(physical, offset);
+        test_assert!(!flags.contains(EntryFlags::WRITABLE));
+    }
+
+    let mut address = start;
+    while address < start + size.bytes() {
+        let (_, page_size) = unsafe { table.unmap_huge(address) }.map_err(|_| "could not unmap split page")?;
+        address += page_size.bytes();
+    }
+
+    Ok(())
+}
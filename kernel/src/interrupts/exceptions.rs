@@ -1,75 +1,234 @@
 //! Exception handlers
 
+use arch::{self, Registers};
+use arch::cpuid::{self, Features};
+use core::fmt;
+use memory::paging::ActivePageTable;
+use unwind::Backtrace;
 use x86_64::structures::idt::{ExceptionStackFrame, PageFaultErrorCode};
 
+/// The amount of words at the top of the interrupted stack which are printed in diagnostics
+const STACK_DUMP_WORDS: usize = 8;
+
+/// Set in a page fault's error code if the page was present, so the fault is a protection violation
+const PAGE_FAULT_PRESENT: u64 = 1 << 0;
+/// Set in a page fault's error code if the access was a write
+const PAGE_FAULT_WRITE: u64 = 1 << 1;
+/// Set in a page fault's error code if the access was from user mode
+const PAGE_FAULT_USER: u64 = 1 << 2;
+/// Set in a page fault's error code if a reserved bit was set in a page table entry
+const PAGE_FAULT_RESERVED_BIT: u64 = 1 << 3;
+/// Set in a page fault's error code if the access was an instruction fetch
+const PAGE_FAULT_INSTRUCTION_FETCH: u64 = 1 << 4;
+
+/// Machine check global capabilities MSR
+const IA32_MCG_CAP: u32 = 0x179;
+/// Machine check global status MSR
+const IA32_MCG_STATUS: u32 = 0x17A;
+/// The status MSR of the first machine check bank. Each bank has 4 MSRs: control, status, address
+/// and miscellaneous information.
+const IA32_MC0_STATUS: u32 = 0x401;
+/// The address MSR of the first machine check bank
+const IA32_MC0_ADDR: u32 = 0x402;
+/// Set in a bank's status if it holds a valid error
+const MCI_STATUS_VALID: u64 = 1 << 63;
+/// Set in a bank's status if its address MSR is valid
+const MCI_STATUS_ADDRESS_VALID: u64 = 1 << 58;
+/// Set in the global status if execution can be restarted
+const MCG_STATUS_RESTART_IP_VALID: u64 = 1 << 0;
+
 pub extern "x86-interrupt" fn divide_by_zero(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: divide by zero\n{:#?}", stack_frame);
+    panic!("cpuex: divide by zero\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn breakpoint(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: breakpoint\n{:#?}", stack_frame);
+    panic!("cpuex: breakpoint\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn overflow(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: overflow\n{:#?}", stack_frame);
+    panic!("cpuex: overflow\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn out_of_bounds(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: out of bounds\n{:#?}", stack_frame);
+    panic!("cpuex: out of bounds\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn invalid_opcode(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: invalid opcode\n{:#?}", stack_frame);
+    panic!("cpuex: invalid opcode\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn device_not_available(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: device not available\n{:#?}", stack_frame);
+    panic!("cpuex: device not available\n{}", Context(stack_frame));
 }
 
-pub extern "x86-interrupt" fn double_fault(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: double fault {}\n{:#?}", code, stack_frame);
+pub extern "x86-interrupt" fn double_fault(stack_frame: &mut ExceptionStackFrame, _code: u64) {
+    // Runs on its own IST stack, so that a kernel stack overflow can still be reported. The error
+    // code is always zero.
+    panic!("cpuex: double fault\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn invalid_tss(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: invalid tss {}\n{:#?}", code, stack_frame);
+    panic!("cpuex: invalid tss {}\n{}", code, Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn segment_not_present(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: segment not present {}\n{:#?}", code, stack_frame);
+    panic!("cpuex: segment not present {}\n{}", code, Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn stack_segment_fault(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: stack segment fault {}\n{:#?}", code, stack_frame);
+    panic!("cpuex: stack segment fault {}\n{}", code, Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn general_protection_fault(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: general protection fault {}\n{:#?}", code, stack_frame);
+    panic!("cpuex: general protection fault ({})\n{}", SelectorErrorCode(code), Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn page_fault(stack_frame: &mut ExceptionStackFrame, code: PageFaultErrorCode) {
-    panic!("cpuex: page fault fault {:?}\n{:#?}", code, stack_frame);
+    panic!("cpuex: page fault ({})\n{}", PageFaultCode(code), Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn x87_floating_point(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: x87 floating point\n{:#?}", stack_frame);
+    panic!("cpuex: x87 floating point\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn alignment_check(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: alignment check {}\n{:#?}", code, stack_frame);
+    panic!("cpuex: alignment check {}\n{}", code, Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn machine_check(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: machine check\n{:#?}", stack_frame);
+    // Runs on its own IST stack, as the interrupted stack may be in the failing memory
+    panic!("cpuex: machine check\n{}{}", MachineCheck, Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn simd_floating_point(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: simd floating point\n{:#?}", stack_frame);
+    panic!("cpuex: simd floating point\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn virtualization(stack_frame: &mut ExceptionStackFrame) {
-    panic!("cpuex: simd floating point\n{:#?}", stack_frame);
+    panic!("cpuex: virtualization\n{}", Context(stack_frame));
 }
 
 pub extern "x86-interrupt" fn security_exception(stack_frame: &mut ExceptionStackFrame, code: u64) {
-    panic!("cpuex: security exception {}\n{:#?}", code, stack_frame);
+    panic!("cpuex: security exception {}\n{}", code, Context(stack_frame));
+}
+
+/// Formats the context an exception interrupted: its registers, the top of the interrupted stack if
+/// it is mapped, and a backtrace from the interrupted instruction
+struct Context<'a>(&'a ExceptionStackFrame);
+
+impl<'a> fmt::Display for Context<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = Registers::from_exception(self.0);
+        let stack_pointer = registers.rsp as usize;
+
+        write!(f, "{}", registers)?;
+
+        // Safe because the tables are only read
+        let table = unsafe { ActivePageTable::unlocked() };
+
+        for i in 0..STACK_DUMP_WORDS {
+            let address = stack_pointer + i * 8;
+
+            // The stack may have overflowed into unmapped memory
+            if table.translate(address).is_none() {
+                writeln!(f, "{:#018x}: <not mapped>", address)?;
+                break;
+            }
+
+            let word = unsafe { *(address as *const u64) };
+            writeln!(f, "{:#018x}: {:#018x}", address, word)?;
+        }
+
+        write!(f, "backtrace:\n{}", Backtrace::new(registers.frame))
+    }
+}
+
+/// Formats the error code of an exception which refers to a segment selector
+struct SelectorErrorCode(u64);
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.0;
+
+        if code == 0 {
+            return write!(f, "not segment related");
+        }
+
+        let table = match (code >> 1) & 0b11 {
+            0b00 => "gdt",
+            0b10 => "ldt",
+            _ => "idt",
+        };
+
+        write!(f, "{} selector {:#x}", table, code & 0xFFF8)?;
+
+        if code & 1 != 0 {
+            write!(f, ", external")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats the error code of a page fault as the cause and kind of the access
+struct PageFaultCode(PageFaultErrorCode);
+
+impl fmt::Display for PageFaultCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.0.bits();
+
+        let cause = if code & PAGE_FAULT_PRESENT != 0 { "protection violation" } else { "page not present" };
+        let access = if code & PAGE_FAULT_INSTRUCTION_FETCH != 0 {
+            "instruction fetch"
+        } else if code & PAGE_FAULT_WRITE != 0 {
+            "write"
+        } else {
+            "read"
+        };
+        let mode = if code & PAGE_FAULT_USER != 0 { "user" } else { "kernel" };
+
+        write!(f, "{} on {} in {} mode", cause, access, mode)?;
+
+        if code & PAGE_FAULT_RESERVED_BIT != 0 {
+            write!(f, ", reserved bit set in page table")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats the state of the machine check banks
+struct MachineCheck;
+
+impl fmt::Display for MachineCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !cpuid::has(Features::MCA) {
+            return writeln!(f, "machine check architecture unsupported, no details available");
+        }
+
+        unsafe {
+            let status = arch::rdmsr(IA32_MCG_STATUS);
+            let banks = (arch::rdmsr(IA32_MCG_CAP) & 0xFF) as u32;
+            let restartable = status & MCG_STATUS_RESTART_IP_VALID != 0;
+
+            writeln!(f, "mcg status {:#x} (restartable: {})", status, restartable)?;
+
+            for bank in 0..banks {
+                let bank_status = arch::rdmsr(IA32_MC0_STATUS + bank * 4);
+                if bank_status & MCI_STATUS_VALID == 0 {
+                    continue;
+                }
+
+                write!(f, "bank {}: status {:#018x}", bank, bank_status)?;
+
+                if bank_status & MCI_STATUS_ADDRESS_VALID != 0 {
+                    write!(f, " address {:#x}", arch::rdmsr(IA32_MC0_ADDR + bank * 4))?;
+                }
+
+                writeln!(f, "")?;
+            }
+        }
+
+        Ok(())
+    }
 }
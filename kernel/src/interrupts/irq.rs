@@ -0,0 +1,152 @@
+//! # IRQs
+//!
+//! Hardware interrupts from the legacy PICs are dispatched to handlers which drivers register at
+//! runtime, so that drivers can claim IRQs without editing the interrupts module. IRQs may be shared
+//! by up to `MAX_SHARED_HANDLERS` handlers, each of which is called when the IRQ fires.
+//!
+//! An IRQ is unmasked when its first handler is registered, and masked again when its last handler
+//! is unregistered.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! fn keyboard_irq(_irq: u8) -> bool {
+//!     // Read the scancode...
+//!     true
+//! }
+//!
+//! interrupts::register(1, keyboard_irq)?;
+//! ```
+
+use arch;
+use super::legacy_pic::CHAINED_PICS;
+use spin::RwLock;
+use x86_64::structures::idt::{ExceptionStackFrame, Idt};
+
+/// The amount of IRQs provided by the chained PICs
+pub const IRQ_COUNT: usize = 16;
+/// The maximum amount of handlers which can share a single IRQ
+pub const MAX_SHARED_HANDLERS: usize = 4;
+/// The interrupt vector of IRQ 0
+pub const IRQ_OFFSET: u8 = 0x20;
+
+/// The IRQ which chains the slave PIC to the master, which cannot be registered
+const CASCADE_IRQ: u8 = 2;
+
+/// A function called when an IRQ fires. It returns `true` if its device raised the interrupt.
+pub type IrqHandler = fn(u8) -> bool;
+
+/// An error returned when registering an IRQ handler
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum IrqError {
+    /// The IRQ does not exist, or cannot be registered
+    InvalidIrq,
+    /// This handler is already registered for the IRQ
+    AlreadyRegistered,
+    /// The IRQ is already shared by `MAX_SHARED_HANDLERS` handlers
+    LineFull,
+}
+
+/// The handlers registered for each IRQ. This is only written to with interrupts disabled, so that
+/// dispatching an IRQ cannot deadlock on it.
+static HANDLERS: RwLock<[[Option<IrqHandler>; MAX_SHARED_HANDLERS]; IRQ_COUNT]> =
+    RwLock::new([[None; MAX_SHARED_HANDLERS]; IRQ_COUNT]);
+
+/// Registers a handler for the given IRQ, from 0 to 15, unmasking it if it had no handlers
+#[allow(dead_code)] // Part of API
+pub fn register(irq: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    if irq as usize >= IRQ_COUNT || irq == CASCADE_IRQ {
+        return Err(IrqError::InvalidIrq);
+    }
+
+    arch::without_interrupts(|| {
+        let mut handlers = HANDLERS.write();
+        let line = &mut handlers[irq as usize];
+
+        if line.iter().any(|slot| *slot == Some(handler)) {
+            return Err(IrqError::AlreadyRegistered);
+        }
+
+        let first = line.iter().all(|slot| slot.is_none());
+
+        match line.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(handler),
+            None => return Err(IrqError::LineFull),
+        }
+
+        if first {
+            CHAINED_PICS.lock().set_masked(irq, false);
+        }
+
+        Ok(())
+    })
+}
+
+/// Unregisters a handler for the given IRQ, masking it if no handlers remain. Returns `true` if the
+/// handler was registered.
+#[allow(dead_code)] // Part of API
+pub fn unregister(irq: u8, handler: IrqHandler) -> bool {
+    if irq as usize >= IRQ_COUNT {
+        return false;
+    }
+
+    arch::without_interrupts(|| {
+        let mut handlers = HANDLERS.write();
+        let line = &mut handlers[irq as usize];
+
+        let removed = match line.iter_mut().find(|slot| **slot == Some(handler)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        };
+
+        if removed && line.iter().all(|slot| slot.is_none()) {
+            CHAINED_PICS.lock().set_masked(irq, true);
+        }
+
+        removed
+    })
+}
+
+/// Points the IRQ vectors of the IDT at the dispatcher
+pub fn install(idt: &mut Idt) {
+    for (irq, &stub) in STUBS.iter().enumerate() {
+        idt[IRQ_OFFSET as usize + irq].set_handler_fn(stub);
+    }
+}
+
+/// Calls every handler registered for the given IRQ, and acknowledges it
+fn dispatch(irq: u8) {
+    if CHAINED_PICS.lock().is_spurious(irq) {
+        return;
+    }
+
+    // Every handler is called, as more than one device sharing the IRQ may have raised it
+    let handlers = HANDLERS.read()[irq as usize];
+    for handler in handlers.iter().filter_map(|slot| *slot) {
+        handler(irq);
+    }
+
+    CHAINED_PICS.lock().end_of_interrupt(irq);
+}
+
+/// Creates an interrupt handler for each IRQ which calls `dispatch`, as interrupt handlers are not
+/// told which vector they were called for
+macro_rules! irq_stubs {
+    ($($name:ident = $irq:expr),*) => {
+        $(
+            extern "x86-interrupt" fn $name(_stack_frame: &mut ExceptionStackFrame) {
+                dispatch($irq);
+            }
+        )*
+
+        const STUBS: [extern "x86-interrupt" fn(&mut ExceptionStackFrame); IRQ_COUNT] = [$($name),*];
+    };
+}
+
+irq_stubs! {
+    irq_0 = 0, irq_1 = 1, irq_2 = 2, irq_3 = 3, irq_4 = 4, irq_5 = 5, irq_6 = 6, irq_7 = 7,
+    irq_8 = 8, irq_9 = 9, irq_10 = 10, irq_11 = 11, irq_12 = 12, irq_13 = 13, irq_14 = 14, irq_15 = 15
+}
@@ -10,10 +10,16 @@ pub static IO_WAIT_PORT: SynchronizedPort<u8> = unsafe { SynchronizedPort::new(0
 
 #[repr(u8)]
 enum Commands {
-    Init = 0x10,
+    /// Starts initialization, and indicates that the mode will be set afterwards
+    Init = 0x11,
     EndOfInterrupt = 0x20,
+    /// Makes the next read from the command port return the in-service register
+    ReadInService = 0x0B,
 }
 
+/// The line on the master PIC which the slave is chained to
+const CASCADE_IRQ: u8 = 2;
+
 /// Represents an 8295/8295A PIC (superseded by APIC)
 pub struct Pic {
     pub offset: u8,
@@ -34,6 +40,23 @@ impl Pic {
         self.command_port.write(Commands::EndOfInterrupt as u8);
     }
 
+    /// Masks or unmasks the given line of this PIC, from 0 to 7
+    fn set_masked(&self, line: u8, masked: bool) {
+        let mask = self.data_port.read();
+
+        if masked {
+            self.data_port.write(mask | 1 << line);
+        } else {
+            self.data_port.write(mask & !(1 << line));
+        }
+    }
+
+    /// Returns `true` if the given line of this PIC, from 0 to 7, is being serviced
+    fn in_service(&self, line: u8) -> bool {
+        self.command_port.write(Commands::ReadInService as u8);
+        self.command_port.read() & 1 << line != 0
+    }
+
     pub fn initialise(&self) {
         // Tell the PIC to initialise
         self.command_port.write(Commands::Init as u8);
@@ -96,4 +119,41 @@ impl ChainedPics {
         self.inner[0].data_port.write(0xFF);
         self.inner[1].data_port.write(0xFF);
     }
+
+    /// Masks or unmasks the given IRQ, from 0 to 15. Unmasking an IRQ on the slave PIC also
+    /// unmasks the line it is chained to.
+    pub fn set_masked(&self, irq: u8, masked: bool) {
+        if irq < 8 {
+            self.inner[0].set_masked(irq, masked);
+        } else {
+            self.inner[1].set_masked(irq - 8, masked);
+
+            if !masked {
+                self.inner[0].set_masked(CASCADE_IRQ, false);
+            }
+        }
+    }
+
+    /// Returns `true` if the given IRQ is spurious, in which case it must not be acknowledged. A PIC
+    /// raises a spurious IRQ 7 or 15 when an interrupt is withdrawn before it could be delivered.
+    pub fn is_spurious(&self, irq: u8) -> bool {
+        match irq {
+            7 => !self.inner[0].in_service(7),
+            15 if !self.inner[1].in_service(7) => {
+                // The master does not know that the slave's interrupt was spurious
+                self.inner[0].end_of_interrupt();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Acknowledges the given IRQ, so that the PICs can raise further interrupts
+    pub fn end_of_interrupt(&self, irq: u8) {
+        if irq >= 8 {
+            self.inner[1].end_of_interrupt();
+        }
+
+        self.inner[0].end_of_interrupt();
+    }
 }
@@ -1,9 +1,25 @@
 //! Module for interrupt handling/IDT
+//!
+//! Hardware interrupts are dispatched to handlers registered at runtime with `register`. See the
+//! [irq] module.
+//!
+//! Double faults and machine checks are handled on their own stacks from the interrupt stack table,
+//! as they can occur when the interrupted stack is unusable.
 
+use arch;
+use arch::cpuid::{self, Features};
+use arch::gdt;
 use x86_64::structures::idt::Idt;
 
+/// CR4 bit which enables the machine check exception. Without it, a machine check shuts down the
+/// machine.
+const CR4_MACHINE_CHECK_ENABLE: u64 = 1 << 6;
+
 mod legacy_pic;
 mod exceptions;
+mod irq;
+
+pub use self::irq::{register, unregister, IrqError, IrqHandler};
 
 lazy_static! {
     static ref IDT: Idt = {
@@ -14,7 +30,10 @@ lazy_static! {
         idt.bound_range_exceeded.set_handler_fn(exceptions::out_of_bounds);
         idt.invalid_opcode.set_handler_fn(exceptions::invalid_opcode);
         idt.device_not_available.set_handler_fn(exceptions::device_not_available);
-        idt.double_fault.set_handler_fn(exceptions::double_fault);
+        unsafe {
+            idt.double_fault.set_handler_fn(exceptions::double_fault)
+                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        }
         idt.invalid_tss.set_handler_fn(exceptions::invalid_tss);
         idt.segment_not_present.set_handler_fn(exceptions::segment_not_present);
         idt.stack_segment_fault.set_handler_fn(exceptions::stack_segment_fault);
@@ -22,17 +41,36 @@ lazy_static! {
         idt.page_fault.set_handler_fn(exceptions::page_fault);
         idt.x87_floating_point.set_handler_fn(exceptions::x87_floating_point);
         idt.alignment_check.set_handler_fn(exceptions::alignment_check);
-        idt.machine_check.set_handler_fn(exceptions::machine_check);
+        unsafe {
+            idt.machine_check.set_handler_fn(exceptions::machine_check)
+                .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+        }
         idt.simd_floating_point.set_handler_fn(exceptions::simd_floating_point);
         idt.virtualization.set_handler_fn(exceptions::virtualization);
         idt.security_exception.set_handler_fn(exceptions::security_exception);
+        irq::install(&mut idt);
         idt
     };
 }
 
-/// Implicitly invoke the lazy initializer of the IDT & load it, as well as disable PICs and set up
-/// APICs
+/// Implicitly invoke the lazy initializer of the IDT & load it, as well as remap the PICs and
+/// enable interrupts. Every IRQ stays masked until a handler is registered for it.
 pub fn init() {
+    gdt::init();
     IDT.load();
     legacy_pic::CHAINED_PICS.lock().remap_and_disable();
+
+    if cpuid::has(Features::MCE) {
+        enable_machine_check();
+    }
+
+    arch::enable_interrupts();
+}
+
+fn enable_machine_check() {
+    unsafe {
+        let cr4: u64;
+        asm!("mov %cr4, $0" : "=r"(cr4) ::: "volatile");
+        asm!("mov $0, %cr4" :: "r"(cr4 | CR4_MACHINE_CHECK_ENABLE) : "memory" : "volatile");
+    }
 }
@@ -1,11 +1,20 @@
 //! Lang items
 
-use ::halt;
+use arch::Registers;
 use color::{Color, ColorPair};
 use core::fmt::{self, Write};
+use drivers::serial::{self, SerialPort};
+use drivers::speaker;
 use drivers::vga::VgaWriter;
+use monitor;
 use spin::RwLock;
 use terminal::{Stdout, TerminalOutput};
+use unwind::Backtrace;
+
+/// The frequency of the tone played when the kernel panics, in hertz
+const PANIC_BEEP_FREQUENCY: u32 = 220;
+/// The duration of the tone played when the kernel panics, in milliseconds
+const PANIC_BEEP_DURATION: u32 = 300;
 
 #[lang = "eh_personality"]
 #[no_mangle]
@@ -17,6 +26,8 @@ extern fn eh_personality() {}
 #[allow(private_no_mangle_fns)] // publicity is not required, but no mangle is
 // TODO backtrace
 extern fn panic_fmt(args: fmt::Arguments, file: &'static str, line: u32) -> ! {
+    let registers = Registers::capture();
+
     let vga_writer = RwLock::new(VgaWriter::new());
     let mut writer = Stdout(&vga_writer);
 
@@ -24,6 +35,21 @@ extern fn panic_fmt(args: fmt::Arguments, file: &'static str, line: u32) -> ! {
 
     let _ = writer.set_color(ColorPair::new(Color::Red, Color::Black));
     let _ = write!(&mut writer, "Panicked at \"{}\", {file}:{line}\n", args, file = file, line = line);
+    let _ = writer.set_color(ColorPair::new(Color::White, Color::Black));
+
+    // A new port is used, as the panic may have happened while `COM1` was locked. It was set up at
+    // the start of `kmain`, before anything which could panic.
+    let mut serial = unsafe { SerialPort::new(serial::COM1_BASE) };
+    let _ = write!(&mut serial, "panic {}:{} {}\n", file, line, args);
+    let _ = write!(&mut serial, "{}", Backtrace::new(registers.frame));
+
+    // A panic fails the run rather than waiting for input which will never come
+    #[cfg(feature = "integration-test")]
+    {
+        ::qemu::exit(::qemu::ExitCode::Failure);
+    }
+
+    speaker::beep(PANIC_BEEP_FREQUENCY, PANIC_BEEP_DURATION);
 
-    halt()
+    monitor::enter(registers, writer)
 }
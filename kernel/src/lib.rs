@@ -11,6 +11,7 @@
 #![feature(type_ascription)]
 #![feature(ptr_internals)]
 #![feature(abi_x86_interrupt)]
+#![cfg_attr(feature = "integration-test", feature(used))]
 
 extern crate rlibc;
 extern crate volatile;
@@ -23,8 +24,10 @@ extern crate bitflags;
 #[macro_use]
 extern crate lazy_static;
 
-use drivers::keyboard::{Keyboard, KeyEventType, Ps2Keyboard};
+use drivers::keyboard::{Keyboard, KeyEventType, ModifierFlags, Ps2Keyboard};
+use drivers::keyboard::chord::{self, Chord};
 use drivers::keyboard::keymap;
+use drivers::mouse::Ps2Mouse;
 use drivers::ps2;
 use terminal::TerminalOutput;
 
@@ -35,18 +38,39 @@ mod log;
 mod util;
 #[macro_use]
 mod color;
+#[macro_use]
+mod terminal;
 mod io;
+mod arch;
+mod multiboot;
+mod acpi;
+mod bootargs;
+mod memory;
+mod fs;
+mod graphics;
 mod interrupts;
-
+mod power;
+mod rand;
+mod monitor;
+mod symbols;
+mod unwind;
+mod sync;
+mod workqueue;
+mod time;
+
+#[cfg(feature = "integration-test")]
 #[macro_use]
-mod terminal;
+mod integration;
+#[cfg(feature = "integration-test")]
+mod qemu;
 mod drivers;
 
 /// Kernel main function
 #[no_mangle]
-pub extern fn kmain() -> ! {
-    interrupts::init();
-
+pub extern fn kmain(multiboot_info: usize) -> ! {
+    // The console is set up before anything else, so that failures while reading the boot
+    // information or setting up memory are logged rather than silent
+    drivers::serial::COM1.lock().init();
     terminal::STDOUT.write().clear().expect("Screen clear failed");
 
     print_flower().expect("Flower print failed");
@@ -62,17 +86,72 @@ pub extern fn kmain() -> ! {
     terminal::STDOUT.write().set_color(color!(White on Black))
         .expect("Color should be supported");
 
-    let mut controller = ps2::CONTROLLER.lock();
-    match controller.initialize() {
+    let boot_info = unsafe { multiboot::BootInfo::load(multiboot_info) };
+    bootargs::init(&boot_info);
+    log::init();
+
+    interrupts::init();
+
+    arch::cpuid::print_summary();
+    arch::fpu::init();
+    arch::percpu::init_bsp();
+    rand::init();
+
+    memory::init_memory(&boot_info);
+    fs::init(&boot_info);
+    acpi::init(&boot_info);
+    drivers::iommu::init();
+    time::init();
+    graphics::init(&boot_info);
+
+    register_chords();
+
+    #[cfg(feature = "integration-test")]
+    {
+        integration::run(bootargs::get("test"));
+    }
+
+    match ps2::CONTROLLER.lock().initialize() {
         Ok(_) => info!("ps2c: init successful"),
         Err(error) => error!("ps2c: {:?}", error),
     }
 
-    let keyboard_device = controller.device(ps2::DevicePort::Keyboard);
-    let mut keyboard = Ps2Keyboard::new(keyboard_device);
+    // Only the keyboard's own lock is held while it is in use, not the controller's
+    let mut keyboard_device = ps2::device(ps2::DevicePort::Keyboard).lock();
+    if keyboard_device.state == ps2::DeviceState::Unavailable {
+        warn!("kbd: no ps/2 keyboard available");
+        idle()
+    }
+
+    // The mouse is optional, and its packets are received by its interrupt handler once enabled
+    let mut mouse_device = ps2::device(ps2::DevicePort::Mouse).lock();
+    let mut mouse = if mouse_device.state != ps2::DeviceState::Unavailable {
+        let mut mouse = Ps2Mouse::new(&mut mouse_device);
+        match mouse.enable() {
+            Ok(_) => {
+                info!("mouse: successfully enabled");
+                Some(mouse)
+            }
+            Err(error) => {
+                warn!("mouse: enable unsuccessful: {:?}", error);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut keyboard = Ps2Keyboard::new(&mut keyboard_device);
     if let Ok(_) = keyboard.enable() {
         info!("kbd: successfully enabled");
         loop {
+            workqueue::run_pending();
+
+            if let Some(ref mut mouse) = mouse {
+                // Nothing consumes mouse events yet, but they are drained so that packets are not dropped
+                let _ = mouse.read_event();
+            }
+
             if let Ok(Some(event)) = keyboard.read_event() {
                 if event.event_type != KeyEventType::Break {
                     if event.keycode == keymap::codes::BACKSPACE {
@@ -88,7 +167,46 @@ pub extern fn kmain() -> ! {
         error!("kbd: enable unsuccessful");
     }
 
-    halt()
+    idle()
+}
+
+/// Registers the kernel's global key chords
+fn register_chords() {
+    let mut chords = chord::CHORDS.lock();
+
+    for &delete in [keymap::codes::DELETE, keymap::codes::NUM_PAD_DELETE].iter() {
+        let reboot = Chord::new(ModifierFlags::CTRL | ModifierFlags::ALT, delete);
+        if let Err(error) = chords.register(reboot, |_| power::reboot()) {
+            warn!("kbd: failed to register reboot chord: {:?}", error);
+        }
+    }
+
+    let shutdown = Chord::new(ModifierFlags::CTRL | ModifierFlags::ALT, keymap::codes::END);
+    if let Err(error) = chords.register(shutdown, |_| power::shutdown()) {
+        warn!("kbd: failed to register shutdown chord: {:?}", error);
+    }
+
+    let split = Chord::new(ModifierFlags::CTRL | ModifierFlags::ALT, keymap::codes::L);
+    if let Err(error) = chords.register(split, |_| terminal::toggle_split()) {
+        warn!("kbd: failed to register split chord: {:?}", error);
+    }
+
+    #[cfg(feature = "lock-stats")]
+    {
+        let stats = Chord::new(ModifierFlags::CTRL | ModifierFlags::ALT, keymap::codes::S);
+        if let Err(error) = chords.register(stats, |_| report_locks()) {
+            warn!("kbd: failed to register lock statistics chord: {:?}", error);
+        }
+    }
+}
+
+/// Logs the statistics of the kernel's most used locks
+#[cfg(feature = "lock-stats")]
+fn report_locks() {
+    sync::report("ps2 controller", ps2::CONTROLLER.stats());
+    sync::report("ps2 keyboard", ps2::device(ps2::DevicePort::Keyboard).stats());
+    sync::report("log buffer", log::LOG_BUFFER.stats());
+    sync::report("workqueue", workqueue::lock_stats());
 }
 
 fn print_flower() -> Result<(), terminal::TerminalOutputError<()>> {
@@ -103,6 +221,22 @@ fn print_flower() -> Result<(), terminal::TerminalOutputError<()>> {
     stdout.set_cursor_pos(old)
 }
 
+/// Runs deferred work forever, idling the CPU until an interrupt when there is none
+fn idle() -> ! {
+    loop {
+        workqueue::run_pending();
+
+        // Interrupts are disabled while checking for work, so that work queued by an interrupt
+        // cannot arrive between the check and going idle
+        arch::disable_interrupts();
+        if workqueue::has_pending() {
+            arch::enable_interrupts();
+        } else {
+            arch::idle::wait();
+        }
+    }
+}
+
 fn halt() -> ! {
     unsafe {
         // Disable interrupts
@@ -1,11 +1,30 @@
+//! # Logging
+//!
+//! The logging macros print messages to the terminal, prefixed with the time since boot and their
+//! level, which is colored by severity. The `debug` and
+//! `trace` levels are only compiled in with their respective features. The maximum level printed
+//! can be lowered at boot with the `loglevel` boot argument, e.g. `loglevel=warn`. Errors are always
+//! printed. If the screen is split with `terminal::set_split`, messages are printed to the log pane.
+//!
+//! Printed messages are also recorded in `LOG_BUFFER`, a ring buffer holding the most recent
+//! `LOG_BUFFER_SIZE` bytes of log output, so that they can be inspected after a panic.
+//!
+//! Messages are also written to the `COM1` serial port, one per line, in a format meant to be parsed
+//! on the host: the time since boot in seconds with nanosecond precision, the level's name, the
+//! module the message was logged from, and the message, separated by spaces, e.g.
+//! `12.000312500 info flower_kernel::memory mem: 130048 KiB available`.
+//!
+//! None of the built in sinks allocate, so logging can be used from the start of `kmain`, before
+//! memory is set up. The serial port is set up first, so that failures while reading the boot
+//! information are visible on the host, and panics are also written to it.
+//!
+//! Each message is passed as a [Record] to every registered [Sink]. The terminal, the log buffer and
+//! the serial port are sinks, and more can be added with `add_sink`, such as to send logs over the
+//! network.
+
 macro_rules! error {
     ($thing:expr, $($extra:tt)*) => {
-        {
-            use terminal::TerminalOutput;
-            ::terminal::STDOUT.write().write_string_colored("[error] ", color!(Red on Black))
-                .expect("Error logging");
-            println!($thing, $($extra)*);
-        }
+        ::log::log(::log::Level::Error, module_path!(), format_args!($thing, $($extra)*))
     };
 
     ($thing:expr) => {
@@ -16,11 +35,8 @@ macro_rules! error {
 
 macro_rules! warn {
     ($thing:expr, $($extra:tt)*) => {
-        {
-            use terminal::TerminalOutput;
-            ::terminal::STDOUT.write().write_string_colored("[warn]  ", color!(LightRed on Black))
-                .expect("Error logging");
-            println!($thing, $($extra)*);
+        if ::log::enabled(::log::Level::Warn) {
+            ::log::log(::log::Level::Warn, module_path!(), format_args!($thing, $($extra)*));
         }
     };
 
@@ -31,11 +47,8 @@ macro_rules! warn {
 
 macro_rules! info {
     ($thing:expr, $($extra:tt)*) => {
-        {
-            use terminal::TerminalOutput;
-            ::terminal::STDOUT.write().write_string_colored("[info]  ", color!(LightBlue on Black))
-                .expect("Error logging");
-            println!($thing, $($extra)*);
+        if ::log::enabled(::log::Level::Info) {
+            ::log::log(::log::Level::Info, module_path!(), format_args!($thing, $($extra)*));
         }
     };
 
@@ -48,10 +61,9 @@ macro_rules! debug {
     ($thing:expr, $($extra:tt)*) => {
         #[cfg(feature = "debug")]
         {
-            use terminal::TerminalOutput;
-            ::terminal::STDOUT.write().write_string_colored("[debug] ", color!(Cyan on Black))
-                .expect("Error logging");
-            println!($thing, $($extra)*);
+            if ::log::enabled(::log::Level::Debug) {
+                ::log::log(::log::Level::Debug, module_path!(), format_args!($thing, $($extra)*));
+            }
         }
     };
 
@@ -64,10 +76,9 @@ macro_rules! trace {
     ($thing:expr, $($extra:tt)*) => {
         #[cfg(feature = "trace")]
         {
-            use terminal::TerminalOutput;
-            ::terminal::STDOUT.write().write_string_colored("[trace] ", color!(White on Black))
-                .expect("Error logging");
-            println!($thing, $($extra)*);
+            if ::log::enabled(::log::Level::Trace) {
+                ::log::log(::log::Level::Trace, module_path!(), format_args!($thing, $($extra)*));
+            }
         }
     };
 
@@ -75,3 +86,277 @@ macro_rules! trace {
         trace!($thing,)
     }
 }
+
+use bootargs;
+use color::{Color, ColorPair};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use drivers::serial;
+use spin::RwLock;
+use sync::IrqLock;
+use terminal;
+use time::{self, Clock};
+
+/// The size of the log ring buffer in bytes
+pub const LOG_BUFFER_SIZE: usize = 4096;
+
+/// The maximum amount of sinks which can be registered
+pub const MAX_SINKS: usize = 8;
+
+/// The most recent log output
+pub static LOG_BUFFER: IrqLock<LogBuffer> = IrqLock::new(LogBuffer::new());
+
+static TERMINAL_SINK: TerminalSink = TerminalSink;
+static BUFFER_SINK: BufferSink = BufferSink;
+static SERIAL_SINK: SerialSink = SerialSink;
+
+/// The sinks each record is written to
+static SINKS: RwLock<[Option<&'static Sink>; MAX_SINKS]> = RwLock::new([
+    Some(&TERMINAL_SINK as &Sink), Some(&BUFFER_SINK as &Sink), Some(&SERIAL_SINK as &Sink),
+    None, None, None, None, None,
+]);
+
+/// The severity of a log message, in increasing order of verbosity
+#[allow(dead_code)] // Part of API
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl Level {
+    /// The lowercase name of this level
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    /// The prefix printed before messages of this level on the terminal
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            Level::Error => "[error] ",
+            Level::Warn => "[warn]  ",
+            Level::Info => "[info]  ",
+            Level::Debug => "[debug] ",
+            Level::Trace => "[trace] ",
+        }
+    }
+
+    /// The color the prefix of this level is printed in
+    pub fn color(&self) -> ColorPair {
+        match *self {
+            Level::Error => ColorPair::new(Color::Red, Color::Black),
+            Level::Warn => ColorPair::new(Color::LightRed, Color::Black),
+            Level::Info => ColorPair::new(Color::LightBlue, Color::Black),
+            Level::Debug => ColorPair::new(Color::Cyan, Color::Black),
+            Level::Trace => ColorPair::new(Color::White, Color::Black),
+        }
+    }
+
+    /// Parses a level from its lowercase name
+    fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// The most verbose level which is printed
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(Level::Trace as usize);
+
+/// Sets the maximum log level from the `loglevel` boot argument, if it was passed
+pub fn init() {
+    if let Some(name) = bootargs::get("loglevel") {
+        match Level::from_name(name) {
+            Some(level) => set_max_level(level),
+            None => warn!("log: unknown level \"{}\"", name),
+        }
+    }
+}
+
+/// Sets the most verbose level which is printed
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Returns `true` if messages of the given level should be printed
+pub fn enabled(level: Level) -> bool {
+    level as usize <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// A time since boot in nanoseconds, displayed in seconds with microsecond precision
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Timestamp(pub u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:5}.{:06}] ", self.0 / time::NANOSECONDS_PER_SECOND, self.0 % time::NANOSECONDS_PER_SECOND / 1000)
+    }
+}
+
+/// A log message, as passed to each sink
+pub struct Record<'a> {
+    pub level: Level,
+    /// The path of the module the message was logged from
+    pub target: &'static str,
+    pub args: fmt::Arguments<'a>,
+    pub timestamp: Timestamp,
+}
+
+/// Something log records are written to, such as the terminal
+pub trait Sink: Sync {
+    /// Writes the given record. Records should be dropped rather than waiting if the sink is in use,
+    /// as logging may happen anywhere, including from a panic.
+    fn write(&self, record: &Record);
+}
+
+/// An error returned when adding a sink
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SinkError {
+    /// `MAX_SINKS` sinks are already registered
+    TooManySinks,
+}
+
+/// Registers a sink, which every following record is written to
+#[allow(dead_code)] // Part of API
+pub fn add_sink(sink: &'static Sink) -> Result<(), SinkError> {
+    let mut sinks = SINKS.write();
+    let slot = sinks.iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(SinkError::TooManySinks)?;
+
+    *slot = Some(sink);
+    Ok(())
+}
+
+/// Unregisters the given sink. Returns `true` if it was registered.
+#[allow(dead_code)] // Part of API
+pub fn remove_sink(sink: &'static Sink) -> bool {
+    let mut sinks = SINKS.write();
+    let slot = sinks.iter_mut()
+        .find(|slot| slot.map_or(false, |registered| same_sink(registered, sink)));
+
+    match slot {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+fn same_sink(a: &Sink, b: &Sink) -> bool {
+    a as *const Sink as *const u8 == b as *const Sink as *const u8
+}
+
+/// Writes a message of the given level to every sink, for log macro use. If the sinks are being
+/// changed, such as when logging from a panic which occurred while adding one, the message is dropped.
+pub fn log(level: Level, target: &'static str, args: fmt::Arguments) {
+    let record = Record {
+        level,
+        target,
+        args,
+        timestamp: Timestamp(time::now(Clock::Monotonic)),
+    };
+
+    if let Some(sinks) = SINKS.try_read() {
+        for sink in sinks.iter().filter_map(|sink| *sink) {
+            sink.write(&record);
+        }
+    }
+}
+
+/// Prints records to the terminal
+struct TerminalSink;
+
+impl Sink for TerminalSink {
+    fn write(&self, record: &Record) {
+        terminal::log_print(record);
+    }
+}
+
+/// Records printed messages in `LOG_BUFFER`. If the buffer is in use, such as when logging from a
+/// panic which occurred while recording, the message is dropped.
+struct BufferSink;
+
+impl Sink for BufferSink {
+    fn write(&self, record: &Record) {
+        if let Some(mut buffer) = LOG_BUFFER.try_lock() {
+            let _ = write!(buffer, "{}{}{}\n", record.timestamp, record.level.prefix(), record.args);
+        }
+    }
+}
+
+/// Writes records to `COM1` in the machine readable format. If the port is in use, the message is
+/// dropped.
+struct SerialSink;
+
+impl Sink for SerialSink {
+    fn write(&self, record: &Record) {
+        if let Some(mut com1) = serial::COM1.try_lock() {
+            let seconds = record.timestamp.0 / time::NANOSECONDS_PER_SECOND;
+            let nanoseconds = record.timestamp.0 % time::NANOSECONDS_PER_SECOND;
+            let _ = write!(com1, "{}.{:09} {} {} {}\n", seconds, nanoseconds, record.level.name(), record.target, record.args);
+        }
+    }
+}
+
+/// A ring buffer of log output, which overwrites the oldest output when full
+pub struct LogBuffer {
+    bytes: [u8; LOG_BUFFER_SIZE],
+    /// The index the next byte will be written to
+    end: usize,
+    /// If the buffer has wrapped around, so that every byte is valid
+    full: bool,
+}
+
+impl LogBuffer {
+    const fn new() -> Self {
+        LogBuffer {
+            bytes: [0; LOG_BUFFER_SIZE],
+            end: 0,
+            full: false,
+        }
+    }
+
+    /// Gets the contents of the buffer as two slices, the first holding the oldest output
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        if self.full {
+            (&self.bytes[self.end..], &self.bytes[..self.end])
+        } else {
+            (&self.bytes[..self.end], &[])
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.bytes[self.end] = byte;
+        self.end = (self.end + 1) % LOG_BUFFER_SIZE;
+
+        if self.end == 0 {
+            self.full = true;
+        }
+    }
+}
+
+impl fmt::Write for LogBuffer {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for byte in string.bytes() {
+            self.push(byte);
+        }
+
+        Ok(())
+    }
+}
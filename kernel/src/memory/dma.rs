@@ -0,0 +1,212 @@
+//! # DMA
+//!
+//! Allocates buffers for devices to access through direct memory access. A DMA buffer is physically
+//! contiguous, so that a device can be given a single physical address for it, and is mapped
+//! uncached, so that the device and the CPU always see the same data. Its frames are also made
+//! uncached in the physical memory mapping, as mapping memory as both cached and uncached is
+//! undefined.
+//!
+//! Some devices can only address the first 4GiB of physical memory, and some require their buffers
+//! to be aligned to more than a page, which `alloc_contiguous` can be asked for.
+//!
+//! # Note
+//!
+//! The frame allocator cannot reuse frames, so DMA buffers are never freed. Drivers should allocate
+//! their buffers once, when the device is initialized.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let mut buffer = dma::alloc_contiguous(4096, 1024, true)?;
+//! buffer.as_mut_slice()[0] = 0xF1;
+//! device.set_buffer_address(buffer.physical_address());
+//! ```
+
+use core::slice;
+use memory;
+use spin::Mutex;
+use super::{PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use super::frame::Frame;
+use super::paging::{self, EntryFlags, MapError, Page};
+
+/// The virtual address DMA buffers are mapped from
+const DMA_START: VirtualAddress = 0xFFFF_FE00_0000_0000;
+/// The size of the virtual address range DMA buffers are mapped within
+const DMA_SIZE: usize = 0x40_0000_0000;
+
+/// The end of the first 4GiB of physical memory
+const LIMIT_4GIB: PhysicalAddress = 0x1_0000_0000;
+
+/// The size of a cache line flushed by `clflush`
+const CACHE_LINE_SIZE: usize = 64;
+
+/// The virtual address the next DMA buffer is mapped at
+static NEXT_ADDRESS: Mutex<VirtualAddress> = Mutex::new(DMA_START);
+
+/// An error returned when allocating a DMA buffer
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DmaError {
+    /// The length is zero
+    EmptyBuffer,
+    /// The alignment is not a power of two
+    InvalidAlignment,
+    /// The frame allocator is not initialized
+    NoFrameAllocator,
+    /// No physically contiguous range of the requested size is available
+    OutOfFrames,
+    /// The virtual address range for DMA buffers has been used up
+    OutOfAddressSpace,
+    Map(MapError),
+}
+
+impl From<MapError> for DmaError {
+    fn from(error: MapError) -> Self {
+        DmaError::Map(error)
+    }
+}
+
+/// A physically contiguous buffer which can be accessed by devices
+#[derive(Debug)]
+pub struct DmaBuffer {
+    virtual_address: VirtualAddress,
+    physical_address: PhysicalAddress,
+    len: usize,
+}
+
+// Safe because the buffer is only accessed through its owner, which owns its mapping
+unsafe impl Send for DmaBuffer {}
+
+impl DmaBuffer {
+    /// The physical address of the start of this buffer, to be given to the device
+    pub fn physical_address(&self) -> PhysicalAddress {
+        self.physical_address
+    }
+
+    /// The virtual address of the start of this buffer
+    #[allow(dead_code)] // Part of API
+    pub fn virtual_address(&self) -> VirtualAddress {
+        self.virtual_address
+    }
+
+    /// The length of this buffer in bytes
+    #[allow(dead_code)] // Part of API
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)] // Part of API
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.virtual_address as *const u8, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.virtual_address as *mut u8, self.len) }
+    }
+}
+
+/// Allocates a zeroed, physically contiguous buffer of at least `len` bytes, whose physical address is
+/// aligned to `align` bytes. If `below_4g` is set, the buffer lies entirely below 4GiB.
+pub fn alloc_contiguous(len: usize, align: usize, below_4g: bool) -> Result<DmaBuffer, DmaError> {
+    if len == 0 {
+        return Err(DmaError::EmptyBuffer);
+    }
+
+    if !align.is_power_of_two() {
+        return Err(DmaError::InvalidAlignment);
+    }
+
+    let size = (len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let align_frames = if align > PAGE_SIZE { align / PAGE_SIZE } else { 1 };
+    let limit = if below_4g { LIMIT_4GIB } else { usize::max_value() };
+
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = super::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or(DmaError::NoFrameAllocator)?;
+
+    let mut next_address = NEXT_ADDRESS.lock();
+    if *next_address + size > DMA_START + DMA_SIZE {
+        return Err(DmaError::OutOfAddressSpace);
+    }
+
+    let first = allocator.allocate_contiguous(size / PAGE_SIZE, align_frames, limit)
+        .ok_or(DmaError::OutOfFrames)?;
+
+    // The buffer is shared with a device, so it must not be cached
+    let flags = EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE | EntryFlags::NO_CACHE;
+    let start = *next_address;
+
+    let physical_map_address = memory::phys_to_virt(first.start_address());
+
+    let mut mapped = 0;
+    let mut result = Ok(());
+    while mapped < size {
+        let page = Page::containing_address(start + mapped);
+        let frame = Frame::containing_address(first.start_address() + mapped);
+
+        // Safe because the frames were just allocated, so are not mapped anywhere else
+        result = unsafe { table.map_to(page, frame, flags, allocator) };
+        if result.is_err() {
+            break;
+        }
+
+        mapped += PAGE_SIZE;
+    }
+
+    // The physical memory mapping of the frames must have the same memory type. Lines cached
+    // through it before are then written back and evicted.
+    if result.is_ok() {
+        result = table.set_flags(physical_map_address, size, flags, allocator);
+    }
+
+    if let Err(error) = result {
+        // The addresses are reused by the next buffer, so must not stay mapped
+        let mut unmapped = 0;
+        while unmapped < mapped {
+            let _ = unsafe { table.unmap(Page::containing_address(start + unmapped), allocator) };
+            unmapped += PAGE_SIZE;
+        }
+
+        // The frames are leaked, but must go back to the flags of the rest of the physical memory mapping
+        let physical_map_flags = EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE;
+        let _ = table.set_flags(physical_map_address, size, physical_map_flags, allocator);
+        return Err(DmaError::Map(error));
+    }
+
+    let mut line = physical_map_address;
+    while line < physical_map_address + size {
+        unsafe { asm!("clflush ($0)" :: "r"(line) : "memory" : "volatile"); }
+        line += CACHE_LINE_SIZE;
+    }
+
+    *next_address += size;
+
+    let mut buffer = DmaBuffer {
+        virtual_address: start,
+        physical_address: first.start_address(),
+        len,
+    };
+
+    // Newly allocated frames are not zeroed
+    for byte in buffer.as_mut_slice().iter_mut() {
+        *byte = 0;
+    }
+
+    Ok(buffer)
+}
+
+kernel_test!(fn allocates_aligned_buffers() {
+    let buffer = alloc_contiguous(3 * PAGE_SIZE, 0x1_0000, true).map_err(|_| "allocation failed")?;
+    test_assert_eq!(buffer.physical_address() % 0x1_0000, 0);
+    test_assert!(buffer.physical_address() + buffer.len() <= LIMIT_4GIB);
+
+    let table = paging::ACTIVE_TABLE.lock();
+    for offset in [0, PAGE_SIZE, 2 * PAGE_SIZE].iter() {
+        let translated = table.translate(buffer.virtual_address() + offset);
+        test_assert_eq!(translated, Some(buffer.physical_address() + offset));
+
+        // The physical memory mapping must not alias the buffer as cached
+        let physical_map_address = memory::phys_to_virt(buffer.physical_address() + offset);
+        let (_, flags) = table.translate_with_flags(physical_map_address).ok_or("buffer not in physical map")?;
+        test_assert!(flags.contains(EntryFlags::NO_CACHE));
+    }
+});
@@ -0,0 +1,183 @@
+//! # Frames
+//!
+//! A frame is a 4KiB region of physical memory. Frames are handed out by a [FrameAllocator], which
+//! is used by the paging API to allocate page tables and back mappings.
+
+use core::cmp;
+use multiboot::{MemoryArea, MemoryAreaIter};
+use memory::{self, PhysicalAddress, PAGE_SIZE};
+
+/// Frames below this address are never allocated, as they contain firmware structures, and frame
+/// zero cannot be safely dereferenced
+const LOW_MEMORY_END: PhysicalAddress = 0x10_0000;
+
+/// The maximum amount of ranges which can be reserved in a [BumpFrameAllocator]
+pub const MAX_RESERVED: usize = 16;
+
+/// Represents a 4KiB physical frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Frame {
+    number: usize,
+}
+
+impl Frame {
+    /// Gets the frame containing the given physical address
+    pub fn containing_address(address: PhysicalAddress) -> Frame {
+        Frame { number: address / PAGE_SIZE }
+    }
+
+    /// The physical address of the start of this frame
+    pub fn start_address(&self) -> PhysicalAddress {
+        self.number * PAGE_SIZE
+    }
+
+    /// Gets the frame directly after this one
+    fn next(&self) -> Frame {
+        Frame { number: self.number + 1 }
+    }
+}
+
+/// Interface to something which can allocate physical frames
+pub trait FrameAllocator {
+    /// Allocates a single frame, or returns `None` if no frames are available
+    fn allocate_frame(&mut self) -> Option<Frame>;
+
+    /// Returns a frame to this allocator
+    fn deallocate_frame(&mut self, frame: Frame);
+}
+
+/// A simple frame allocator which hands out available frames in increasing order, skipping
+/// reserved ranges such as the kernel, boot information and boot modules.
+///
+/// # Note
+///
+/// Frames are only allocated from memory within the physical memory mapping, so that page tables
+/// can be accessed through it. Freed frames are not reused.
+pub struct BumpFrameAllocator {
+    next_free: Frame,
+    current_area: Option<&'static MemoryArea>,
+    areas: MemoryAreaIter,
+    /// The first and last frames of each reserved range
+    reserved: [Option<(Frame, Frame)>; MAX_RESERVED],
+}
+
+impl BumpFrameAllocator {
+    /// Creates a new allocator over the given memory areas. Ranges which must not be allocated from
+    /// should be reserved with `reserve` before any frames are allocated.
+    pub fn new(areas: MemoryAreaIter) -> Self {
+        let mut allocator = BumpFrameAllocator {
+            next_free: Frame::containing_address(LOW_MEMORY_END),
+            current_area: None,
+            areas,
+            reserved: [None; MAX_RESERVED],
+        };
+
+        allocator.choose_next_area();
+        allocator
+    }
+
+    /// Reserves the given physical range, from its start to its end (exclusive), so that it is never
+    /// allocated
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MAX_RESERVED` ranges have already been reserved
+    pub fn reserve(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
+        if start >= end {
+            return;
+        }
+
+        let range = (Frame::containing_address(start), Frame::containing_address(end - 1));
+        let slot = self.reserved.iter_mut()
+            .find(|slot| slot.is_none())
+            .expect("Too many reserved ranges");
+
+        *slot = Some(range);
+    }
+
+    /// Allocates `count` physically contiguous frames, the first of which is aligned to `align` frames,
+    /// and all of which end before the given address. Returns the first frame, or `None` if no such
+    /// range is available.
+    ///
+    /// Frames skipped to align the range are not allocated later.
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize, limit: PhysicalAddress) -> Option<Frame> {
+        debug_assert!(count > 0 && align.is_power_of_two());
+
+        loop {
+            let area = self.current_area?;
+            let first = Frame { number: (self.next_free.number + align - 1) & !(align - 1) };
+            let last = Frame { number: first.number + count - 1 };
+            let area_end = cmp::min(area.end_address(), memory::physical_map_end());
+            let area_last = Frame::containing_address(area_end - 1);
+
+            if last.start_address() + PAGE_SIZE > limit {
+                // Areas are chosen in increasing order, so no later range can be below the limit
+                return None;
+            } else if last > area_last {
+                // The range does not fit in the rest of the current area, so move onto the next
+                self.next_free = area_last.next();
+                self.choose_next_area();
+            } else if let Some((_, reserved_last)) = self.reserved_overlapping(first, last) {
+                self.next_free = reserved_last.next();
+            } else {
+                self.next_free = last.next();
+                return Some(first);
+            }
+        }
+    }
+
+    /// Gets the reserved range containing the given frame
+    fn reserved_range(&self, frame: Frame) -> Option<(Frame, Frame)> {
+        self.reserved_overlapping(frame, frame)
+    }
+
+    /// Gets a reserved range overlapping the frames from `first` to `last`
+    fn reserved_overlapping(&self, first: Frame, last: Frame) -> Option<(Frame, Frame)> {
+        self.reserved.iter()
+            .filter_map(|range| *range)
+            .find(|&(reserved_first, reserved_last)| first <= reserved_last && last >= reserved_first)
+    }
+
+    /// Chooses the lowest available area which still has free frames
+    fn choose_next_area(&mut self) {
+        let next_free = self.next_free;
+
+        // Only the part of an area within the physical memory map can be allocated from
+        let physical_map_end = memory::physical_map_end();
+
+        self.current_area = self.areas.clone()
+            .filter(|area| area.is_available() && area.start_address() < physical_map_end)
+            .filter(|area| Frame::containing_address(cmp::min(area.end_address(), physical_map_end) - 1) >= next_free)
+            .min_by_key(|area| area.start_address());
+
+        if let Some(area) = self.current_area {
+            let start = Frame::containing_address(area.start_address());
+            self.next_free = cmp::max(self.next_free, start);
+        }
+    }
+}
+
+impl FrameAllocator for BumpFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<Frame> {
+        loop {
+            let area = self.current_area?;
+            let frame = self.next_free;
+            let area_end = cmp::min(area.end_address(), memory::physical_map_end());
+            let last_frame = Frame::containing_address(area_end - 1);
+
+            if frame > last_frame {
+                // The current area has been used up, so move onto the next
+                self.choose_next_area();
+            } else if let Some((_, last)) = self.reserved_range(frame) {
+                self.next_free = last.next();
+            } else {
+                self.next_free = frame.next();
+                return Some(frame);
+            }
+        }
+    }
+
+    fn deallocate_frame(&mut self, _frame: Frame) {
+        // TODO: frames are leaked until a reusing allocator is implemented
+    }
+}
@@ -0,0 +1,225 @@
+//! # MMIO
+//!
+//! Maps the memory of devices, and accesses their registers. `map` claims a physical range as an
+//! MMIO zone for its driver and maps it uncached, returning an [MmioRegion]. Registers can then be
+//! read and written at an offset into the region, or through a struct of [Register]s laid out like
+//! the device's registers.
+//!
+//! Every access is volatile, so that it is never removed or merged. Writes are preceded by a fence,
+//! so that memory written before them, such as a DMA buffer, is visible to the device when it sees
+//! the write, and reads are followed by one, so that memory is not read before a status register
+//! says it is ready.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! #[repr(C)]
+//! struct Registers {
+//!     status: Register<u32>,
+//!     command: Register<u32>,
+//! }
+//!
+//! let region = mmio::map(bar_address, 0x1000, "nic")?;
+//! let registers: &Registers = region.registers();
+//! registers.command.write(1);
+//! while registers.status.read() & 1 == 0 {}
+//! ```
+
+use core::cell::UnsafeCell;
+use core::mem;
+use core::sync::atomic::{self, Ordering};
+use spin::Mutex;
+use super::{PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use super::frame::Frame;
+use super::paging::{self, EntryFlags, MapError, Page};
+use super::zone::{self, ZoneError, ZoneType};
+
+/// The virtual address MMIO regions are mapped from
+const MMIO_START: VirtualAddress = 0xFFFF_FD00_0000_0000;
+/// The size of the virtual address range MMIO regions are mapped within
+const MMIO_SIZE: usize = 0x40_0000_0000;
+
+/// The virtual address the next MMIO region is mapped at
+static NEXT_ADDRESS: Mutex<VirtualAddress> = Mutex::new(MMIO_START);
+
+/// An error returned when mapping an MMIO region
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MmioError {
+    /// The range could not be reserved or claimed
+    Zone(ZoneError),
+    /// The frame allocator is not initialized
+    NoFrameAllocator,
+    /// The virtual address range for MMIO regions has been used up
+    OutOfAddressSpace,
+    Map(MapError),
+}
+
+impl From<ZoneError> for MmioError {
+    fn from(error: ZoneError) -> Self {
+        MmioError::Zone(error)
+    }
+}
+
+impl From<MapError> for MmioError {
+    fn from(error: MapError) -> Self {
+        MmioError::Map(error)
+    }
+}
+
+/// A device register of type `T`, to be used within a `#[repr(C)]` struct of registers
+#[repr(C)]
+pub struct Register<T: Copy> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Copy> Register<T> {
+    pub fn read(&self) -> T {
+        let value = unsafe { self.value.get().read_volatile() };
+        atomic::fence(Ordering::SeqCst);
+        value
+    }
+
+    pub fn write(&self, value: T) {
+        atomic::fence(Ordering::SeqCst);
+        unsafe { self.value.get().write_volatile(value) }
+    }
+
+    /// Reads the register, and writes back the value returned by the given function
+    #[allow(dead_code)] // Part of API
+    pub fn update<F>(&self, f: F)
+        where F: FnOnce(T) -> T
+    {
+        let value = self.read();
+        self.write(f(value));
+    }
+}
+
+/// A mapped range of device memory
+#[derive(Debug)]
+pub struct MmioRegion {
+    virtual_address: VirtualAddress,
+    physical_address: PhysicalAddress,
+    size: usize,
+}
+
+// Safe because the region is only accessed through its owner, which owns its mapping
+unsafe impl Send for MmioRegion {}
+// Safe because registers are only accessed volatilely
+unsafe impl Sync for MmioRegion {}
+
+impl MmioRegion {
+    /// The physical address of the start of this region
+    #[allow(dead_code)] // Part of API
+    pub fn physical_address(&self) -> PhysicalAddress {
+        self.physical_address
+    }
+
+    /// The size of this region in bytes
+    #[allow(dead_code)] // Part of API
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// A pointer to the start of this region, for bulk accesses such as to a framebuffer
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.virtual_address as *mut u8
+    }
+
+    /// Gets the registers at the start of this region
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is larger than this region
+    #[allow(dead_code)] // Part of API
+    pub fn registers<T>(&self) -> &T {
+        assert!(mem::size_of::<T>() <= self.size, "Registers larger than MMIO region");
+        unsafe { &*(self.virtual_address as *const T) }
+    }
+
+    /// Reads the register at the given offset into this region
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is not within this region
+    #[allow(dead_code)] // Part of API
+    pub fn read<T: Copy>(&self, offset: usize) -> T {
+        self.register::<T>(offset).read()
+    }
+
+    /// Writes the register at the given offset into this region
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is not within this region
+    #[allow(dead_code)] // Part of API
+    pub fn write<T: Copy>(&self, offset: usize, value: T) {
+        self.register::<T>(offset).write(value)
+    }
+
+    fn register<T: Copy>(&self, offset: usize) -> &Register<T> {
+        assert!(offset + mem::size_of::<T>() <= self.size, "Register {:#x} outside of MMIO region", offset);
+        unsafe { &*((self.virtual_address + offset) as *const Register<T>) }
+    }
+}
+
+/// Claims the given physical range of device memory for the given owner, and maps it uncached. The
+/// range is reserved as an MMIO zone if it is not already, and released if it cannot be mapped.
+pub fn map(physical_address: PhysicalAddress, size: usize, owner: &'static str) -> Result<MmioRegion, MmioError> {
+    let end = physical_address + size;
+    zone::reserve(physical_address, end, ZoneType::Mmio)?;
+    zone::claim(physical_address, end, ZoneType::Mmio, owner)?;
+
+    let result = map_claimed(physical_address, size);
+    if result.is_err() {
+        zone::release(physical_address, owner);
+    }
+
+    result
+}
+
+/// Maps the given claimed range. Nothing is left mapped if this fails.
+fn map_claimed(physical_address: PhysicalAddress, size: usize) -> Result<MmioRegion, MmioError> {
+    let offset = physical_address % PAGE_SIZE;
+    let mapped_size = (offset + size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let mut allocator = super::FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or(MmioError::NoFrameAllocator)?;
+
+    let mut next_address = NEXT_ADDRESS.lock();
+    if *next_address + mapped_size > MMIO_START + MMIO_SIZE {
+        return Err(MmioError::OutOfAddressSpace);
+    }
+
+    // Device memory must not be cached
+    let flags = EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE | EntryFlags::NO_CACHE;
+    let start = *next_address;
+
+    let mut mapped = 0;
+    while mapped < mapped_size {
+        let page = Page::containing_address(start + mapped);
+        let frame = Frame::containing_address(physical_address - offset + mapped);
+
+        // Safe because the range was claimed, so is not mapped by another driver
+        if let Err(error) = unsafe { table.map_to(page, frame, flags, allocator) } {
+            // The addresses are reused by the next region, so must not stay mapped
+            let mut unmapped = 0;
+            while unmapped < mapped {
+                let _ = unsafe { table.unmap(Page::containing_address(start + unmapped), allocator) };
+                unmapped += PAGE_SIZE;
+            }
+
+            return Err(MmioError::Map(error));
+        }
+
+        mapped += PAGE_SIZE;
+    }
+
+    *next_address += mapped_size;
+
+    Ok(MmioRegion {
+        virtual_address: start + offset,
+        physical_address,
+        size,
+    })
+}
@@ -0,0 +1,233 @@
+//! # Memory
+//!
+//! Handles allocation of physical frames and mapping of virtual memory. `init_memory` must be
+//! called with the multiboot information before either are used.
+//!
+//! The kernel is linked in the higher half, at `KERNEL_OFFSET`. During boot, the first 1GiB of
+//! physical memory is mapped there, and also at `PHYSICAL_MEMORY_OFFSET`, through which page
+//! tables, the boot information and devices are accessed. `init_memory` extends the latter mapping
+//! with huge pages to cover all available memory. Physical addresses can be converted to addresses
+//! in this mapping with `phys_to_virt`.
+//!
+//! Once the frame allocator is set up, the kernel is remapped so that writable memory is never
+//! executable (W^X): `.text` is read-only and executable, `.rodata` is read-only and no-execute,
+//! and `.data`, `.bss` and the physical memory mapping are writable and no-execute.
+//!
+//! Memory which is not available for use, such as ACPI tables and device memory, is tracked as
+//! typed zones by the `zone` module, which drivers reserve and claim their device memory through.
+//! Device memory is mapped by the `mmio` module, and buffers shared with devices are allocated by
+//! the `dma` module.
+
+pub mod dma;
+pub mod frame;
+pub mod mmio;
+pub mod paging;
+pub mod zone;
+
+use arch;
+use arch::cpuid::{self, Features};
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use multiboot::{BootInfo, MemoryAreaIter};
+use spin::Mutex;
+use self::frame::BumpFrameAllocator;
+use self::paging::{EntryFlags, PageSize, HUGE_PAGE_1GIB, HUGE_PAGE_2MIB};
+
+pub type PhysicalAddress = usize;
+pub type VirtualAddress = usize;
+
+/// The size of a page and frame
+pub const PAGE_SIZE: usize = 4096;
+
+/// The virtual address the kernel is linked at, which is mapped to physical address 0
+pub const KERNEL_OFFSET: VirtualAddress = 0xFFFF_FFFF_8000_0000;
+
+/// The virtual address at which physical memory is mapped
+pub const PHYSICAL_MEMORY_OFFSET: VirtualAddress = 0xFFFF_8000_0000_0000;
+
+/// The amount of physical memory mapped at `PHYSICAL_MEMORY_OFFSET` during boot
+pub const BOOT_PHYSICAL_MAP_SIZE: usize = 0x4000_0000;
+
+/// The end of the physical memory mapped at `PHYSICAL_MEMORY_OFFSET`, once extended
+static PHYSICAL_MAP_END: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The EFER model specific register
+const IA32_EFER: u32 = 0xC000_0080;
+/// The EFER bit which enables the no-execute page bit
+const EFER_NO_EXECUTE_ENABLE: u64 = 1 << 11;
+/// The CR0 bit which makes read-only pages read-only for the kernel too
+const CR0_WRITE_PROTECT: u64 = 1 << 16;
+
+/// The global frame allocator, which is available once `init_memory` has been called
+pub static FRAME_ALLOCATOR: Mutex<Option<BumpFrameAllocator>> = Mutex::new(None);
+
+#[allow(non_upper_case_globals)]
+extern {
+    /// The physical address of the start of the kernel image, provided by the linker
+    static kernel_physical_start: u8;
+    /// The start of the kernel image in the higher half, provided by the linker
+    static kernel_start: u8;
+    /// The end of the kernel image, provided by the linker
+    static kernel_end: u8;
+    /// The page aligned start of the kernel's `.text` section, provided by the linker
+    static text_start: u8;
+    /// The page aligned end of the kernel's `.text` section, provided by the linker
+    static text_end: u8;
+    /// The page aligned start of the kernel's writable sections, provided by the linker
+    static data_start: u8;
+}
+
+/// The addresses of the kernel's sections, from the linker
+struct KernelSections {
+    physical_start: PhysicalAddress,
+    start: usize,
+    end: usize,
+    text_start: usize,
+    text_end: usize,
+    data_start: usize,
+}
+
+impl KernelSections {
+    fn get() -> Self {
+        unsafe {
+            KernelSections {
+                physical_start: &kernel_physical_start as *const u8 as usize,
+                start: &kernel_start as *const u8 as usize,
+                end: &kernel_end as *const u8 as usize,
+                text_start: &text_start as *const u8 as usize,
+                text_end: &text_end as *const u8 as usize,
+                data_start: &data_start as *const u8 as usize,
+            }
+        }
+    }
+
+    /// The physical address of the end of the kernel image
+    fn physical_end(&self) -> PhysicalAddress {
+        self.end - KERNEL_OFFSET
+    }
+
+    /// Gets the flags which the kernel page at the given address should be mapped with
+    fn flags(&self, address: usize) -> EntryFlags {
+        if address >= self.text_start && address < self.text_end {
+            EntryFlags::empty()
+        } else if address >= self.start && address < self.data_start {
+            EntryFlags::NO_EXECUTE
+        } else {
+            EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE
+        }
+    }
+}
+
+/// Initializes the frame allocator from the given boot information, and enables the no-execute
+/// page bit if supported
+pub fn init_memory(boot_info: &BootInfo) {
+    let areas = boot_info.memory_areas().expect("Bootloader should provide a memory map");
+
+    let sections = KernelSections::get();
+    let kernel = (sections.physical_start, sections.physical_end());
+
+    let available: usize = areas.clone()
+        .filter(|area| area.is_available())
+        .map(|area| area.size())
+        .sum();
+
+    info!("mem: {} KiB available", available / 1024);
+    debug!("mem: kernel at {:#x}-{:#x}, mapped at {:#x}", kernel.0, kernel.1, sections.start);
+
+    if cpuid::has(Features::NX) {
+        unsafe { arch::wrmsr(IA32_EFER, arch::rdmsr(IA32_EFER) | EFER_NO_EXECUTE_ENABLE); }
+    }
+
+    let mut allocator = BumpFrameAllocator::new(areas.clone());
+    allocator.reserve(kernel.0, kernel.1);
+    allocator.reserve(boot_info.start_address(), boot_info.end_address());
+
+    for module in boot_info.modules() {
+        allocator.reserve(module.start_address(), module.end_address());
+    }
+
+    zone::init(areas.clone());
+    map_physical_memory(areas, &mut allocator);
+    remap_kernel(&sections, &mut allocator);
+
+    *FRAME_ALLOCATOR.lock() = Some(allocator);
+}
+
+/// Extends the physical memory mapping made during boot to cover all available memory, using the
+/// largest supported huge pages
+fn map_physical_memory(areas: MemoryAreaIter, allocator: &mut BumpFrameAllocator) {
+    let size = if PageSize::Size1GiB.supported() {
+        PageSize::Size1GiB
+    } else {
+        PageSize::Size2MiB
+    };
+
+    let end = areas
+        .filter(|area| area.is_available())
+        .map(|area| area.end_address())
+        .max()
+        .unwrap_or(0);
+    let end = (end + size.bytes() - 1) & !(size.bytes() - 1);
+
+    let mut table = paging::ACTIVE_TABLE.lock();
+    let flags = EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE;
+
+    let mut physical = BOOT_PHYSICAL_MAP_SIZE;
+    while physical < end {
+        unsafe { table.map_huge_to(PHYSICAL_MEMORY_OFFSET + physical, physical, size, flags, allocator) }
+            .expect("Physical memory should be mappable");
+        physical += size.bytes();
+    }
+
+    PHYSICAL_MAP_END.store(cmp::max(end, BOOT_PHYSICAL_MAP_SIZE), Ordering::SeqCst);
+
+    debug!("mem: mapped physical memory up to {:#x} with {:?} pages", physical_map_end(), size);
+}
+
+/// Remaps the kernel so that only its code is executable, and only memory which is not its code or
+/// read-only data is writable
+fn remap_kernel(sections: &KernelSections, allocator: &mut BumpFrameAllocator) {
+    let mut table = paging::ACTIVE_TABLE.lock();
+
+    // Physical memory is never executed through its mapping
+    let flags = EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE;
+    table.set_flags(PHYSICAL_MEMORY_OFFSET, BOOT_PHYSICAL_MAP_SIZE, flags, allocator)
+        .expect("Physical memory should be mapped");
+
+    // The kernel is mapped with 4KiB pages so that each section can have its own permissions, which
+    // splits the huge pages containing it
+    let split_start = sections.start & !(HUGE_PAGE_2MIB - 1);
+    let split_end = (sections.end + HUGE_PAGE_2MIB - 1) & !(HUGE_PAGE_2MIB - 1);
+
+    let mut address = KERNEL_OFFSET;
+    while address < KERNEL_OFFSET + HUGE_PAGE_1GIB {
+        let size = if address >= split_start && address < split_end {
+            PAGE_SIZE
+        } else {
+            HUGE_PAGE_2MIB
+        };
+
+        table.set_flags(address, size, sections.flags(address), allocator).expect("Kernel should be mapped");
+        address += size;
+    }
+
+    // Make read-only pages read-only for the kernel too
+    unsafe {
+        let mut cr0: u64;
+        asm!("mov %cr0, $0" : "=r"(cr0) ::: "volatile");
+        asm!("mov $0, %cr0" :: "r"(cr0 | CR0_WRITE_PROTECT) : "memory" : "volatile");
+    }
+
+    debug!("mem: remapped kernel with W^X");
+}
+
+/// The end of the physical memory mapped at `PHYSICAL_MEMORY_OFFSET`
+pub fn physical_map_end() -> PhysicalAddress {
+    cmp::max(PHYSICAL_MAP_END.load(Ordering::SeqCst), BOOT_PHYSICAL_MAP_SIZE)
+}
+
+/// Gets the virtual address through which the given physical address can be accessed
+pub fn phys_to_virt(address: PhysicalAddress) -> VirtualAddress {
+    debug_assert!(address < physical_map_end(), "{:#x} is not mapped", address);
+    address + PHYSICAL_MEMORY_OFFSET
+}
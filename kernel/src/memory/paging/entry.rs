@@ -0,0 +1,64 @@
+use memory::PhysicalAddress;
+
+/// The bits of an entry which hold the physical address it points to
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+bitflags! {
+    pub struct EntryFlags: u64 {
+        /// If the entry is mapped
+        const PRESENT = 1 << 0;
+        /// If the mapped memory can be written to
+        const WRITABLE = 1 << 1;
+        /// If the mapped memory can be accessed from user mode
+        const USER_ACCESSIBLE = 1 << 2;
+        /// If writes go directly to memory
+        const WRITE_THROUGH = 1 << 3;
+        /// If the mapped memory is not cached
+        const NO_CACHE = 1 << 4;
+        /// Set by the CPU when the mapped memory is accessed
+        const ACCESSED = 1 << 5;
+        /// Set by the CPU when the mapped memory is written to
+        const DIRTY = 1 << 6;
+        /// If the entry maps a 2MiB or 1GiB page rather than pointing to another table
+        const HUGE_PAGE = 1 << 7;
+        /// If the mapping is not flushed from the TLB when the address space changes
+        const GLOBAL = 1 << 8;
+        /// If code cannot be executed from the mapped memory
+        const NO_EXECUTE = 1 << 63;
+    }
+}
+
+/// A single entry in a page table
+pub struct Entry(u64);
+
+impl Entry {
+    /// Returns `true` if this entry does not map or point to anything
+    pub fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Clears this entry
+    pub fn set_unused(&mut self) {
+        self.0 = 0;
+    }
+
+    /// Gets the flags of this entry
+    pub fn flags(&self) -> EntryFlags {
+        EntryFlags::from_bits_truncate(self.0)
+    }
+
+    /// Gets the physical address this entry points to, if it is present
+    pub fn address(&self) -> Option<PhysicalAddress> {
+        if self.flags().contains(EntryFlags::PRESENT) {
+            Some((self.0 & ADDRESS_MASK) as PhysicalAddress)
+        } else {
+            None
+        }
+    }
+
+    /// Sets this entry to point to the given physical address with the given flags
+    pub fn set(&mut self, address: PhysicalAddress, flags: EntryFlags) {
+        assert_eq!(address as u64 & !ADDRESS_MASK, 0, "entry address must be page aligned");
+        self.0 = address as u64 | flags.bits();
+    }
+}
@@ -0,0 +1,723 @@
+//! # Paging
+//!
+//! The paging API provides access to the active page tables, allowing arbitrary virtual ranges to
+//! be mapped and unmapped with typed [EntryFlags], virtual addresses to be translated to physical
+//! addresses, and the tables to be dumped for debugging.
+//!
+//! Besides 4KiB pages, 2MiB and 1GiB huge pages can be mapped with `map_huge_to`. 1GiB pages are
+//! only available if `cpuid` reports support for them. Huge pages are split into smaller pages when
+//! only part of one is unmapped or has its flags changed.
+//!
+//! The active tables are accessed through the static `ACTIVE_TABLE`, and individual tables are
+//! accessed through the physical memory mapping.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let mut table = paging::ACTIVE_TABLE.lock();
+//! let mut allocator = memory::FRAME_ALLOCATOR.lock();
+//! let allocator = allocator.as_mut().unwrap();
+//!
+//! table.map_range(0x4000_0000, 0x4000, EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE, allocator)?;
+//! assert!(table.translate(0x4000_0000).is_some());
+//! ```
+
+pub mod entry;
+pub mod table;
+
+pub use self::entry::EntryFlags;
+
+use arch::cpuid::{self, Features};
+use core::fmt;
+use memory::{self, PhysicalAddress, VirtualAddress, PAGE_SIZE};
+use memory::frame::{Frame, FrameAllocator};
+use spin::Mutex;
+use self::entry::Entry;
+use self::table::{Table, ENTRY_COUNT};
+
+/// The size of a huge page mapped by a level 2 entry
+pub const HUGE_PAGE_2MIB: usize = 0x20_0000;
+/// The size of a huge page mapped by a level 3 entry
+pub const HUGE_PAGE_1GIB: usize = 0x4000_0000;
+
+/// The bits of CR3 which hold the physical address of the level 4 table
+const CR3_ADDRESS_MASK: usize = 0x000F_FFFF_FFFF_F000;
+
+/// The currently active page tables
+pub static ACTIVE_TABLE: Mutex<ActivePageTable> = Mutex::new(ActivePageTable { _private: () });
+
+/// An error returned when mapping or unmapping pages
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MapError {
+    /// The page is already mapped
+    AlreadyMapped,
+    /// The page is not mapped
+    NotMapped,
+    /// The page lies within a huge page
+    HugePage,
+    /// No frames could be allocated for a table or to back the mapping
+    OutOfFrames,
+    /// The given address or size is not page aligned
+    Unaligned,
+    /// The page size is not supported by the CPU
+    Unsupported,
+}
+
+/// The sizes of page which can be mapped
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PageSize {
+    /// A 4KiB page, mapped by a level 1 entry
+    Size4KiB,
+    /// A 2MiB huge page, mapped by a level 2 entry
+    Size2MiB,
+    /// A 1GiB huge page, mapped by a level 3 entry
+    Size1GiB,
+}
+
+impl PageSize {
+    /// The size of this page in bytes
+    pub fn bytes(&self) -> usize {
+        match *self {
+            PageSize::Size4KiB => PAGE_SIZE,
+            PageSize::Size2MiB => HUGE_PAGE_2MIB,
+            PageSize::Size1GiB => HUGE_PAGE_1GIB,
+        }
+    }
+
+    /// Returns `true` if this page size is supported by the CPU
+    pub fn supported(&self) -> bool {
+        *self != PageSize::Size1GiB || cpuid::has(Features::PAGE_1GB)
+    }
+}
+
+/// Represents a 4KiB page of virtual memory
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Page {
+    number: usize,
+}
+
+impl Page {
+    /// Gets the page containing the given virtual address
+    ///
+    /// # Panics
+    ///
+    /// Panics if the address is not canonical
+    pub fn containing_address(address: VirtualAddress) -> Page {
+        assert!(
+            address < 0x0000_8000_0000_0000 || address >= 0xFFFF_8000_0000_0000,
+            "invalid address: {:#x}", address
+        );
+
+        Page { number: address / PAGE_SIZE }
+    }
+
+    /// The virtual address of the start of this page
+    pub fn start_address(&self) -> VirtualAddress {
+        self.number * PAGE_SIZE
+    }
+
+    fn p4_index(&self) -> usize {
+        (self.number >> 27) & 0o777
+    }
+
+    fn p3_index(&self) -> usize {
+        (self.number >> 18) & 0o777
+    }
+
+    fn p2_index(&self) -> usize {
+        (self.number >> 9) & 0o777
+    }
+
+    fn p1_index(&self) -> usize {
+        self.number & 0o777
+    }
+}
+
+/// An iterator over a range of pages
+pub struct PageIter {
+    next: Page,
+    end: Page,
+}
+
+impl PageIter {
+    /// Creates an iterator over the pages in the given page aligned range
+    pub fn new(start: VirtualAddress, size: usize) -> Result<PageIter, MapError> {
+        if start % PAGE_SIZE != 0 || size % PAGE_SIZE != 0 {
+            return Err(MapError::Unaligned);
+        }
+
+        Ok(PageIter {
+            next: Page::containing_address(start),
+            end: Page { number: Page::containing_address(start).number + size / PAGE_SIZE },
+        })
+    }
+}
+
+impl Iterator for PageIter {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        if self.next < self.end {
+            let page = self.next;
+            self.next.number += 1;
+            Some(page)
+        } else {
+            None
+        }
+    }
+}
+
+/// Interface to the currently active page tables
+pub struct ActivePageTable {
+    _private: (),
+}
+
+#[allow(dead_code)] // Part of API
+impl ActivePageTable {
+    /// Gets a handle to the active page tables without locking `ACTIVE_TABLE`, for use when the lock
+    /// may be held by code which will never release it, such as after a panic
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may modify the page tables while the handle is in use
+    pub unsafe fn unlocked() -> ActivePageTable {
+        ActivePageTable { _private: () }
+    }
+
+    fn p4(&self) -> &Table {
+        unsafe { &*(memory::phys_to_virt(p4_address()) as *const Table) }
+    }
+
+    fn p4_mut(&mut self) -> &mut Table {
+        unsafe { &mut *(memory::phys_to_virt(p4_address()) as *mut Table) }
+    }
+
+    /// Translates the given virtual address to the physical address it is mapped to
+    pub fn translate(&self, address: VirtualAddress) -> Option<PhysicalAddress> {
+        self.translate_with_flags(address).map(|(physical, _)| physical)
+    }
+
+    /// Translates the given virtual address to the physical address it is mapped to, along with
+    /// the flags of the mapping
+    pub fn translate_with_flags(&self, address: VirtualAddress) -> Option<(PhysicalAddress, EntryFlags)> {
+        let page = Page::containing_address(address);
+        let p3 = self.p4().next_table(page.p4_index())?;
+
+        let p3_entry = &p3[page.p3_index()];
+        if p3_entry.flags().contains(EntryFlags::HUGE_PAGE) {
+            return huge_address(p3_entry, PageSize::Size1GiB)
+                .map(|base| (base + address % HUGE_PAGE_1GIB, p3_entry.flags()));
+        }
+
+        let p2 = p3.next_table(page.p3_index())?;
+
+        let p2_entry = &p2[page.p2_index()];
+        if p2_entry.flags().contains(EntryFlags::HUGE_PAGE) {
+            return huge_address(p2_entry, PageSize::Size2MiB)
+                .map(|base| (base + address % HUGE_PAGE_2MIB, p2_entry.flags()));
+        }
+
+        let p1 = p2.next_table(page.p2_index())?;
+
+        let p1_entry = &p1[page.p1_index()];
+        p1_entry.address().map(|base| (base + address % PAGE_SIZE, p1_entry.flags()))
+    }
+
+    /// Maps the given page to a newly allocated frame
+    pub fn map<A>(&mut self, page: Page, flags: EntryFlags, allocator: &mut A) -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        let frame = allocator.allocate_frame().ok_or(MapError::OutOfFrames)?;
+
+        // Safe because the frame was just allocated, and so cannot be aliased
+        let result = unsafe { self.map_to(page, frame, flags, allocator) };
+
+        if result.is_err() {
+            allocator.deallocate_frame(frame);
+        }
+
+        result
+    }
+
+    /// Maps the given page to the given frame
+    ///
+    /// # Safety
+    ///
+    /// The frame must not already be in use, unless aliasing it is intended
+    pub unsafe fn map_to<A>(&mut self, page: Page, frame: Frame, flags: EntryFlags, allocator: &mut A)
+        -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        let flags = supported_flags(flags) | EntryFlags::PRESENT;
+
+        // A page within a huge page is already mapped by it
+        let p1 = self.p4_mut()
+            .next_table_create(page.p4_index(), flags, allocator)
+            .and_then(|p3| p3.next_table_create(page.p3_index(), flags, allocator))
+            .and_then(|p2| p2.next_table_create(page.p2_index(), flags, allocator))
+            .map_err(|error| if error == MapError::HugePage { MapError::AlreadyMapped } else { error })?;
+
+        let entry = &mut p1[page.p1_index()];
+        if !entry.is_unused() {
+            return Err(MapError::AlreadyMapped);
+        }
+
+        entry.set(frame.start_address(), flags);
+
+        Ok(())
+    }
+
+    /// Maps a page of the given size at the given virtual address to the given physical address.
+    /// Both addresses must be aligned to the page size.
+    ///
+    /// # Safety
+    ///
+    /// The physical memory must not already be in use, unless aliasing it is intended
+    pub unsafe fn map_huge_to<A>(
+        &mut self,
+        address: VirtualAddress,
+        physical: PhysicalAddress,
+        size: PageSize,
+        flags: EntryFlags,
+        allocator: &mut A
+    ) -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        if address % size.bytes() != 0 || physical % size.bytes() != 0 {
+            return Err(MapError::Unaligned);
+        }
+
+        if !size.supported() {
+            return Err(MapError::Unsupported);
+        }
+
+        let page = Page::containing_address(address);
+        let flags = supported_flags(flags) | EntryFlags::PRESENT;
+
+        match size {
+            PageSize::Size4KiB => self.map_to(page, Frame::containing_address(physical), flags, allocator),
+            PageSize::Size2MiB => {
+                let p2 = self.p4_mut()
+                    .next_table_create(page.p4_index(), flags, allocator)?
+                    .next_table_create(page.p3_index(), flags, allocator)?;
+
+                set_huge_entry(&mut p2[page.p2_index()], physical, flags)
+            }
+            PageSize::Size1GiB => {
+                let p3 = self.p4_mut().next_table_create(page.p4_index(), flags, allocator)?;
+
+                set_huge_entry(&mut p3[page.p3_index()], physical, flags)
+            }
+        }
+    }
+
+    /// Unmaps the page of any size at the given virtual address, returning the physical address it
+    /// was mapped to and its size. The address must be aligned to the size of the page.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may reference the memory in this page after it is unmapped
+    pub unsafe fn unmap_huge(&mut self, address: VirtualAddress) -> Result<(PhysicalAddress, PageSize), MapError> {
+        let page = Page::containing_address(address);
+
+        let p3 = next_level(self.p4_mut(), page.p4_index())?;
+        if p3[page.p3_index()].flags().contains(EntryFlags::HUGE_PAGE) {
+            return clear_huge_entry(&mut p3[page.p3_index()], address, PageSize::Size1GiB);
+        }
+
+        let p2 = next_level(p3, page.p3_index())?;
+        if p2[page.p2_index()].flags().contains(EntryFlags::HUGE_PAGE) {
+            return clear_huge_entry(&mut p2[page.p2_index()], address, PageSize::Size2MiB);
+        }
+
+        self.unmap_small(page).map(|frame| (frame.start_address(), PageSize::Size4KiB))
+    }
+
+    /// Maps the given frame to the page with the same address
+    ///
+    /// # Safety
+    ///
+    /// See `map_to`
+    pub unsafe fn identity_map<A>(&mut self, frame: Frame, flags: EntryFlags, allocator: &mut A)
+        -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        let page = Page::containing_address(frame.start_address());
+        self.map_to(page, frame, flags, allocator)
+    }
+
+    /// Maps the given page aligned virtual range to newly allocated frames.
+    ///
+    /// # Note
+    ///
+    /// If an error occurs, the pages mapped before it remain mapped.
+    pub fn map_range<A>(&mut self, start: VirtualAddress, size: usize, flags: EntryFlags, allocator: &mut A)
+        -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        for page in PageIter::new(start, size)? {
+            self.map(page, flags, allocator)?;
+        }
+
+        Ok(())
+    }
+
+    /// Unmaps the given page, returning the frame it was mapped to. If the page is within a huge
+    /// page, the huge page is split so that the rest of it stays mapped.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may reference the memory in this page after it is unmapped
+    pub unsafe fn unmap<A>(&mut self, page: Page, allocator: &mut A) -> Result<Frame, MapError>
+        where A: FrameAllocator
+    {
+        while self.page_size(page.start_address())? != PageSize::Size4KiB {
+            self.split_huge_page(page.start_address(), allocator)?;
+        }
+
+        self.unmap_small(page)
+    }
+
+    /// Unmaps the given page, which must not be within a huge page
+    unsafe fn unmap_small(&mut self, page: Page) -> Result<Frame, MapError> {
+        let p1 = next_level(self.p4_mut(), page.p4_index())
+            .and_then(|p3| next_level(p3, page.p3_index()))
+            .and_then(|p2| next_level(p2, page.p2_index()))?;
+
+        let entry = &mut p1[page.p1_index()];
+        let frame = entry.address().map(Frame::containing_address).ok_or(MapError::NotMapped)?;
+
+        entry.set_unused();
+        flush(page);
+
+        Ok(frame)
+    }
+
+    /// Unmaps the given page aligned virtual range, returning the frames it was mapped to to the
+    /// allocator
+    ///
+    /// # Safety
+    ///
+    /// See `unmap`. The frames must also have been allocated from the given allocator.
+    pub unsafe fn unmap_range<A>(&mut self, start: VirtualAddress, size: usize, allocator: &mut A)
+        -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        for page in PageIter::new(start, size)? {
+            let frame = self.unmap(page, allocator)?;
+            allocator.deallocate_frame(frame);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the flags of the given page aligned virtual range. Huge pages entirely within the range
+    /// keep their size, and those only partly within it are split.
+    pub fn set_flags<A>(&mut self, start: VirtualAddress, size: usize, flags: EntryFlags, allocator: &mut A)
+        -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        if start % PAGE_SIZE != 0 || size % PAGE_SIZE != 0 {
+            return Err(MapError::Unaligned);
+        }
+
+        let flags = supported_flags(flags) | EntryFlags::PRESENT;
+        let end = start + size;
+
+        let mut address = start;
+        while address < end {
+            let page_size = self.page_size(address)?;
+            let bytes = page_size.bytes();
+
+            if address % bytes != 0 || end - address < bytes {
+                self.split_huge_page(address, allocator)?;
+                continue;
+            }
+
+            let huge = if page_size == PageSize::Size4KiB { EntryFlags::empty() } else { EntryFlags::HUGE_PAGE };
+            update_entry(self.entry_mut(address, page_size)?, flags | huge)?;
+            flush(Page::containing_address(address));
+
+            address += bytes;
+        }
+
+        Ok(())
+    }
+
+    /// Splits the huge page containing the given address into pages of the next smaller size with
+    /// the same flags, so that parts of it can be remapped. Does nothing if the address is already
+    /// mapped with 4KiB pages.
+    pub fn split_huge_page<A>(&mut self, address: VirtualAddress, allocator: &mut A) -> Result<(), MapError>
+        where A: FrameAllocator
+    {
+        let size = self.page_size(address)?;
+        let (smaller, smaller_flags) = match size {
+            PageSize::Size4KiB => return Ok(()),
+            PageSize::Size2MiB => (PageSize::Size4KiB, EntryFlags::empty()),
+            PageSize::Size1GiB => (PageSize::Size2MiB, EntryFlags::HUGE_PAGE),
+        };
+
+        let entry = self.entry_mut(address, size)?;
+        let flags = entry.flags();
+        let base = huge_address(entry, size).ok_or(MapError::NotMapped)?;
+
+        let frame = allocator.allocate_frame().ok_or(MapError::OutOfFrames)?;
+        let table = unsafe { &mut *(memory::phys_to_virt(frame.start_address()) as *mut Table) };
+
+        for index in 0..ENTRY_COUNT {
+            let flags = (flags - EntryFlags::HUGE_PAGE) | smaller_flags;
+            table[index].set(base + index * smaller.bytes(), flags);
+        }
+
+        let table_flags = EntryFlags::PRESENT | EntryFlags::WRITABLE |
+            (flags & EntryFlags::USER_ACCESSIBLE);
+        entry.set(frame.start_address(), table_flags);
+
+        flush_all();
+
+        Ok(())
+    }
+
+    /// Gets the size of the page mapping the given address
+    fn page_size(&self, address: VirtualAddress) -> Result<PageSize, MapError> {
+        let page = Page::containing_address(address);
+        let p3 = self.p4().next_table(page.p4_index()).ok_or(MapError::NotMapped)?;
+
+        if p3[page.p3_index()].flags().contains(EntryFlags::HUGE_PAGE) {
+            return Ok(PageSize::Size1GiB);
+        }
+
+        let p2 = p3.next_table(page.p3_index()).ok_or(MapError::NotMapped)?;
+
+        if p2[page.p2_index()].flags().contains(EntryFlags::HUGE_PAGE) {
+            return Ok(PageSize::Size2MiB);
+        }
+
+        let p1 = p2.next_table(page.p2_index()).ok_or(MapError::NotMapped)?;
+        p1[page.p1_index()].address().map(|_| PageSize::Size4KiB).ok_or(MapError::NotMapped)
+    }
+
+    /// Gets the entry mapping the page of the given size containing the given address
+    fn entry_mut(&mut self, address: VirtualAddress, size: PageSize) -> Result<&mut Entry, MapError> {
+        let page = Page::containing_address(address);
+        let p3 = next_level(self.p4_mut(), page.p4_index())?;
+
+        if size == PageSize::Size1GiB {
+            return Ok(&mut p3[page.p3_index()]);
+        }
+
+        let p2 = next_level(p3, page.p3_index())?;
+
+        if size == PageSize::Size2MiB {
+            return Ok(&mut p2[page.p2_index()]);
+        }
+
+        Ok(&mut next_level(p2, page.p2_index())?[page.p1_index()])
+    }
+
+    /// Prints all mappings in the active tables, merging contiguous mappings with the same flags
+    pub fn dump(&self) {
+        println!("paging: active tables at {:#x}", p4_address());
+
+        let mut dumper = TableDumper { run: None };
+        let p4 = self.p4();
+
+        for p4_index in 0..ENTRY_COUNT {
+            let p3 = match p4.next_table(p4_index) {
+                Some(p3) => p3,
+                None => continue,
+            };
+
+            for p3_index in 0..ENTRY_COUNT {
+                let p3_address = p4_index << 39 | p3_index << 30;
+                let p2 = match dumper.next_level(p3, p3_index, p3_address, HUGE_PAGE_1GIB) {
+                    Some(p2) => p2,
+                    None => continue,
+                };
+
+                for p2_index in 0..ENTRY_COUNT {
+                    let p2_address = p3_address | p2_index << 21;
+                    let p1 = match dumper.next_level(p2, p2_index, p2_address, HUGE_PAGE_2MIB) {
+                        Some(p1) => p1,
+                        None => continue,
+                    };
+
+                    for p1_index in 0..ENTRY_COUNT {
+                        let entry = &p1[p1_index];
+                        if let Some(physical) = entry.address() {
+                            dumper.add(p2_address | p1_index << 12, physical, PAGE_SIZE, entry.flags());
+                        }
+                    }
+                }
+            }
+        }
+
+        dumper.flush();
+    }
+}
+
+/// A run of contiguous mappings with the same flags
+struct MappingRun {
+    virtual_start: VirtualAddress,
+    physical_start: PhysicalAddress,
+    size: usize,
+    flags: EntryFlags,
+}
+
+impl fmt::Display for MappingRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#x} {:?}",
+            self.virtual_start,
+            self.virtual_start + (self.size - 1),
+            self.physical_start,
+            self.flags
+        )
+    }
+}
+
+/// Merges and prints mappings while dumping tables
+struct TableDumper {
+    run: Option<MappingRun>,
+}
+
+impl TableDumper {
+    /// Gets the table pointed to by the given entry, or adds the mapping if it is a huge page
+    fn next_level<'a>(&mut self, table: &'a Table, index: usize, address: usize, huge_size: usize)
+        -> Option<&'a Table>
+    {
+        let entry = &table[index];
+
+        if entry.flags().contains(EntryFlags::HUGE_PAGE) {
+            if let Some(physical) = entry.address() {
+                self.add(address, physical & !(huge_size - 1), huge_size, entry.flags());
+            }
+
+            return None;
+        }
+
+        table.next_table(index)
+    }
+
+    /// Adds a mapping, extending the current run if it is contiguous with it
+    fn add(&mut self, address: usize, physical: PhysicalAddress, size: usize, flags: EntryFlags) {
+        let virtual_address = canonical(address);
+        let flags = flags - (EntryFlags::ACCESSED | EntryFlags::DIRTY | EntryFlags::HUGE_PAGE);
+
+        if let Some(ref mut run) = self.run {
+            let contiguous = run.virtual_start + run.size == virtual_address &&
+                run.physical_start + run.size == physical;
+
+            if contiguous && run.flags == flags {
+                run.size += size;
+                return;
+            }
+        }
+
+        self.flush();
+        self.run = Some(MappingRun {
+            virtual_start: virtual_address,
+            physical_start: physical,
+            size,
+            flags,
+        });
+    }
+
+    /// Prints the current run, if any
+    fn flush(&mut self) {
+        if let Some(run) = self.run.take() {
+            println!("{}", run);
+        }
+    }
+}
+
+/// Gets the next level table for unmapping, returning the reason if it cannot be found
+fn next_level(table: &mut Table, index: usize) -> Result<&mut Table, MapError> {
+    if table[index].flags().contains(EntryFlags::HUGE_PAGE) {
+        return Err(MapError::HugePage);
+    }
+
+    table.next_table_mut(index).ok_or(MapError::NotMapped)
+}
+
+/// Sets the given unused entry to map a huge page
+fn set_huge_entry(entry: &mut Entry, physical: PhysicalAddress, flags: EntryFlags) -> Result<(), MapError> {
+    if !entry.is_unused() {
+        return Err(MapError::AlreadyMapped);
+    }
+
+    entry.set(physical, flags | EntryFlags::HUGE_PAGE);
+
+    Ok(())
+}
+
+/// Clears the given huge page entry, returning the physical address it was mapped to
+fn clear_huge_entry(entry: &mut Entry, address: VirtualAddress, size: PageSize)
+    -> Result<(PhysicalAddress, PageSize), MapError>
+{
+    if address % size.bytes() != 0 {
+        return Err(MapError::Unaligned);
+    }
+
+    let physical = huge_address(entry, size).ok_or(MapError::NotMapped)?;
+
+    entry.set_unused();
+    flush(Page::containing_address(address));
+
+    Ok((physical, size))
+}
+
+/// Gets the physical address of the given huge page entry, masking out the PAT bit
+fn huge_address(entry: &Entry, size: PageSize) -> Option<PhysicalAddress> {
+    entry.address().map(|address| address & !(size.bytes() - 1))
+}
+
+/// Replaces the flags of the given present entry
+fn update_entry(entry: &mut Entry, flags: EntryFlags) -> Result<(), MapError> {
+    let address = entry.address().ok_or(MapError::NotMapped)?;
+    entry.set(address, flags);
+    Ok(())
+}
+
+/// Removes flags which are unsupported by the CPU
+fn supported_flags(flags: EntryFlags) -> EntryFlags {
+    if cpuid::has(Features::NX) {
+        flags
+    } else {
+        flags - EntryFlags::NO_EXECUTE
+    }
+}
+
+/// Sign extends the given address from bit 47 to make it canonical
+fn canonical(address: usize) -> VirtualAddress {
+    if address & (1 << 47) != 0 {
+        address | 0xFFFF_0000_0000_0000
+    } else {
+        address
+    }
+}
+
+/// Gets the physical address of the active level 4 table
+fn p4_address() -> PhysicalAddress {
+    let cr3: usize;
+    unsafe {
+        asm!("mov %cr3, $0" : "=r"(cr3) ::: "volatile");
+    }
+    cr3 & CR3_ADDRESS_MASK
+}
+
+/// Flushes the entire TLB, excluding global pages, by reloading CR3
+fn flush_all() {
+    unsafe {
+        asm!("mov %cr3, %rax
+              mov %rax, %cr3" ::: "rax", "memory" : "volatile");
+    }
+}
+
+/// Flushes the given page from the TLB
+fn flush(page: Page) {
+    unsafe {
+        asm!("invlpg ($0)" :: "r"(page.start_address()) : "memory" : "volatile");
+    }
+}
@@ -0,0 +1,88 @@
+use core::ops::{Index, IndexMut};
+use memory;
+use memory::frame::FrameAllocator;
+use super::MapError;
+use super::entry::{Entry, EntryFlags};
+
+/// The amount of entries in a page table
+pub const ENTRY_COUNT: usize = 512;
+
+/// A page table of any level.
+///
+/// # Note
+///
+/// The level of a table is not tracked, so it is up to the caller not to call `next_table` on a
+/// level 1 table.
+pub struct Table {
+    entries: [Entry; ENTRY_COUNT],
+}
+
+impl Table {
+    /// Clears all entries in this table
+    pub fn zero(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.set_unused();
+        }
+    }
+
+    /// Gets the table that the entry at the given index points to, if present and not a huge page
+    pub fn next_table(&self, index: usize) -> Option<&Table> {
+        self.next_table_address(index).map(|address| unsafe { &*(address as *const Table) })
+    }
+
+    /// Mutably gets the table that the entry at the given index points to, if present and not a
+    /// huge page
+    pub fn next_table_mut(&mut self, index: usize) -> Option<&mut Table> {
+        self.next_table_address(index).map(|address| unsafe { &mut *(address as *mut Table) })
+    }
+
+    /// Gets the table that the entry at the given index points to, creating it if it does not
+    /// exist. The new table is user accessible if the given flags are.
+    pub fn next_table_create<A>(&mut self, index: usize, flags: EntryFlags, allocator: &mut A)
+        -> Result<&mut Table, MapError>
+        where A: FrameAllocator
+    {
+        if self.entries[index].flags().contains(EntryFlags::HUGE_PAGE) {
+            return Err(MapError::HugePage);
+        }
+
+        if self.entries[index].is_unused() {
+            let frame = allocator.allocate_frame().ok_or(MapError::OutOfFrames)?;
+            let table_flags = EntryFlags::PRESENT | EntryFlags::WRITABLE |
+                (flags & EntryFlags::USER_ACCESSIBLE);
+
+            self.entries[index].set(frame.start_address(), table_flags);
+            self.next_table_mut(index).unwrap().zero();
+        } else if flags.contains(EntryFlags::USER_ACCESSIBLE) {
+            let existing = self.entries[index].flags();
+            let address = self.entries[index].address().unwrap();
+            self.entries[index].set(address, existing | EntryFlags::USER_ACCESSIBLE);
+        }
+
+        Ok(self.next_table_mut(index).unwrap())
+    }
+
+    fn next_table_address(&self, index: usize) -> Option<usize> {
+        let entry = &self.entries[index];
+
+        if entry.flags().contains(EntryFlags::HUGE_PAGE) {
+            return None;
+        }
+
+        entry.address().map(memory::phys_to_virt)
+    }
+}
+
+impl Index<usize> for Table {
+    type Output = Entry;
+
+    fn index(&self, index: usize) -> &Entry {
+        &self.entries[index]
+    }
+}
+
+impl IndexMut<usize> for Table {
+    fn index_mut(&mut self, index: usize) -> &mut Entry {
+        &mut self.entries[index]
+    }
+}
@@ -0,0 +1,253 @@
+//! # Zones
+//!
+//! Tracks the physical memory which is not RAM available for use, as typed zones: ACPI tables
+//! which can be reclaimed once read, ACPI NVS memory which must be preserved, and device memory
+//! (MMIO). Zones are created from the multiboot memory map by `init`, where reserved areas are
+//! treated as MMIO, and drivers can `reserve` further MMIO ranges, such as a framebuffer or PCI
+//! BARs, which are often missing from the map.
+//!
+//! A zone can never overlap RAM available for use, so its frames are never handed out by the frame
+//! allocator or used for the heap. Drivers `claim` the parts of a zone they use, so that two
+//! drivers cannot use the same device memory.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! zone::reserve(info.address, info.address + size, ZoneType::Mmio)?;
+//! zone::claim(info.address, info.address + size, ZoneType::Mmio, "graphics")?;
+//! ```
+
+use multiboot::{MemoryAreaIter, MemoryAreaType};
+use spin::Mutex;
+use super::PhysicalAddress;
+
+/// The maximum amount of zones which can be tracked
+pub const MAX_ZONES: usize = 32;
+/// The maximum amount of claims which can be made on zones
+pub const MAX_CLAIMS: usize = 32;
+
+/// The zones of physical memory, which are available once `init` has been called
+static ZONES: Mutex<ZoneMap> = Mutex::new(ZoneMap::new());
+
+/// The type of memory in a zone
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ZoneType {
+    /// ACPI tables, which may be reused once they have been read
+    AcpiReclaimable,
+    /// Memory which the firmware requires to be preserved across sleep states
+    AcpiNvs,
+    /// Device memory, which must be mapped uncached
+    Mmio,
+}
+
+/// A range of physical memory of a single type
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Zone {
+    pub zone_type: ZoneType,
+    pub start: PhysicalAddress,
+    /// The end of the zone (exclusive)
+    pub end: PhysicalAddress,
+}
+
+impl Zone {
+    fn overlaps(&self, start: PhysicalAddress, end: PhysicalAddress) -> bool {
+        start < self.end && end > self.start
+    }
+
+    fn contains(&self, start: PhysicalAddress, end: PhysicalAddress) -> bool {
+        start >= self.start && end <= self.end
+    }
+}
+
+/// A range of a zone in use by a driver
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct Claim {
+    start: PhysicalAddress,
+    end: PhysicalAddress,
+    owner: &'static str,
+}
+
+/// An error returned when reserving or claiming a zone
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ZoneError {
+    /// The range is empty
+    EmptyRange,
+    /// The range overlaps RAM available for use
+    OverlapsRam,
+    /// The range overlaps a zone of another type
+    OverlapsZone(ZoneType),
+    /// The range is not within a single zone of the requested type
+    NotReserved,
+    /// The range overlaps a range already claimed by the given owner
+    AlreadyClaimed(&'static str),
+    /// `MAX_ZONES` zones or `MAX_CLAIMS` claims have already been made
+    TooMany,
+}
+
+struct ZoneMap {
+    /// The memory map, used to check that zones never overlap available RAM
+    areas: Option<MemoryAreaIter>,
+    zones: [Option<Zone>; MAX_ZONES],
+    claims: [Option<Claim>; MAX_CLAIMS],
+}
+
+impl ZoneMap {
+    const fn new() -> Self {
+        ZoneMap {
+            areas: None,
+            zones: [None; MAX_ZONES],
+            claims: [None; MAX_CLAIMS],
+        }
+    }
+
+    /// Finds the first zone matching the given predicate
+    fn find<F>(&self, predicate: F) -> Option<Zone>
+        where F: Fn(&Zone) -> bool
+    {
+        self.zones.iter()
+            .filter_map(|zone| *zone)
+            .find(|zone| predicate(zone))
+    }
+
+    fn overlaps_ram(&self, start: PhysicalAddress, end: PhysicalAddress) -> bool {
+        match self.areas {
+            Some(ref areas) => areas.clone()
+                .filter(|area| area.is_available())
+                .any(|area| start < area.end_address() && end > area.start_address()),
+            None => false,
+        }
+    }
+
+    fn reserve(&mut self, start: PhysicalAddress, end: PhysicalAddress, zone_type: ZoneType) -> Result<(), ZoneError> {
+        if start >= end {
+            return Err(ZoneError::EmptyRange);
+        }
+
+        if self.overlaps_ram(start, end) {
+            return Err(ZoneError::OverlapsRam);
+        }
+
+        if let Some(zone) = self.find(|zone| zone.overlaps(start, end)) {
+            return if zone.zone_type != zone_type {
+                Err(ZoneError::OverlapsZone(zone.zone_type))
+            } else if zone.contains(start, end) {
+                Ok(())
+            } else {
+                // Extending zones is not supported, so a range must be reserved whole
+                Err(ZoneError::OverlapsZone(zone_type))
+            };
+        }
+
+        let slot = self.zones.iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(ZoneError::TooMany)?;
+
+        *slot = Some(Zone { zone_type, start, end });
+        Ok(())
+    }
+
+    fn claim(&mut self, start: PhysicalAddress, end: PhysicalAddress, zone_type: ZoneType, owner: &'static str)
+        -> Result<(), ZoneError>
+    {
+        if start >= end {
+            return Err(ZoneError::EmptyRange);
+        }
+
+        if self.find(|zone| zone.zone_type == zone_type && zone.contains(start, end)).is_none() {
+            return Err(ZoneError::NotReserved);
+        }
+
+        let claimed = self.claims.iter()
+            .filter_map(|claim| *claim)
+            .find(|claim| start < claim.end && end > claim.start);
+        if let Some(claim) = claimed {
+            return Err(ZoneError::AlreadyClaimed(claim.owner));
+        }
+
+        let slot = self.claims.iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(ZoneError::TooMany)?;
+
+        *slot = Some(Claim { start, end, owner });
+        Ok(())
+    }
+
+    fn release(&mut self, start: PhysicalAddress, owner: &'static str) -> bool {
+        let slot = self.claims.iter_mut()
+            .find(|slot| slot.map_or(false, |claim| claim.start == start && claim.owner == owner));
+
+        match slot {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Creates zones for the ACPI and reserved areas of the given memory map
+pub fn init(areas: MemoryAreaIter) {
+    let mut map = ZONES.lock();
+    map.areas = Some(areas.clone());
+
+    for area in areas {
+        let zone_type = match area.area_type() {
+            MemoryAreaType::AcpiReclaimable => ZoneType::AcpiReclaimable,
+            MemoryAreaType::AcpiNvs => ZoneType::AcpiNvs,
+            MemoryAreaType::Reserved => ZoneType::Mmio,
+            MemoryAreaType::Available | MemoryAreaType::Defective => continue,
+        };
+
+        if let Err(error) = map.reserve(area.start_address(), area.end_address(), zone_type) {
+            warn!("mem: cannot track {:?} zone at {:#x}-{:#x}: {:?}",
+                  zone_type, area.start_address(), area.end_address(), error);
+        }
+    }
+
+    for zone in map.zones.iter().filter_map(|zone| *zone) {
+        debug!("mem: {:?} zone at {:#x}-{:#x}", zone.zone_type, zone.start, zone.end);
+    }
+}
+
+/// Reserves the given physical range, from its start to its end (exclusive), as a zone of the given
+/// type. Reserving a range already within a zone of the same type does nothing.
+pub fn reserve(start: PhysicalAddress, end: PhysicalAddress, zone_type: ZoneType) -> Result<(), ZoneError> {
+    ZONES.lock().reserve(start, end, zone_type)
+}
+
+/// Claims the given physical range for the given owner. The range must be within a single zone of
+/// the given type, and must not overlap a range claimed by another owner.
+pub fn claim(start: PhysicalAddress, end: PhysicalAddress, zone_type: ZoneType, owner: &'static str)
+    -> Result<(), ZoneError>
+{
+    ZONES.lock().claim(start, end, zone_type, owner)
+}
+
+/// Releases the range starting at the given address claimed by the given owner. Returns `true` if
+/// it was claimed.
+pub fn release(start: PhysicalAddress, owner: &'static str) -> bool {
+    ZONES.lock().release(start, owner)
+}
+
+/// Gets the zone containing the given physical address
+#[allow(dead_code)] // Part of API
+pub fn zone_at(address: PhysicalAddress) -> Option<Zone> {
+    ZONES.lock().find(|zone| zone.contains(address, address + 1))
+}
+
+kernel_test!(fn reserves_and_claims_zones() {
+    let mut map = ZoneMap::new();
+
+    test_assert_eq!(map.reserve(0xFEC0_0000, 0xFED0_0000, ZoneType::Mmio), Ok(()));
+    test_assert_eq!(map.reserve(0xFEC0_0000, 0xFEC0_1000, ZoneType::Mmio), Ok(()));
+    test_assert_eq!(map.reserve(0xFECF_F000, 0xFED0_1000, ZoneType::AcpiNvs), Err(ZoneError::OverlapsZone(ZoneType::Mmio)));
+
+    test_assert_eq!(map.claim(0xFEC0_0000, 0xFEC0_1000, ZoneType::Mmio, "ioapic"), Ok(()));
+    test_assert_eq!(map.claim(0xFEC0_0000, 0xFEC0_2000, ZoneType::Mmio, "hpet"), Err(ZoneError::AlreadyClaimed("ioapic")));
+    test_assert_eq!(map.claim(0xFEC0_1000, 0xFEC0_2000, ZoneType::AcpiNvs, "hpet"), Err(ZoneError::NotReserved));
+    test_assert_eq!(map.claim(0xFED0_0000, 0xFED0_1000, ZoneType::Mmio, "hpet"), Err(ZoneError::NotReserved));
+
+    test_assert!(map.release(0xFEC0_0000, "ioapic"));
+    test_assert_eq!(map.claim(0xFEC0_0000, 0xFEC0_2000, ZoneType::Mmio, "hpet"), Ok(()));
+});
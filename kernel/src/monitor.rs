@@ -0,0 +1,372 @@
+//! # Debug monitor
+//!
+//! When the kernel panics, it drops into an interactive monitor instead of halting, so that the
+//! state it panicked in can be inspected. Commands are read from both the PS/2 keyboard and the
+//! `COM1` serial port, and output is written to both VGA and serial.
+//!
+//! The panic may have happened while any lock was held, so the monitor never locks anything: it
+//! accesses the keyboard, serial port and page tables directly.
+//!
+//! The monitor supports the following commands:
+//!
+//! | Command                | Description                                              |
+//! |------------------------|----------------------------------------------------------|
+//! | `help`                 | Lists the commands                                       |
+//! | `regs`                 | Prints the registers captured when the kernel panicked   |
+//! | `pt <address>`         | Walks the page tables for a virtual address              |
+//! | `hex <address> [len]`  | Hexdumps memory at a virtual address, if it is mapped    |
+//! | `sym <address>`        | Prints the kernel function containing an address         |
+//! | `bt`                   | Prints a backtrace from where the kernel panicked        |
+//! | `log`                  | Prints the log ring buffer                               |
+//! | `reboot`               | Reboots the machine                                      |
+//!
+//! Addresses and lengths are hexadecimal if prefixed with `0x`, and decimal otherwise.
+
+use arch::Registers;
+use core::fmt::{self, Write};
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use drivers::keyboard::keymap::{self, codes};
+use drivers::serial::{self, SerialPort};
+use io::Port;
+use log;
+use memory::PAGE_SIZE;
+use memory::paging::ActivePageTable;
+use power;
+use symbols;
+use terminal::{Stdout, TerminalOutput};
+use unwind::Backtrace;
+
+/// The maximum length of a command line
+const MAX_LINE: usize = 64;
+/// The amount of bytes hexdumped if no length is given
+const DEFAULT_DUMP_LENGTH: usize = 64;
+/// The maximum amount of bytes which can be hexdumped at once
+const MAX_DUMP_LENGTH: usize = 1024;
+
+/// Set once the monitor is entered, so that a panic within it halts instead of recursing
+static ENTERED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Enters the monitor, which runs until the machine is rebooted. If the monitor has already been
+/// entered, this halts instead.
+pub fn enter(registers: Registers, vga: Stdout) -> ! {
+    if ENTERED.swap(true, Ordering::SeqCst) {
+        ::halt();
+    }
+
+    // Safe because the monitor never returns, so nothing else will use the port again
+    let mut serial = unsafe { SerialPort::new(serial::COM1_BASE) };
+    serial.init();
+
+    let mut monitor = Monitor {
+        console: Console { vga, serial },
+        keyboard: KeyboardInput::new(),
+        registers,
+    };
+
+    monitor.run()
+}
+
+/// The monitor's state
+struct Monitor<'a> {
+    console: Console<'a>,
+    keyboard: KeyboardInput,
+    registers: Registers,
+}
+
+impl<'a> Monitor<'a> {
+    fn run(&mut self) -> ! {
+        let _ = write!(self.console, "Entered debug monitor, type `help` for commands\n");
+
+        loop {
+            let mut buffer = [0u8; MAX_LINE];
+            let length = self.read_line(&mut buffer);
+
+            match str::from_utf8(&buffer[..length]) {
+                Ok(line) => self.execute(line),
+                Err(_) => {
+                    let _ = write!(self.console, "Invalid input\n");
+                }
+            }
+        }
+    }
+
+    /// Reads a line into the buffer, returning its length
+    fn read_line(&mut self, buffer: &mut [u8; MAX_LINE]) -> usize {
+        let _ = write!(self.console, "> ");
+        let mut length = 0;
+
+        loop {
+            let byte = match self.read_byte() {
+                Some(byte) => byte,
+                None => continue,
+            };
+
+            match byte {
+                b'\r' | b'\n' => {
+                    let _ = write!(self.console, "\n");
+                    return length;
+                }
+                0x08 | 0x7F if length > 0 => {
+                    length -= 1;
+                    self.console.backspace();
+                }
+                0x20...0x7E if length < MAX_LINE => {
+                    buffer[length] = byte;
+                    length += 1;
+                    let _ = self.console.write_char(byte as char);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.keyboard.read_char()
+            .map(|character| character as u8)
+            .or_else(|| self.console.serial.read_byte())
+    }
+
+    fn execute(&mut self, line: &str) {
+        let mut args = line.split_whitespace();
+
+        let result = match args.next() {
+            Some("help") => self.help(),
+            Some("regs") => self.regs(),
+            Some("pt") => self.page_table(args.next()),
+            Some("hex") => self.hexdump(args.next(), args.next()),
+            Some("sym") => self.symbol(args.next()),
+            Some("bt") => write!(self.console, "{}", Backtrace::new(self.registers.frame)),
+            Some("log") => self.log(),
+            Some("reboot") => power::reboot(),
+            Some(command) => write!(self.console, "Unknown command `{}`\n", command),
+            None => Ok(()),
+        };
+
+        // Ignore error, as there is nowhere else to report it
+        let _ = result;
+    }
+
+    fn help(&mut self) -> fmt::Result {
+        write!(self.console, "help                 lists commands\n")?;
+        write!(self.console, "regs                 prints registers at panic\n")?;
+        write!(self.console, "pt <address>         walks the page tables for an address\n")?;
+        write!(self.console, "hex <address> [len]  hexdumps memory\n")?;
+        write!(self.console, "sym <address>        prints the function containing an address\n")?;
+        write!(self.console, "bt                   prints a backtrace from the panic\n")?;
+        write!(self.console, "log                  prints the log buffer\n")?;
+        write!(self.console, "reboot               reboots the machine\n")
+    }
+
+    fn regs(&mut self) -> fmt::Result {
+        write!(self.console, "{}", self.registers)
+    }
+
+    fn page_table(&mut self, address: Option<&str>) -> fmt::Result {
+        let address = match address.and_then(parse_number) {
+            Some(address) => address,
+            None => return write!(self.console, "Usage: pt <address>\n"),
+        };
+
+        // Safe because the monitor only reads the tables
+        let table = unsafe { ActivePageTable::unlocked() };
+
+        match table.translate_with_flags(address) {
+            Some((physical, flags)) => write!(self.console, "{:#x} -> {:#x} {:?}\n", address, physical, flags),
+            None => write!(self.console, "{:#x} is not mapped\n", address),
+        }
+    }
+
+    fn hexdump(&mut self, address: Option<&str>, length: Option<&str>) -> fmt::Result {
+        let address = match address.and_then(parse_number) {
+            Some(address) => address,
+            None => return write!(self.console, "Usage: hex <address> [len]\n"),
+        };
+
+        let length = length.and_then(parse_number).unwrap_or(DEFAULT_DUMP_LENGTH);
+        let length = if length > MAX_DUMP_LENGTH { MAX_DUMP_LENGTH } else { length };
+
+        if !is_mapped(address, length) {
+            return write!(self.console, "{:#x}..{:#x} is not fully mapped\n", address, address + length);
+        }
+
+        let mut line = address;
+        while line < address + length {
+            let end = if line + 16 > address + length { address + length } else { line + 16 };
+            let bytes = unsafe { ::core::slice::from_raw_parts(line as *const u8, end - line) };
+
+            write!(self.console, "{:016x} ", line)?;
+
+            for byte in bytes {
+                write!(self.console, " {:02x}", byte)?;
+            }
+
+            for _ in bytes.len()..16 {
+                write!(self.console, "   ")?;
+            }
+
+            write!(self.console, "  ")?;
+
+            for &byte in bytes {
+                let character = if byte >= 0x20 && byte < 0x7F { byte as char } else { '.' };
+                self.console.write_char(character)?;
+            }
+
+            write!(self.console, "\n")?;
+            line = end;
+        }
+
+        Ok(())
+    }
+
+    fn symbol(&mut self, address: Option<&str>) -> fmt::Result {
+        let address = match address.and_then(parse_number) {
+            Some(address) => address,
+            None => return write!(self.console, "Usage: sym <address>\n"),
+        };
+
+        match symbols::resolve(address) {
+            Some((symbol, offset)) => write!(self.console, "{:#x}: {}+{:#x}\n", address, symbol.name, offset),
+            None => write!(self.console, "{:#x}: not in a kernel function\n", address),
+        }
+    }
+
+    fn log(&mut self) -> fmt::Result {
+        let buffer = match log::LOG_BUFFER.try_lock() {
+            Some(buffer) => buffer,
+            None => return write!(self.console, "The log buffer was in use when the kernel panicked\n"),
+        };
+        let (older, newer) = buffer.contents();
+
+        for &byte in older.iter().chain(newer.iter()) {
+            let character = if byte == b'\n' || (byte >= 0x20 && byte < 0x7F) { byte as char } else { '?' };
+            self.console.write_char(character)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Output to both VGA and serial
+struct Console<'a> {
+    vga: Stdout<'a>,
+    serial: SerialPort,
+}
+
+impl<'a> Console<'a> {
+    fn backspace(&mut self) {
+        let _ = self.vga.backspace();
+        let _ = self.serial.write_str("\x08 \x08");
+    }
+}
+
+impl<'a> fmt::Write for Console<'a> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        // Ignore VGA errors, so that output still reaches serial
+        let _ = self.vga.write_str(string);
+        self.serial.write_str(string)
+    }
+}
+
+/// Polled input from the PS/2 keyboard, decoding scancode set 2 without going through the locked
+/// PS/2 driver
+struct KeyboardInput {
+    status: Port<u8>,
+    data: Port<u8>,
+    extended: bool,
+    release: bool,
+    shift: bool,
+}
+
+impl KeyboardInput {
+    fn new() -> Self {
+        // Safe because the monitor never returns, so the PS/2 driver will not use the ports again
+        unsafe {
+            KeyboardInput {
+                status: Port::new(0x64),
+                data: Port::new(0x60),
+                extended: false,
+                release: false,
+                shift: false,
+            }
+        }
+    }
+
+    /// Reads a single character, or returns `None` if no key which produces one was pressed
+    fn read_char(&mut self) -> Option<char> {
+        let status = self.status.read();
+
+        // No data is available
+        if status & 0x01 == 0 {
+            return None;
+        }
+
+        let data = self.data.read();
+
+        // Data from the mouse port is discarded
+        if status & 0x20 != 0 {
+            return None;
+        }
+
+        match data {
+            0xE0 | 0xE1 => {
+                self.extended = true;
+                None
+            }
+            0xF0 => {
+                self.release = true;
+                None
+            }
+            code => {
+                let keycode = if self.extended {
+                    keymap::get_extended_code_ps2_set_2(code)
+                } else {
+                    keymap::get_code_ps2_set_2(code)
+                };
+
+                let release = self.release;
+                self.extended = false;
+                self.release = false;
+
+                match keycode? {
+                    codes::LEFT_SHIFT | codes::RIGHT_SHIFT => {
+                        self.shift = !release;
+                        None
+                    }
+                    _ if release => None,
+                    keycode => keymap::get_us_qwerty_char(keycode)
+                        .map(|chars| if self.shift { chars.1 } else { chars.0 }),
+                }
+            }
+        }
+    }
+}
+
+/// Parses a number which is hexadecimal if prefixed with `0x`, and decimal otherwise
+fn parse_number(string: &str) -> Option<usize> {
+    if string.starts_with("0x") {
+        usize::from_str_radix(&string[2..], 16).ok()
+    } else {
+        usize::from_str_radix(string, 10).ok()
+    }
+}
+
+/// Returns `true` if every page in the given range is mapped
+fn is_mapped(address: usize, length: usize) -> bool {
+    // Safe because the monitor only reads the tables
+    let table = unsafe { ActivePageTable::unlocked() };
+    let end = match address.checked_add(length) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    let mut page = address - address % PAGE_SIZE;
+    while page < end {
+        if table.translate(page).is_none() {
+            return false;
+        }
+        page += PAGE_SIZE;
+    }
+
+    true
+}
@@ -0,0 +1,376 @@
+//! # Multiboot 2
+//!
+//! Parses the boot information structure passed to the kernel by a multiboot 2 compliant
+//! bootloader, such as GRUB. The structure is a list of tags, each of which describe something
+//! about the machine or how the kernel was loaded.
+//!
+//! The boot information is accessed through the physical memory mapping, and is never freed, so
+//! references into it are `'static`.
+
+use memory;
+
+/// The type of the tag which ends the tag list
+const END_TAG: u32 = 0;
+/// The type of the boot command line tag
+const COMMAND_LINE_TAG: u32 = 1;
+/// The type of the tag describing a boot module
+const MODULE_TAG: u32 = 3;
+/// The type of the memory map tag
+const MEMORY_MAP_TAG: u32 = 6;
+/// The type of the framebuffer information tag
+const FRAMEBUFFER_TAG: u32 = 8;
+/// The type of the tag holding a copy of the ACPI 1.0 RSDP
+const OLD_ACPI_TAG: u32 = 14;
+/// The type of the tag holding a copy of the ACPI 2.0 or later RSDP
+const NEW_ACPI_TAG: u32 = 15;
+
+/// The framebuffer type of a direct RGB framebuffer
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+/// The fixed header at the start of the boot information
+#[allow(dead_code)] // Fields required for layout
+#[repr(C)]
+struct InfoHeader {
+    total_size: u32,
+    reserved: u32,
+}
+
+/// The header of a boot information tag
+#[repr(C)]
+struct TagHeader {
+    tag_type: u32,
+    size: u32,
+}
+
+/// A boot module tag, which is followed by the module's null terminated command line
+#[repr(C)]
+struct ModuleTag {
+    header: TagHeader,
+    start: u32,
+    end: u32,
+}
+
+/// The framebuffer information tag, with the color information of an RGB framebuffer
+#[allow(dead_code)] // Fields required for layout
+#[repr(C)]
+struct FramebufferTag {
+    header: TagHeader,
+    address: u64,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    bpp: u8,
+    framebuffer_type: u8,
+    reserved: u16,
+    red_position: u8,
+    red_size: u8,
+    green_position: u8,
+    green_size: u8,
+    blue_position: u8,
+    blue_size: u8,
+}
+
+/// The header of the memory map tag, which is followed by the memory areas
+#[allow(dead_code)] // Fields required for layout
+#[repr(C)]
+struct MemoryMapTag {
+    header: TagHeader,
+    entry_size: u32,
+    entry_version: u32,
+}
+
+/// The multiboot 2 boot information structure
+pub struct BootInfo {
+    address: usize,
+}
+
+impl BootInfo {
+    /// Loads the boot information at the given physical address
+    ///
+    /// # Safety
+    ///
+    /// The address must be that passed by the bootloader
+    pub unsafe fn load(address: usize) -> Self {
+        BootInfo { address }
+    }
+
+    /// The physical address of the start of the boot information
+    pub fn start_address(&self) -> usize {
+        self.address
+    }
+
+    /// The physical address of the end of the boot information
+    pub fn end_address(&self) -> usize {
+        self.address + self.header().total_size as usize
+    }
+
+    /// Gets the memory areas reported by the bootloader
+    pub fn memory_areas(&self) -> Option<MemoryAreaIter> {
+        self.tag(MEMORY_MAP_TAG).map(|address| {
+            let tag = unsafe { &*(address as *const MemoryMapTag) };
+
+            MemoryAreaIter {
+                current: address + ::core::mem::size_of::<MemoryMapTag>(),
+                end: address + tag.header.size as usize,
+                entry_size: tag.entry_size as usize,
+            }
+        })
+    }
+
+    /// Gets the command line the kernel was booted with, if the bootloader passed one and it is
+    /// valid UTF-8
+    pub fn command_line(&self) -> Option<&'static str> {
+        self.tag(COMMAND_LINE_TAG).and_then(|address| {
+            let tag = unsafe { &*(address as *const TagHeader) };
+            tag_string(address + ::core::mem::size_of::<TagHeader>(), address + tag.size as usize)
+        })
+    }
+
+    /// Gets the linear framebuffer set up by the bootloader, if it set up a direct RGB one. This is
+    /// not the case in VGA text mode.
+    pub fn framebuffer(&self) -> Option<FramebufferInfo> {
+        let address = self.tag(FRAMEBUFFER_TAG)?;
+        let tag = unsafe { &*(address as *const FramebufferTag) };
+
+        if tag.framebuffer_type != FRAMEBUFFER_TYPE_RGB {
+            return None;
+        }
+
+        Some(FramebufferInfo {
+            address: tag.address as usize,
+            pitch: tag.pitch as usize,
+            width: tag.width as usize,
+            height: tag.height as usize,
+            bpp: tag.bpp,
+            red: ColorField { position: tag.red_position, size: tag.red_size },
+            green: ColorField { position: tag.green_position, size: tag.green_size },
+            blue: ColorField { position: tag.blue_position, size: tag.blue_size },
+        })
+    }
+
+    /// Gets the bootloader's copy of the ACPI root system description pointer, preferring the
+    /// ACPI 2.0 version if both are present
+    pub fn rsdp(&self) -> Option<&'static [u8]> {
+        let address = self.tag(NEW_ACPI_TAG).or_else(|| self.tag(OLD_ACPI_TAG))?;
+        let tag = unsafe { &*(address as *const TagHeader) };
+        let start = address + ::core::mem::size_of::<TagHeader>();
+
+        Some(unsafe { ::core::slice::from_raw_parts(start as *const u8, tag.size as usize - (start - address)) })
+    }
+
+    /// Gets the modules loaded by the bootloader alongside the kernel, such as an initial ramdisk
+    pub fn modules(&self) -> ModuleIter {
+        ModuleIter { tags: self.tags() }
+    }
+
+    fn header(&self) -> &InfoHeader {
+        unsafe { &*(memory::phys_to_virt(self.address) as *const InfoHeader) }
+    }
+
+    /// Finds the virtual address of the first tag with the given type
+    fn tag(&self, tag_type: u32) -> Option<usize> {
+        self.tags()
+            .find(|&(found, _)| found == tag_type)
+            .map(|(_, address)| address)
+    }
+
+    fn tags(&self) -> TagIter {
+        TagIter {
+            current: memory::phys_to_virt(self.address) + ::core::mem::size_of::<InfoHeader>(),
+        }
+    }
+}
+
+/// An iterator over the type and virtual address of each tag
+#[derive(Clone)]
+struct TagIter {
+    current: usize,
+}
+
+impl Iterator for TagIter {
+    type Item = (u32, usize);
+
+    fn next(&mut self) -> Option<(u32, usize)> {
+        let tag = unsafe { &*(self.current as *const TagHeader) };
+
+        if tag.tag_type == END_TAG {
+            return None;
+        }
+
+        let address = self.current;
+
+        // Tags are padded to be 8 byte aligned
+        self.current += (tag.size as usize + 7) & !7;
+
+        Some((tag.tag_type, address))
+    }
+}
+
+/// Reads the null terminated string at the given virtual address, which must lie within a tag
+/// that ends at `end`. Returns `None` if it is not valid UTF-8.
+fn tag_string(start: usize, end: usize) -> Option<&'static str> {
+    let bytes = unsafe { ::core::slice::from_raw_parts(start as *const u8, end - start) };
+    let length = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    ::core::str::from_utf8(&bytes[..length]).ok()
+}
+
+/// The position and size in bits of a color channel within a framebuffer pixel
+#[derive(Copy, Clone, Debug)]
+pub struct ColorField {
+    pub position: u8,
+    pub size: u8,
+}
+
+/// A direct RGB linear framebuffer set up by the bootloader
+#[derive(Copy, Clone, Debug)]
+pub struct FramebufferInfo {
+    /// The physical address of the framebuffer
+    pub address: usize,
+    /// The amount of bytes between the start of each row
+    pub pitch: usize,
+    pub width: usize,
+    pub height: usize,
+    /// The amount of bits per pixel
+    pub bpp: u8,
+    pub red: ColorField,
+    pub green: ColorField,
+    pub blue: ColorField,
+}
+
+/// A module loaded by the bootloader
+pub struct Module {
+    start: usize,
+    end: usize,
+    command_line: &'static str,
+}
+
+impl Module {
+    /// The physical address of the start of this module
+    pub fn start_address(&self) -> usize {
+        self.start
+    }
+
+    /// The physical address of the end of this module (exclusive)
+    pub fn end_address(&self) -> usize {
+        self.end
+    }
+
+    /// The command line given to this module by the bootloader, conventionally used as its name
+    pub fn command_line(&self) -> &'static str {
+        self.command_line
+    }
+
+    /// Gets the contents of this module
+    pub fn data(&self) -> &'static [u8] {
+        unsafe {
+            ::core::slice::from_raw_parts(memory::phys_to_virt(self.start) as *const u8, self.end - self.start)
+        }
+    }
+}
+
+/// An iterator over the modules loaded by the bootloader
+pub struct ModuleIter {
+    tags: TagIter,
+}
+
+impl Iterator for ModuleIter {
+    type Item = Module;
+
+    fn next(&mut self) -> Option<Module> {
+        let address = self.tags
+            .find(|&(tag_type, _)| tag_type == MODULE_TAG)
+            .map(|(_, address)| address)?;
+        let tag = unsafe { &*(address as *const ModuleTag) };
+
+        let string_start = address + ::core::mem::size_of::<ModuleTag>();
+        let string_end = address + tag.header.size as usize;
+
+        Some(Module {
+            start: tag.start as usize,
+            end: tag.end as usize,
+            command_line: tag_string(string_start, string_end).unwrap_or(""),
+        })
+    }
+}
+
+/// The type of a memory area, as reported by the firmware
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MemoryAreaType {
+    /// RAM available for use
+    Available,
+    /// ACPI tables, which may be reused once they have been read
+    AcpiReclaimable,
+    /// Memory which the firmware requires to be preserved across sleep states
+    AcpiNvs,
+    /// RAM which is defective
+    Defective,
+    /// Memory reserved by the firmware, including device memory
+    Reserved,
+}
+
+/// A physical memory area reported by the bootloader
+#[allow(dead_code)] // Fields required for layout
+#[derive(Debug)]
+#[repr(C)]
+pub struct MemoryArea {
+    base_address: u64,
+    length: u64,
+    area_type: u32,
+    reserved: u32,
+}
+
+impl MemoryArea {
+    /// The physical address of the start of this area
+    pub fn start_address(&self) -> usize {
+        self.base_address as usize
+    }
+
+    /// The physical address of the end of this area (exclusive)
+    pub fn end_address(&self) -> usize {
+        (self.base_address + self.length) as usize
+    }
+
+    /// The size of this area in bytes
+    pub fn size(&self) -> usize {
+        self.length as usize
+    }
+
+    /// Returns `true` if this area is RAM available for use
+    pub fn is_available(&self) -> bool {
+        self.area_type() == MemoryAreaType::Available
+    }
+
+    /// The type of this area. Unknown types are treated as reserved.
+    pub fn area_type(&self) -> MemoryAreaType {
+        match self.area_type {
+            1 => MemoryAreaType::Available,
+            3 => MemoryAreaType::AcpiReclaimable,
+            4 => MemoryAreaType::AcpiNvs,
+            5 => MemoryAreaType::Defective,
+            _ => MemoryAreaType::Reserved,
+        }
+    }
+}
+
+/// An iterator over the memory areas in the memory map tag
+#[derive(Clone)]
+pub struct MemoryAreaIter {
+    current: usize,
+    end: usize,
+    entry_size: usize,
+}
+
+impl Iterator for MemoryAreaIter {
+    type Item = &'static MemoryArea;
+
+    fn next(&mut self) -> Option<&'static MemoryArea> {
+        if self.current >= self.end {
+            return None;
+        }
+
+        let area = unsafe { &*(self.current as *const MemoryArea) };
+        self.current += self.entry_size;
+
+        Some(area)
+    }
+}
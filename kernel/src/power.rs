@@ -0,0 +1,109 @@
+//! Power management, handling reboot and shutdown of the machine
+
+use acpi::{self, ResetRegister};
+use core::ptr;
+use drivers::ps2::io::commands::{self, ControllerCommand};
+use io::Port;
+
+/// The PM1 control bit set when the machine is in ACPI mode
+const PM1_SCI_ENABLE: u16 = 1 << 0;
+/// The PM1 control bit which enters the sleep state given by the sleep type
+const PM1_SLEEP_ENABLE: u16 = 1 << 13;
+/// The position of the sleep type field in the PM1 control register
+const PM1_SLEEP_TYPE_SHIFT: u16 = 10;
+/// The mask of the sleep type field in the PM1 control register
+const PM1_SLEEP_TYPE_MASK: u16 = 0b111 << PM1_SLEEP_TYPE_SHIFT;
+/// The amount of times the PM1 control register is polled while waiting for ACPI mode
+const ACPI_ENABLE_POLLS: usize = 1_000_000;
+
+/// Reboots the machine, first through the ACPI reset register if the FADT defines one, then by
+/// pulsing the reset line through the PS/2 controller, and then by triple faulting if both fail
+pub fn reboot() -> ! {
+    info!("power: rebooting");
+
+    unsafe { asm!("cli" :::: "volatile"); }
+
+    if let Some(reset) = acpi::reset() {
+        unsafe { acpi_reset(reset) }
+    }
+
+    // Ignore the error, as we fall back to triple faulting anyway
+    let _ = commands::send(ControllerCommand::PulseReset);
+
+    warn!("power: reset line pulse failed, triple faulting");
+    unsafe { triple_fault() }
+}
+
+/// Shuts down the machine through ACPI if the sleep values were found in the ACPI tables, and
+/// through emulator specific ports otherwise. Halts if both fail.
+pub fn shutdown() -> ! {
+    info!("power: shutting down");
+
+    unsafe { asm!("cli" :::: "volatile"); }
+
+    match acpi::power_off() {
+        Some(power_off) => unsafe { acpi_power_off(power_off) },
+        None => warn!("power: no acpi power off values"),
+    }
+
+    unsafe {
+        // QEMU (newer versions)
+        Port::<u16>::new(0x604).write(0x2000);
+        // Bochs & QEMU (older versions)
+        Port::<u16>::new(0xB004).write(0x2000);
+        // VirtualBox
+        Port::<u16>::new(0x4004).write(0x3400);
+    }
+
+    warn!("power: shutdown unsupported, halting");
+    ::halt()
+}
+
+/// Enters the S5 soft off sleep state. This returns if the machine is still running afterwards.
+unsafe fn acpi_power_off(power_off: acpi::PowerOff) {
+    let mut pm1a = Port::<u16>::new(power_off.pm1a_control);
+
+    if pm1a.read() & PM1_SCI_ENABLE == 0 && power_off.smi_command != 0 {
+        Port::<u8>::new(power_off.smi_command).write(power_off.acpi_enable);
+
+        let mut polls = 0;
+        while pm1a.read() & PM1_SCI_ENABLE == 0 && polls < ACPI_ENABLE_POLLS {
+            polls += 1;
+        }
+    }
+
+    enter_sleep_state(&mut pm1a, power_off.sleep_type_a);
+
+    if power_off.pm1b_control != 0 {
+        enter_sleep_state(&mut Port::<u16>::new(power_off.pm1b_control), power_off.sleep_type_b);
+    }
+
+    warn!("power: acpi power off failed");
+}
+
+/// Enters the given sleep type through a PM1 control register, keeping its other bits, such as
+/// `PM1_SCI_ENABLE`
+fn enter_sleep_state(pm1: &mut Port<u16>, sleep_type: u8) {
+    let control = pm1.read() & !PM1_SLEEP_TYPE_MASK;
+    pm1.write(control | ((sleep_type as u16) << PM1_SLEEP_TYPE_SHIFT & PM1_SLEEP_TYPE_MASK) | PM1_SLEEP_ENABLE);
+}
+
+/// Writes the reset value to the ACPI reset register. This returns if the machine is still running
+/// afterwards.
+unsafe fn acpi_reset(reset: acpi::Reset) {
+    match reset.register {
+        ResetRegister::Io(port) => Port::<u8>::new(port).write(reset.value),
+        ResetRegister::Memory(address) => ptr::write_volatile(address as *mut u8, reset.value),
+    }
+
+    warn!("power: acpi reset failed");
+}
+
+/// Resets the CPU by loading an empty IDT and raising an interrupt
+unsafe fn triple_fault() -> ! {
+    let null_idt = [0u8; 10];
+    asm!("lidt ($0)
+          int3" :: "r"(&null_idt) : "memory" : "volatile");
+
+    ::halt()
+}
@@ -0,0 +1,29 @@
+//! # QEMU
+//!
+//! Support for devices only present when running under QEMU. The isa-debug-exit device lets the
+//! kernel exit QEMU with a status code, which is used to report integration test results. It must be
+//! enabled with `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
+
+use io::Port;
+
+/// The port of the isa-debug-exit device
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+
+/// A status to exit QEMU with. QEMU exits with `(code << 1) | 1`, so that a successful exit can be
+/// told apart from QEMU itself failing.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(u32)]
+pub enum ExitCode {
+    /// QEMU exits with status 33
+    Success = 0x10,
+    /// QEMU exits with status 35
+    Failure = 0x11,
+}
+
+/// Exits QEMU with the given status. If the isa-debug-exit device is not present, this halts instead.
+pub fn exit(code: ExitCode) -> ! {
+    unsafe { Port::<u32>::new(DEBUG_EXIT_PORT).write(code as u32) };
+
+    warn!("qemu: isa-debug-exit not present, halting");
+    ::halt()
+}
@@ -0,0 +1,201 @@
+//! # Random Number Generation
+//!
+//! Provides cryptographically secure random numbers for things such as stack canaries, ASLR and
+//! network sequence numbers, through `rand::u64`.
+//!
+//! Entropy is gathered from `rdseed`/`rdrand` when `cpuid` reports them, and from timing jitter
+//! which is added through `add_timing_entropy` by event sources such as interrupts. This is mixed
+//! into the key of a ChaCha20 generator, which is rekeyed with its own output after every use so
+//! that previous outputs cannot be recovered from its state.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let canary = rand::u64();
+//! ```
+
+use arch;
+use arch::cpuid::{self, Features};
+use sync::IrqLock;
+
+/// The amount of times to retry `rdrand`/`rdseed` before giving up
+const HARDWARE_RETRIES: usize = 10;
+
+/// The ChaCha constant, "expand 32-byte k"
+const CHACHA_CONSTANT: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+/// Locked with interrupts disabled, as entropy is added from interrupt handlers
+static POOL: IrqLock<EntropyPool> = IrqLock::new(EntropyPool::new());
+
+/// Seeds the generator, and logs the hardware entropy source used
+pub fn init() {
+    let source = if cpuid::has(Features::RDSEED) {
+        "rdseed"
+    } else if cpuid::has(Features::RDRAND) {
+        "rdrand"
+    } else {
+        "timing jitter only"
+    };
+
+    add_timing_entropy();
+    POOL.lock().reseed();
+
+    info!("rand: seeded from {}", source);
+}
+
+/// Returns a random `u64`
+#[allow(dead_code)] // Part of API
+pub fn u64() -> u64 {
+    let mut pool = POOL.lock();
+    pool.reseed();
+    pool.next()
+}
+
+/// Mixes the current time stamp counter into the entropy pool. This should be called from
+/// events which happen at unpredictable times, such as interrupts.
+pub fn add_timing_entropy() {
+    add_entropy(arch::rdtsc());
+}
+
+/// Mixes the given value into the entropy pool
+pub fn add_entropy(value: u64) {
+    POOL.lock().mix(value);
+}
+
+/// A ChaCha20 based generator and entropy accumulator
+struct EntropyPool {
+    key: [u32; 8],
+    counter: u64,
+    accumulator: u64,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        EntropyPool {
+            key: [0; 8],
+            counter: 0,
+            accumulator: 0,
+        }
+    }
+
+    /// Mixes the given value into the accumulator
+    fn mix(&mut self, value: u64) {
+        self.accumulator = (self.accumulator.rotate_left(7) ^ value).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+
+    /// Mixes the accumulated and hardware entropy into the key
+    fn reseed(&mut self) {
+        let timing = arch::rdtsc();
+        self.mix(timing);
+
+        let accumulated = self.accumulator;
+        self.key[0] ^= accumulated as u32;
+        self.key[1] ^= (accumulated >> 32) as u32;
+
+        if let Some(hardware) = hardware_entropy() {
+            self.key[2] ^= hardware as u32;
+            self.key[3] ^= (hardware >> 32) as u32;
+        }
+    }
+
+    /// Generates the next random value, rekeying the generator with the rest of the block
+    fn next(&mut self) -> u64 {
+        let block = self.block();
+        self.counter = self.counter.wrapping_add(1);
+
+        self.key.copy_from_slice(&block[8..16]);
+
+        (block[1] as u64) << 32 | block[0] as u64
+    }
+
+    /// Computes the ChaCha20 block for the current key and counter
+    fn block(&self) -> [u32; 16] {
+        let mut initial = [0u32; 16];
+        initial[0..4].copy_from_slice(&CHACHA_CONSTANT);
+        initial[4..12].copy_from_slice(&self.key);
+        initial[12] = self.counter as u32;
+        initial[13] = (self.counter >> 32) as u32;
+
+        let mut state = initial;
+        for _ in 0..10 {
+            // Column rounds
+            quarter_round(&mut state, 0, 4, 8, 12);
+            quarter_round(&mut state, 1, 5, 9, 13);
+            quarter_round(&mut state, 2, 6, 10, 14);
+            quarter_round(&mut state, 3, 7, 11, 15);
+
+            // Diagonal rounds
+            quarter_round(&mut state, 0, 5, 10, 15);
+            quarter_round(&mut state, 1, 6, 11, 12);
+            quarter_round(&mut state, 2, 7, 8, 13);
+            quarter_round(&mut state, 3, 4, 9, 14);
+        }
+
+        for (word, initial) in state.iter_mut().zip(initial.iter()) {
+            *word = word.wrapping_add(*initial);
+        }
+
+        state
+    }
+}
+
+/// The ChaCha quarter round
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Gets entropy from `rdseed`, falling back to `rdrand`, if either are supported
+fn hardware_entropy() -> Option<u64> {
+    if cpuid::has(Features::RDSEED) {
+        if let Some(seed) = rdseed() {
+            return Some(seed);
+        }
+    }
+
+    if cpuid::has(Features::RDRAND) {
+        return rdrand();
+    }
+
+    None
+}
+
+/// Executes `rdrand`, retrying if no value is available
+fn rdrand() -> Option<u64> {
+    for _ in 0..HARDWARE_RETRIES {
+        let (value, success): (u64, u8);
+        unsafe {
+            asm!("rdrand $0
+                  setc $1" : "=r"(value), "=r"(success) :: "cc" : "volatile");
+        }
+
+        if success != 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Executes `rdseed`, retrying if no value is available
+fn rdseed() -> Option<u64> {
+    for _ in 0..HARDWARE_RETRIES {
+        let (value, success): (u64, u8);
+        unsafe {
+            asm!("rdseed $0
+                  setc $1" : "=r"(value), "=r"(success) :: "cc" : "volatile");
+        }
+
+        if success != 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
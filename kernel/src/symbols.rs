@@ -0,0 +1,94 @@
+//! # Kernel symbols
+//!
+//! Resolves addresses within the kernel to the functions containing them, for the debug monitor
+//! and for printing backtraces. The symbol table is generated at build time from a first link of
+//! the kernel by `cfg/symbols.sh`, and linked into the `.symbols` section in the second. It holds
+//! the demangled name, address and size of every function, sorted by address.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! if let Some((symbol, offset)) = symbols::resolve(address) {
+//!     println!("{:#x} is in {}+{:#x}", address, symbol.name, offset);
+//! }
+//! ```
+
+use core::{mem, slice, str};
+
+#[allow(non_upper_case_globals)]
+extern {
+    /// The start of the symbol table, provided by the linker
+    static symbols_start: u8;
+    /// The end of the symbol table, provided by the linker
+    static symbols_end: u8;
+}
+
+/// An entry of the symbol table, as written by `symbols.sh`
+#[repr(C)]
+struct RawSymbol {
+    address: usize,
+    size: usize,
+    name: *const u8,
+    name_length: usize,
+}
+
+/// A function in the kernel
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Symbol {
+    /// The demangled name of the function
+    pub name: &'static str,
+    pub address: usize,
+    /// The size of the function in bytes
+    pub size: usize,
+}
+
+/// Gets the function containing the given address, and the offset of the address into it
+pub fn resolve(address: usize) -> Option<(Symbol, usize)> {
+    let table = table();
+
+    // The containing function is the last one starting at or before the address
+    let index = match table.binary_search_by(|symbol| symbol.address.cmp(&address)) {
+        Ok(index) => index,
+        Err(0) => return None,
+        Err(index) => index - 1,
+    };
+
+    let raw = &table[index];
+    let offset = address - raw.address;
+    if offset >= raw.size {
+        return None;
+    }
+
+    let name = unsafe { slice::from_raw_parts(raw.name, raw.name_length) };
+
+    Some((
+        Symbol {
+            name: str::from_utf8(name).unwrap_or("<invalid name>"),
+            address: raw.address,
+            size: raw.size,
+        },
+        offset,
+    ))
+}
+
+/// Gets the symbol table, which is empty if the kernel was linked without one
+fn table() -> &'static [RawSymbol] {
+    unsafe {
+        let start = &symbols_start as *const u8 as usize;
+        let end = &symbols_end as *const u8 as usize;
+        let count = (end - start) / mem::size_of::<RawSymbol>();
+
+        slice::from_raw_parts(start as *const RawSymbol, count)
+    }
+}
+
+kernel_test!(fn resolves_kernel_functions() {
+    let address = resolve as usize;
+
+    let (symbol, offset) = resolve(address + 1).ok_or("Function not in symbol table")?;
+    test_assert!(symbol.name.contains("symbols::resolve"));
+    test_assert_eq!(symbol.address, address);
+    test_assert_eq!(offset, 1);
+
+    test_assert!(resolve(0).is_none());
+});
@@ -0,0 +1,293 @@
+//! # Synchronization primitives
+//!
+//! `IrqLock` is a spin lock which disables interrupts while it is held. Data shared with interrupt
+//! handlers must be protected by one, as otherwise an interrupt arriving while the lock is held
+//! would spin forever on it.
+//!
+//! `Mutex` is a plain spin lock, like `spin::Mutex`. Both lock types keep `LockStats` on how they
+//! are used when the kernel is built with the `lock-stats` feature, so that contended locks can be
+//! found. Without the feature, the statistics stay zero and cost nothing to keep.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! static COUNTER: IrqLock<usize> = IrqLock::new(0);
+//!
+//! fn irq_handler(_irq: u8) -> bool {
+//!     *COUNTER.lock() += 1;
+//!     true
+//! }
+//! ```
+
+use arch;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin;
+
+/// Statistics on how a lock is used, kept when the kernel is built with the `lock-stats` feature
+pub struct LockStats {
+    acquisitions: AtomicUsize,
+    contended: AtomicUsize,
+    max_hold_cycles: AtomicUsize,
+}
+
+/// A copy of a lock's statistics at one point in time
+#[derive(Copy, Clone, Debug)]
+pub struct LockStatsSnapshot {
+    /// The amount of times the lock was acquired
+    pub acquisitions: usize,
+    /// The amount of acquisitions which had to wait for the lock to be released
+    pub contended: usize,
+    /// The longest time the lock was held for, in TSC cycles
+    pub max_hold_cycles: usize,
+}
+
+impl LockStats {
+    const fn new() -> Self {
+        LockStats {
+            acquisitions: AtomicUsize::new(0),
+            contended: AtomicUsize::new(0),
+            max_hold_cycles: AtomicUsize::new(0),
+        }
+    }
+
+    /// Gets a copy of these statistics
+    pub fn snapshot(&self) -> LockStatsSnapshot {
+        LockStatsSnapshot {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            max_hold_cycles: self.max_hold_cycles.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Locks the given mutex, counting the acquisition and whether it had to wait
+    #[cfg(feature = "lock-stats")]
+    fn acquire<'a, T>(&self, mutex: &'a spin::Mutex<T>) -> spin::MutexGuard<'a, T> {
+        let guard = match mutex.try_lock() {
+            Some(guard) => guard,
+            None => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                mutex.lock()
+            }
+        };
+
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        guard
+    }
+
+    #[cfg(not(feature = "lock-stats"))]
+    fn acquire<'a, T>(&self, mutex: &'a spin::Mutex<T>) -> spin::MutexGuard<'a, T> {
+        mutex.lock()
+    }
+
+    /// Tries to lock the given mutex, counting the acquisition if it succeeds
+    fn try_acquire<'a, T>(&self, mutex: &'a spin::Mutex<T>) -> Option<spin::MutexGuard<'a, T>> {
+        let guard = mutex.try_lock();
+
+        if cfg!(feature = "lock-stats") && guard.is_some() {
+            self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        guard
+    }
+
+    /// Gets the time at which a lock was acquired, to be passed to `release`
+    fn hold_start() -> u64 {
+        if cfg!(feature = "lock-stats") { arch::rdtsc() } else { 0 }
+    }
+
+    /// Records the time a lock acquired at `start` was held for
+    fn release(&self, start: u64) {
+        if !cfg!(feature = "lock-stats") {
+            return;
+        }
+
+        let cycles = arch::rdtsc().wrapping_sub(start) as usize;
+        let mut max = self.max_hold_cycles.load(Ordering::Relaxed);
+
+        while cycles > max {
+            let previous = self.max_hold_cycles.compare_and_swap(max, cycles, Ordering::Relaxed);
+            if previous == max {
+                break;
+            }
+            max = previous;
+        }
+    }
+}
+
+/// Logs the statistics of the given lock
+#[allow(dead_code)] // Part of API
+pub fn report(name: &str, stats: &LockStats) {
+    let stats = stats.snapshot();
+    info!(
+        "lock: {}: {} acquisitions, {} contended, held for at most {} cycles",
+        name, stats.acquisitions, stats.contended, stats.max_hold_cycles
+    );
+}
+
+/// A spin lock which disables interrupts while held, restoring them when released
+pub struct IrqLock<T> {
+    inner: spin::Mutex<T>,
+    stats: LockStats,
+}
+
+impl<T> IrqLock<T> {
+    pub const fn new(value: T) -> Self {
+        IrqLock { inner: spin::Mutex::new(value), stats: LockStats::new() }
+    }
+
+    /// Disables interrupts and locks, spinning until the lock is available
+    pub fn lock(&self) -> IrqLockGuard<T> {
+        let enabled = arch::disable_interrupts();
+        let guard = self.stats.acquire(&self.inner);
+
+        IrqLockGuard {
+            guard: Some(guard),
+            enabled,
+            stats: &self.stats,
+            start: LockStats::hold_start(),
+        }
+    }
+
+    /// Disables interrupts and locks, or returns `None` with interrupts restored if the lock is
+    /// already held
+    pub fn try_lock(&self) -> Option<IrqLockGuard<T>> {
+        let enabled = arch::disable_interrupts();
+
+        match self.stats.try_acquire(&self.inner) {
+            Some(guard) => Some(IrqLockGuard {
+                guard: Some(guard),
+                enabled,
+                stats: &self.stats,
+                start: LockStats::hold_start(),
+            }),
+            None => {
+                if enabled {
+                    arch::enable_interrupts();
+                }
+                None
+            }
+        }
+    }
+
+    /// Gets the statistics of this lock
+    #[allow(dead_code)] // Part of API
+    pub fn stats(&self) -> &LockStats {
+        &self.stats
+    }
+}
+
+/// A held `IrqLock`, which releases the lock and then restores interrupts when dropped
+pub struct IrqLockGuard<'a, T: 'a> {
+    /// Always `Some` until dropped, so that the lock can be released before interrupts are restored
+    guard: Option<spin::MutexGuard<'a, T>>,
+    /// If interrupts were enabled before locking
+    enabled: bool,
+    stats: &'a LockStats,
+    start: u64,
+}
+
+impl<'a, T> Deref for IrqLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<'a, T> DerefMut for IrqLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<'a, T> Drop for IrqLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.stats.release(self.start);
+        self.guard.take();
+
+        if self.enabled {
+            arch::enable_interrupts();
+        }
+    }
+}
+
+/// A spin lock, which keeps statistics when built with the `lock-stats` feature
+pub struct Mutex<T> {
+    inner: spin::Mutex<T>,
+    stats: LockStats,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex { inner: spin::Mutex::new(value), stats: LockStats::new() }
+    }
+
+    /// Locks, spinning until the lock is available
+    pub fn lock(&self) -> MutexGuard<T> {
+        MutexGuard {
+            guard: self.stats.acquire(&self.inner),
+            stats: &self.stats,
+            start: LockStats::hold_start(),
+        }
+    }
+
+    /// Locks, or returns `None` if the lock is already held
+    #[allow(dead_code)] // Part of API
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        self.stats.try_acquire(&self.inner).map(|guard| MutexGuard {
+            guard,
+            stats: &self.stats,
+            start: LockStats::hold_start(),
+        })
+    }
+
+    /// Gets the statistics of this lock
+    #[allow(dead_code)] // Part of API
+    pub fn stats(&self) -> &LockStats {
+        &self.stats
+    }
+}
+
+/// A held `Mutex`, which is released when dropped
+pub struct MutexGuard<'a, T: 'a> {
+    guard: spin::MutexGuard<'a, T>,
+    stats: &'a LockStats,
+    start: u64,
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &*self.guard
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.guard
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.stats.release(self.start);
+    }
+}
+
+kernel_test!(fn restores_interrupts() {
+    let lock = IrqLock::new(0);
+    let enabled = arch::interrupts_enabled();
+
+    {
+        let mut guard = lock.lock();
+        *guard += 1;
+        test_assert!(!arch::interrupts_enabled());
+        test_assert!(lock.try_lock().is_none());
+        test_assert!(!arch::interrupts_enabled());
+    }
+
+    test_assert_eq!(arch::interrupts_enabled(), enabled);
+    test_assert_eq!(*lock.lock(), 1);
+});
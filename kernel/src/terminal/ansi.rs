@@ -0,0 +1,225 @@
+//! # ANSI escape sequences
+//!
+//! Parses the ANSI escape sequences written to a terminal, one character at a time, so that
+//! programs can move the cursor, set a scroll region, and insert and delete lines by writing text.
+//! Sequences are executed with `TerminalOutput::execute`. Rows and columns in sequences are counted
+//! from 1 at the top left, as in a VT100.
+//!
+//! The supported sequences are:
+//!
+//! | Sequence          | Description                                              |
+//! |-------------------|----------------------------------------------------------|
+//! | `ESC 7`, `CSI s`  | Saves the cursor position and color                      |
+//! | `ESC 8`, `CSI u`  | Restores the saved cursor position and color             |
+//! | `CSI t ; b r`     | Sets the scroll region to rows `t` to `b`, or resets it  |
+//! | `CSI n L`         | Inserts `n` blank lines at the cursor                    |
+//! | `CSI n M`         | Deletes `n` lines at the cursor                          |
+//! | `CSI r ; c H`     | Moves the cursor to row `r`, column `c`                  |
+//! | `CSI n A` to `D`  | Moves the cursor up, down, forward or back by `n`        |
+//! | `CSI n J`         | Erases the display after, before or all around the cursor|
+//! | `CSI n K`         | Erases the line after, before or all around the cursor   |
+//!
+//! Other sequences, including private ones such as `CSI ? 25 l`, are ignored.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! // Scroll all but the top line, and keep a status bar there
+//! print!("\x1B[2;25r");
+//! print!("\x1B7\x1B[1;1Hstatus\x1B8");
+//! ```
+
+/// The character which starts an escape sequence
+const ESCAPE: char = '\x1B';
+/// The most parameters a sequence can have. Further parameters are ignored.
+const MAX_PARAMETERS: usize = 4;
+
+/// An escape sequence to execute on a terminal
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Sequence {
+    SaveCursor,
+    RestoreCursor,
+    /// Sets the scroll region to the given rows, inclusive. The bottom is the last row if `None`.
+    SetScrollRegion { top: usize, bottom: Option<usize> },
+    InsertLines(usize),
+    DeleteLines(usize),
+    CursorPosition { row: usize, column: usize },
+    CursorUp(usize),
+    CursorDown(usize),
+    CursorForward(usize),
+    CursorBack(usize),
+    EraseDisplay(Erase),
+    EraseLine(Erase),
+}
+
+/// The part of the display or line to erase
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Erase {
+    /// From the cursor to the end, inclusive
+    ToEnd,
+    /// From the start to the cursor, inclusive
+    ToStart,
+    All,
+}
+
+/// What to do with a character written to a terminal
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Action {
+    /// Print the character
+    Print(char),
+    /// The character completed an escape sequence
+    Execute(Sequence),
+    /// The character was part of an escape sequence, or ended an unsupported one
+    None,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum State {
+    Ground,
+    /// After an escape character
+    Escape,
+    /// Within a control sequence, after `ESC [`
+    Csi,
+}
+
+/// Parses escape sequences from the characters written to a terminal
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    /// The parameters of the control sequence being parsed, where 0 is the default
+    parameters: [usize; MAX_PARAMETERS],
+    /// The index of the parameter being parsed
+    parameter: usize,
+    /// Whether the control sequence is private, such as `CSI ? 25 l`
+    private: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            parameters: [0; MAX_PARAMETERS],
+            parameter: 0,
+            private: false,
+        }
+    }
+
+    /// Parses the next character written to the terminal
+    pub fn advance(&mut self, character: char) -> Action {
+        match self.state {
+            State::Ground if character == ESCAPE => {
+                self.state = State::Escape;
+                Action::None
+            }
+            State::Ground => Action::Print(character),
+            State::Escape => {
+                self.state = State::Ground;
+
+                match character {
+                    '[' => {
+                        self.state = State::Csi;
+                        self.parameters = [0; MAX_PARAMETERS];
+                        self.parameter = 0;
+                        self.private = false;
+                        Action::None
+                    }
+                    '7' => Action::Execute(Sequence::SaveCursor),
+                    '8' => Action::Execute(Sequence::RestoreCursor),
+                    _ => Action::None,
+                }
+            }
+            State::Csi => match character {
+                '0'...'9' => {
+                    if let Some(parameter) = self.parameters.get_mut(self.parameter) {
+                        let digit = character as usize - '0' as usize;
+                        *parameter = parameter.saturating_mul(10).saturating_add(digit);
+                    }
+                    Action::None
+                }
+                ';' => {
+                    self.parameter += 1;
+                    Action::None
+                }
+                '<'...'?' => {
+                    self.private = true;
+                    Action::None
+                }
+                // Intermediate characters
+                ' '...'/' => Action::None,
+                '@'...'~' => {
+                    self.state = State::Ground;
+
+                    if self.private {
+                        Action::None
+                    } else {
+                        self.sequence(character).map(Action::Execute).unwrap_or(Action::None)
+                    }
+                }
+                _ => {
+                    self.state = State::Ground;
+                    Action::None
+                }
+            },
+        }
+    }
+
+    /// Gets the sequence for the given final character of a control sequence
+    fn sequence(&self, character: char) -> Option<Sequence> {
+        let count = self.parameter_or(0, 1);
+
+        let sequence = match character {
+            's' => Sequence::SaveCursor,
+            'u' => Sequence::RestoreCursor,
+            'r' => Sequence::SetScrollRegion {
+                top: self.parameter_or(0, 1),
+                bottom: if self.parameters[1] == 0 { None } else { Some(self.parameters[1]) },
+            },
+            'L' => Sequence::InsertLines(count),
+            'M' => Sequence::DeleteLines(count),
+            'H' | 'f' => Sequence::CursorPosition { row: self.parameter_or(0, 1), column: self.parameter_or(1, 1) },
+            'A' => Sequence::CursorUp(count),
+            'B' => Sequence::CursorDown(count),
+            'C' => Sequence::CursorForward(count),
+            'D' => Sequence::CursorBack(count),
+            'J' => Sequence::EraseDisplay(self.erase()?),
+            'K' => Sequence::EraseLine(self.erase()?),
+            _ => return None,
+        };
+
+        Some(sequence)
+    }
+
+    /// Gets the given parameter, or the default if it was not given or is 0
+    fn parameter_or(&self, index: usize, default: usize) -> usize {
+        match self.parameters[index] {
+            0 => default,
+            parameter => parameter,
+        }
+    }
+
+    fn erase(&self) -> Option<Erase> {
+        match self.parameters[0] {
+            0 => Some(Erase::ToEnd),
+            1 => Some(Erase::ToStart),
+            2 => Some(Erase::All),
+            _ => None,
+        }
+    }
+}
+
+kernel_test!(fn parses_sequences() {
+    fn parse(string: &str) -> Option<Action> {
+        let mut parser = Parser::new();
+        string.chars().map(|character| parser.advance(character)).last()
+    }
+
+    test_assert_eq!(parse("a"), Some(Action::Print('a')));
+    test_assert_eq!(parse("\x1B7"), Some(Action::Execute(Sequence::SaveCursor)));
+    test_assert_eq!(parse("\x1B[2;24r"), Some(Action::Execute(Sequence::SetScrollRegion { top: 2, bottom: Some(24) })));
+    test_assert_eq!(parse("\x1B[r"), Some(Action::Execute(Sequence::SetScrollRegion { top: 1, bottom: None })));
+    test_assert_eq!(parse("\x1B[L"), Some(Action::Execute(Sequence::InsertLines(1))));
+    test_assert_eq!(parse("\x1B[;5H"), Some(Action::Execute(Sequence::CursorPosition { row: 1, column: 5 })));
+    test_assert_eq!(parse("\x1B[2K"), Some(Action::Execute(Sequence::EraseLine(Erase::All))));
+    test_assert_eq!(parse("\x1B[?25l"), Some(Action::None));
+    test_assert_eq!(parse("\x1B[?25lb"), Some(Action::Print('b')));
+});
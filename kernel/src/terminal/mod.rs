@@ -0,0 +1,863 @@
+//! # Terminal Driver
+//!
+//! The terminal driver's goal is to provide text writer access to outputs such as VGA.
+//! The driver accomplishes this through two trait categories
+//!  - [TerminalWriter]- The terminal writer handles raw access to an output, for instance raw
+//!    access to the vga buffer
+//!  - [Terminal] - The terminal is a high-level wrapper of the [TerminalWriter],
+//!    handling the cursor or tasks such as line-wrapping
+//!
+//!
+//! The terminal driver also has an `STDOUT`, which is the standard output for terminals,
+//! generally writing to VGA. This can be invoked through the `print!` and `println!` macros,
+//! or directly referencing it through `drivers::terminal::STDOUT`
+//!
+//! The screen can be split with `set_split` (or Ctrl+Alt+L) so that log messages are written to
+//! `LOG`, a [Pane] at the top of the screen, and everything else to `STDOUT` below it. Each pane
+//! has its own cursor and scrolls independently.
+//!
+//! Full-screen programs can restrict scrolling to a [ScrollRegion], save and restore the cursor,
+//! and insert and delete lines, either through [TerminalOutput] or by writing the ANSI escape
+//! sequences in [ansi] to a [Pane].
+
+use color::{Color, ColorPair};
+use core::cmp;
+use core::fmt::{self, Debug, Write};
+use core::ops::Add;
+use core::result::Result;
+use drivers::{speaker, vga};
+use log::Record;
+use spin::RwLock;
+use self::ansi::{Action, Erase, Sequence};
+
+// Macros up here to allow use in submodules for debugging
+macro_rules! print {
+    ($($arg:tt)*) => ({
+        $crate::terminal::stdout_print(format_args!($($arg)*));
+    });
+}
+
+macro_rules! println {
+    ($fmt:expr) => (print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+pub mod ansi;
+
+/// Writes formatted string to stdout, for print macro use
+pub fn stdout_print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    STDOUT.write().write_fmt(args).unwrap();
+}
+
+/// A standard output terminal, covering the whole screen unless it is split
+pub static STDOUT: RwLock<Pane<'static>> = RwLock::new(Pane::new(&vga::WRITER, 0, vga::RESOLUTION.y));
+
+/// The pane log messages are written to when the screen is split. It has no lines otherwise.
+pub static LOG: RwLock<Pane<'static>> = RwLock::new(Pane::empty(&vga::WRITER));
+
+/// The amount of lines given to `LOG` when the screen is split
+const LOG_PANE_HEIGHT: usize = 8;
+
+/// The frequency of the tone played for the bell character, in hertz
+const BELL_FREQUENCY: u32 = 800;
+/// The duration of the tone played for the bell character, in milliseconds
+const BELL_DURATION: u32 = 100;
+
+/// The color the timestamps of log messages are printed in
+const TIMESTAMP_COLOR: ColorPair = color!(DarkGray on Black);
+
+/// Writes a log record to `LOG` if the screen is split, or `STDOUT` otherwise, for the log sink.
+/// The record is dropped if the pane or the VGA writer is in use, as the log may be written from an
+/// interrupt handler which interrupted the code using them.
+pub fn log_print(record: &Record) {
+    // Panes lock the writer for each character, so it is only checked to be free here. It cannot
+    // be taken by the interrupted code while this runs.
+    if vga::WRITER.try_write().is_none() {
+        return;
+    }
+
+    let mut log = match LOG.try_write() {
+        Some(log) => log,
+        None => return,
+    };
+
+    if log.resolution().y > 0 {
+        write_log_message(&mut *log, record);
+        return;
+    }
+
+    drop(log);
+
+    if let Some(mut stdout) = STDOUT.try_write() {
+        write_log_message(&mut *stdout, record);
+    }
+}
+
+fn write_log_message(pane: &mut Pane, record: &Record) {
+    let previous = pane.color();
+    pane.set_color(TIMESTAMP_COLOR).expect("Color should be supported");
+    write!(pane, "{}", record.timestamp).expect("Error logging");
+    pane.set_color(previous).expect("Color should be supported");
+
+    pane.write_string_colored(record.level.prefix(), record.level.color()).expect("Error logging");
+    pane.write_fmt(record.args).expect("Error logging");
+    pane.write_str("\n").expect("Error logging");
+}
+
+/// Returns `true` if the screen is split into `LOG` and `STDOUT`
+pub fn is_split() -> bool {
+    LOG.read().resolution().y > 0
+}
+
+/// Splits the screen into `LOG` at the top and `STDOUT` below it, separated by a line, or gives
+/// the whole screen back to `STDOUT`. Both panes are cleared.
+pub fn set_split(split: bool) {
+    let mut stdout = STDOUT.write();
+    let mut log = LOG.write();
+    let lines = vga::RESOLUTION.y;
+
+    if split {
+        let separator_line = lines - LOG_PANE_HEIGHT - 1;
+        log.resize(separator_line + 1, LOG_PANE_HEIGHT);
+        stdout.resize(0, separator_line);
+
+        let mut writer = vga::WRITER.write();
+        let separator = TerminalCharacter::new('═', color!(DarkGray on Black));
+        for x in 0..vga::RESOLUTION.x {
+            // Cannot fail, as the point is in bounds and the color is supported
+            let _ = writer.set_char(separator, Point::new(x, separator_line));
+        }
+    } else {
+        log.resize(lines, 0);
+        stdout.resize(0, lines);
+    }
+}
+
+/// Toggles whether the screen is split
+pub fn toggle_split() {
+    let split = is_split();
+    set_split(!split);
+}
+
+/// The standard output. You should not assume that the `Other` variant will
+/// always carry a `()`.
+// Crate public writer for `panic_fmt` to construct
+pub struct Stdout<'a>(pub(crate) &'a RwLock<vga::VgaWriter>);
+
+impl<'a> TerminalOutput<()> for Stdout<'a> {
+    fn color_supported(&self, color: Color) -> bool {
+        self.0.read().color_supported(color)
+    }
+
+    fn resolution(&self) -> Resolution {
+        self.0.read().resolution()
+    }
+
+    fn cursor_pos(&self) -> Point {
+        self.0.read().cursor_pos()
+    }
+
+    fn set_cursor_pos(&mut self, point: Point) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().set_cursor_pos(point)
+    }
+
+    fn color(&self) -> ColorPair {
+        self.0.read().color()
+    }
+
+    fn set_color(&mut self, color: ColorPair) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().set_color(color)
+    }
+
+    fn set_char(&mut self, char: TerminalCharacter, point: Point) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().set_char(char, point)
+    }
+
+    fn write_colored(&mut self, character: char, color: ColorPair) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().write_colored(character, color)
+    }
+
+    fn clear_line(&mut self, y: usize) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().clear_line(y)
+    }
+
+    fn clear(&mut self) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().clear()
+    }
+
+    fn scroll_down(&mut self, lines: usize) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().scroll_down(lines)
+    }
+
+    fn scroll_lines(&mut self, region: ScrollRegion, direction: ScrollDirection, lines: usize)
+        -> Result<(), TerminalOutputError<()>>
+    {
+        self.0.write().scroll_lines(region, direction, lines)
+    }
+
+    fn scroll_region(&self) -> Option<ScrollRegion> {
+        self.0.read().scroll_region()
+    }
+
+    fn set_scroll_region(&mut self, region: Option<ScrollRegion>) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().set_scroll_region(region)
+    }
+
+    fn save_cursor(&mut self) {
+        self.0.write().save_cursor()
+    }
+
+    fn restore_cursor(&mut self) -> Result<(), TerminalOutputError<()>> {
+        self.0.write().restore_cursor()
+    }
+}
+
+impl<'a> Write for Stdout<'a> {
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        self.write_string(s).map_err(|_| fmt::Error)
+    }
+}
+
+/// A range of lines of the VGA writer, which has its own cursor and scrolls independently of the
+/// rest of the screen
+pub struct Pane<'a> {
+    writer: &'a RwLock<vga::VgaWriter>,
+    /// The line of the writer which is the bottom of this pane
+    bottom: usize,
+    height: usize,
+    cursor: Point,
+    color: ColorPair,
+    scroll_region: Option<ScrollRegion>,
+    /// The cursor position and color saved by `save_cursor`
+    saved_cursor: (Point, ColorPair),
+    parser: ansi::Parser,
+}
+
+impl<'a> Pane<'a> {
+    /// Creates a pane covering the given lines of the writer, counted from the bottom. The height
+    /// must not be zero.
+    pub const fn new(writer: &'a RwLock<vga::VgaWriter>, bottom: usize, height: usize) -> Self {
+        Pane {
+            writer,
+            bottom,
+            height,
+            cursor: Point::new(0, height - 1),
+            color: color!(White on Black),
+            scroll_region: None,
+            saved_cursor: (Point::new(0, height - 1), color!(White on Black)),
+            parser: ansi::Parser::new(),
+        }
+    }
+
+    /// Creates a pane with no lines, which cannot be written to until it is resized
+    pub const fn empty(writer: &'a RwLock<vga::VgaWriter>) -> Self {
+        Pane {
+            writer,
+            bottom: 0,
+            height: 0,
+            cursor: Point::new(0, 0),
+            color: color!(White on Black),
+            scroll_region: None,
+            saved_cursor: (Point::new(0, 0), color!(White on Black)),
+            parser: ansi::Parser::new(),
+        }
+    }
+
+    /// Moves this pane to cover the given lines, clearing them, moving the cursor to the top and
+    /// resetting the scroll region
+    pub fn resize(&mut self, bottom: usize, height: usize) {
+        self.bottom = bottom;
+        self.height = height;
+        self.cursor = Point::new(0, height.saturating_sub(1));
+        self.scroll_region = None;
+        self.saved_cursor = (self.cursor, self.color);
+
+        // Cannot fail, as every line is in bounds
+        let _ = self.clear();
+    }
+
+    /// Converts a point in this pane to a point in the writer
+    fn to_writer(&self, point: Point) -> Point {
+        Point::new(point.x, self.bottom + point.y)
+    }
+}
+
+impl<'a> TerminalOutput<()> for Pane<'a> {
+    fn color_supported(&self, color: Color) -> bool {
+        self.writer.read().color_supported(color)
+    }
+
+    fn resolution(&self) -> Resolution {
+        Resolution::new(vga::RESOLUTION.x, self.height)
+    }
+
+    fn cursor_pos(&self) -> Point {
+        self.cursor
+    }
+
+    fn set_cursor_pos(&mut self, point: Point) -> Result<(), TerminalOutputError<()>> {
+        if self.in_bounds(point) {
+            self.cursor = point;
+            Ok(())
+        } else {
+            Err(TerminalOutputError::OutOfBounds(point))
+        }
+    }
+
+    fn color(&self) -> ColorPair {
+        self.color
+    }
+
+    fn set_color(&mut self, color: ColorPair) -> Result<(), TerminalOutputError<()>> {
+        if !self.color_supported(color.foreground) {
+            return Err(TerminalOutputError::ColorUnsupported(color.foreground));
+        }
+        if !self.color_supported(color.background) {
+            return Err(TerminalOutputError::ColorUnsupported(color.background));
+        }
+
+        self.color = color;
+
+        Ok(())
+    }
+
+    fn set_char(&mut self, char: TerminalCharacter, point: Point) -> Result<(), TerminalOutputError<()>> {
+        if !self.in_bounds(point) {
+            return Err(TerminalOutputError::OutOfBounds(point));
+        }
+
+        let point = self.to_writer(point);
+        self.writer.write().set_char(char, point)
+    }
+
+    fn write_colored(&mut self, character: char, color: ColorPair) -> Result<(), TerminalOutputError<()>> {
+        let character = match self.parser.advance(character) {
+            Action::Print(character) => character,
+            Action::Execute(sequence) => return self.execute(sequence),
+            Action::None => return Ok(()),
+        };
+
+        match character {
+            '\n' => self.new_line(),
+            '\x07' => {
+                speaker::beep(BELL_FREQUENCY, BELL_DURATION);
+                Ok(())
+            }
+            _ => {
+                let mut pos = self.cursor_pos();
+                self.set_char(TerminalCharacter::new(character, color), pos)?;
+
+                pos.x += 1;
+
+                // If the x point went out of bounds, wrap
+                if pos.x >= vga::RESOLUTION.x {
+                    self.new_line()?;
+                } else {
+                    self.set_cursor_pos(pos)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn clear_line(&mut self, y: usize) -> Result<(), TerminalOutputError<()>> {
+        let blank = TerminalCharacter::new(' ', ColorPair::new(self.color.background, self.color.background));
+
+        for x in 0..vga::RESOLUTION.x {
+            self.set_char(blank, Point::new(x, y))?;
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), TerminalOutputError<()>> {
+        for line in 0..self.height {
+            self.clear_line(line)?;
+        }
+
+        Ok(())
+    }
+
+    fn scroll_down(&mut self, lines: usize) -> Result<(), TerminalOutputError<()>> {
+        self.scroll_lines(ScrollRegion::new(0, self.height), ScrollDirection::Down, lines)
+    }
+
+    fn scroll_lines(&mut self, region: ScrollRegion, direction: ScrollDirection, lines: usize)
+        -> Result<(), TerminalOutputError<()>>
+    {
+        if !region.fits(self.resolution()) {
+            return Err(TerminalOutputError::OutOfBounds(Point::new(0, region.top())));
+        }
+
+        let mut writer = self.writer.write();
+        let color = writer.color();
+
+        // The writer clears scrolled lines with its own background color
+        writer.set_color(ColorPair::new(color.foreground, self.color.background))?;
+        let region = ScrollRegion::new(self.bottom + region.bottom, region.height);
+        let result = writer.scroll_lines(region, direction, lines);
+        writer.set_color(color)?;
+
+        result
+    }
+
+    fn scroll_region(&self) -> Option<ScrollRegion> {
+        self.scroll_region
+    }
+
+    fn set_scroll_region(&mut self, region: Option<ScrollRegion>) -> Result<(), TerminalOutputError<()>> {
+        match region {
+            Some(region) if !region.fits(self.resolution()) => {
+                Err(TerminalOutputError::OutOfBounds(Point::new(0, region.top())))
+            }
+            _ => {
+                self.scroll_region = region;
+                Ok(())
+            }
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = (self.cursor, self.color);
+    }
+
+    fn restore_cursor(&mut self) -> Result<(), TerminalOutputError<()>> {
+        let (cursor, color) = self.saved_cursor;
+        self.set_cursor_pos(cursor)?;
+        self.set_color(color)
+    }
+}
+
+impl<'a> Write for Pane<'a> {
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        self.write_string(s).map_err(|_| fmt::Error)
+    }
+}
+
+/// A general [TerminalOutput] error
+#[derive(Debug)]
+#[allow(dead_code)] // Dead variants for completeness
+pub enum TerminalOutputError<E: Debug> {
+    /// Backspacing is not supported by the terminal
+    BackspaceUnsupported,
+    /// Backspacing is unavailable
+    BackspaceUnavailable(BackspaceUnavailableCause),
+    /// The given point was attempted to be accessed but is out of bounds
+    OutOfBounds(Point),
+    Debug(Point, Point, Point),
+    /// The given color is not supported by the terminal
+    ColorUnsupported(Color),
+    /// An error with no other representation
+    Other(E),
+}
+
+#[derive(Debug)]
+#[allow(dead_code)] // Dead variants for completeness
+pub enum BackspaceUnavailableCause {
+    Disabled,
+    TopOfTerminal,
+}
+
+/// Represents a character in a terminal, containing a character and color
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TerminalCharacter {
+    pub character: char,
+    pub color: ColorPair,
+}
+
+impl TerminalCharacter {
+    pub const fn new(character: char, color: ColorPair) -> Self {
+        TerminalCharacter { character, color }
+    }
+}
+
+/// Represents a 2d point in a terminal. Origin is __bottom left__
+#[derive(Default, Debug, Copy, Clone, Eq, PartialOrd, PartialEq)]
+pub struct Point {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Point {
+    /// Creates a new point at the given coordinate
+    pub const fn new(x: usize, y: usize) -> Self {
+        Point { x, y }
+    }
+}
+
+impl Add<Point> for Point {
+    type Output = Point;
+
+    fn add(self, rhs: Point) -> Point {
+        Point::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+/// Represents a terminal's resolution.
+///
+/// # Note
+/// although the fields are the same as [Point], they *are* semantically different
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Resolution {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Resolution {
+    pub const fn new(x: usize, y: usize) -> Self {
+        Resolution { x, y }
+    }
+}
+
+/// A range of lines of a terminal which scrolls independently of the others. Like [Point], lines
+/// are counted from the bottom.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ScrollRegion {
+    /// The bottom line of the region
+    pub bottom: usize,
+    /// The amount of lines in the region, which is not zero
+    pub height: usize,
+}
+
+impl ScrollRegion {
+    pub const fn new(bottom: usize, height: usize) -> Self {
+        ScrollRegion { bottom, height }
+    }
+
+    /// The top line of the region
+    pub fn top(&self) -> usize {
+        self.bottom + self.height - 1
+    }
+
+    /// Checks if the given line is within the region
+    pub fn contains(&self, y: usize) -> bool {
+        y >= self.bottom && y <= self.top()
+    }
+
+    /// Checks if the region is not empty and within a terminal of the given resolution
+    pub fn fits(&self, resolution: Resolution) -> bool {
+        self.height > 0 && self.bottom + self.height <= resolution.y
+    }
+}
+
+/// The direction the contents of a terminal scroll in
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScrollDirection {
+    /// Lines move up and blank lines appear at the bottom, as when a new line is written at the
+    /// bottom of the terminal
+    Down,
+    /// Lines move down and blank lines appear at the top
+    Up,
+}
+
+/// A writable terminal
+///
+/// # Note
+///
+/// The default implementations may not be the most efficient possible for your usecase - if you can
+/// implement it more efficiently, then do.
+pub trait TerminalOutput<E: Debug> {
+    /// Check if a color is supported by this terminal
+    fn color_supported(&self, color: Color) -> bool;
+
+    /// The resolution of the [TerminalWriter].
+    fn resolution(&self) -> Resolution;
+
+    /// Checks if a point is in bounds of the text area
+    fn in_bounds(&self, p: Point) -> bool {
+        p.x < self.resolution().x && p.y < self.resolution().y
+    }
+
+    /// Gets position of this terminal's cursor
+    fn cursor_pos(&self) -> Point;
+
+    /// Sets the position of this terminal's cursor
+    ///
+    /// # Implementation Note
+    ///
+    /// This should check whether the point is in bounds
+    fn set_cursor_pos(&mut self, point: Point) -> Result<(), TerminalOutputError<E>>;
+
+    /// Gets the color this terminal is using
+    fn color(&self) -> ColorPair;
+
+    /// Sets the color for this terminal to use
+    ///
+    /// # Implementation Note
+    ///
+    /// This should check whether the color is supported by this terminal
+    fn set_color(&mut self, color: ColorPair) -> Result<(), TerminalOutputError<E>>;
+
+    /// Sets the character at a position
+    ///
+    /// # Implementation Note
+    ///
+    /// This should check whether the point is in bounds
+    fn set_char(&mut self, char: TerminalCharacter, point: Point) -> Result<(), TerminalOutputError<E>>;
+
+    /// Writes a colored character to this terminal
+    fn write_colored(&mut self, character: char, color: ColorPair) -> Result<(), TerminalOutputError<E>>;
+
+    /// Writes a character to this terminal with the current set color
+    fn write(&mut self, character: char) -> Result<(), TerminalOutputError<E>> {
+        self.write_colored(character, self.color())
+    }
+
+    /// Writes a string to this terminal with the current set color
+    fn write_string(&mut self, str: &str) -> Result<(), TerminalOutputError<E>> {
+        self.write_string_colored(str, self.color())
+    }
+
+    /// Writes a colored string to this terminal
+    fn write_string_colored(&mut self, str: &str, color: ColorPair) -> Result<(), TerminalOutputError<E>> {
+        for character in str.chars() {
+            self.write_colored(character, color)?;
+        }
+
+        Ok(())
+    }
+
+    /// Clears one line with the current background color
+    ///
+    /// # Note
+    ///
+    /// This is not defaultedly implemented
+    fn clear_line(&mut self, y: usize) -> Result<(), TerminalOutputError<E>>;
+
+    /// Clears the screen with the current background color
+    fn clear(&mut self) -> Result<(), TerminalOutputError<E>>;
+
+    /// Scrolls the terminal down
+    fn scroll_down(&mut self, lines: usize) -> Result<(), TerminalOutputError<E>>;
+
+    /// Scrolls the given lines of the terminal, leaving the others as they are. Lines scrolled out
+    /// of the region are lost, and the lines scrolled in are cleared with the current background
+    /// color.
+    ///
+    /// # Implementation Note
+    ///
+    /// This should check whether the region is in bounds
+    fn scroll_lines(&mut self, region: ScrollRegion, direction: ScrollDirection, lines: usize)
+        -> Result<(), TerminalOutputError<E>>;
+
+    /// Gets the region which scrolls when a new line is written at its bottom, or `None` if the
+    /// whole terminal scrolls
+    fn scroll_region(&self) -> Option<ScrollRegion>;
+
+    /// Sets the region which scrolls when a new line is written at its bottom, or `None` for the
+    /// whole terminal to scroll
+    ///
+    /// # Implementation Note
+    ///
+    /// This should check whether the region is in bounds
+    fn set_scroll_region(&mut self, region: Option<ScrollRegion>) -> Result<(), TerminalOutputError<E>>;
+
+    /// Saves the cursor position and color, to be restored with `restore_cursor`
+    fn save_cursor(&mut self);
+
+    /// Restores the cursor position and color saved by `save_cursor`
+    fn restore_cursor(&mut self) -> Result<(), TerminalOutputError<E>>;
+
+    /// Writes a newline to this terminal, resetting cursor position. At the bottom of the scroll
+    /// region, the region is scrolled instead.
+    fn new_line(&mut self) -> Result<(), TerminalOutputError<E>> {
+        let mut pos = self.cursor_pos();
+        pos.x = 0;
+
+        match self.scroll_region() {
+            Some(region) if pos.y == region.bottom => self.scroll_lines(region, ScrollDirection::Down, 1)?,
+            _ if pos.y > 0 => pos.y -= 1,
+            None => self.scroll_down(1)?,
+            // The bottom of the terminal does not scroll if it is outside of the scroll region
+            Some(_) => (),
+        }
+
+        self.set_cursor_pos(pos)
+    }
+
+    /// Inserts blank lines at the cursor, moving the lines below it down within the scroll region.
+    /// Nothing is inserted if the cursor is outside of the scroll region.
+    fn insert_lines(&mut self, lines: usize) -> Result<(), TerminalOutputError<E>> {
+        match self.lines_below_cursor() {
+            Some(region) => {
+                self.scroll_lines(region, ScrollDirection::Up, lines)?;
+                let y = self.cursor_pos().y;
+                self.set_cursor_pos(Point::new(0, y))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes lines at the cursor, moving the lines below it up within the scroll region. Nothing is
+    /// deleted if the cursor is outside of the scroll region.
+    fn delete_lines(&mut self, lines: usize) -> Result<(), TerminalOutputError<E>> {
+        match self.lines_below_cursor() {
+            Some(region) => {
+                self.scroll_lines(region, ScrollDirection::Down, lines)?;
+                let y = self.cursor_pos().y;
+                self.set_cursor_pos(Point::new(0, y))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Gets the lines from the cursor to the bottom of the scroll region, or `None` if the cursor
+    /// is outside of the scroll region
+    fn lines_below_cursor(&self) -> Option<ScrollRegion> {
+        let region = self.scroll_region()
+            .unwrap_or_else(|| ScrollRegion::new(0, self.resolution().y));
+        let y = self.cursor_pos().y;
+
+        if region.contains(y) {
+            Some(ScrollRegion::new(region.bottom, y - region.bottom + 1))
+        } else {
+            None
+        }
+    }
+
+    /// Executes an ANSI escape sequence
+    fn execute(&mut self, sequence: Sequence) -> Result<(), TerminalOutputError<E>> {
+        let resolution = self.resolution();
+        let cursor = self.cursor_pos();
+
+        // Rows are counted from 1 at the top, and lines from 0 at the bottom
+        let row_to_line = |row: usize| resolution.y - cmp::min(cmp::max(row, 1), resolution.y);
+
+        match sequence {
+            Sequence::SaveCursor => {
+                self.save_cursor();
+                Ok(())
+            }
+            Sequence::RestoreCursor => self.restore_cursor(),
+            Sequence::SetScrollRegion { top, bottom } => {
+                let bottom = cmp::min(bottom.unwrap_or(resolution.y), resolution.y);
+
+                if top >= bottom {
+                    return Ok(());
+                }
+
+                let region = if top == 1 && bottom == resolution.y {
+                    None
+                } else {
+                    Some(ScrollRegion::new(row_to_line(bottom), bottom - top + 1))
+                };
+
+                self.set_scroll_region(region)?;
+
+                // Setting the scroll region moves the cursor to the top left
+                self.set_cursor_pos(Point::new(0, resolution.y - 1))
+            }
+            Sequence::InsertLines(lines) => self.insert_lines(lines),
+            Sequence::DeleteLines(lines) => self.delete_lines(lines),
+            Sequence::CursorPosition { row, column } => {
+                let x = cmp::min(cmp::max(column, 1), resolution.x) - 1;
+                self.set_cursor_pos(Point::new(x, row_to_line(row)))
+            }
+            Sequence::CursorUp(lines) => {
+                let y = cmp::min(cursor.y.saturating_add(lines), resolution.y - 1);
+                self.set_cursor_pos(Point::new(cursor.x, y))
+            }
+            Sequence::CursorDown(lines) => self.set_cursor_pos(Point::new(cursor.x, cursor.y.saturating_sub(lines))),
+            Sequence::CursorForward(columns) => {
+                let x = cmp::min(cursor.x.saturating_add(columns), resolution.x - 1);
+                self.set_cursor_pos(Point::new(x, cursor.y))
+            }
+            Sequence::CursorBack(columns) => self.set_cursor_pos(Point::new(cursor.x.saturating_sub(columns), cursor.y)),
+            Sequence::EraseLine(erase) => {
+                let (start, end) = match erase {
+                    Erase::ToEnd => (cursor.x, resolution.x),
+                    Erase::ToStart => (0, cursor.x + 1),
+                    Erase::All => (0, resolution.x),
+                };
+
+                let blank = TerminalCharacter::new(' ', ColorPair::new(
+                    self.color().background, self.color().background,
+                ));
+
+                for x in start..end {
+                    self.set_char(blank, Point::new(x, cursor.y))?;
+                }
+
+                Ok(())
+            }
+            Sequence::EraseDisplay(erase) => {
+                // The cursor's line is erased up to the cursor, and the lines above or below it fully
+                let lines = match erase {
+                    Erase::ToEnd => 0..cursor.y,
+                    Erase::ToStart => cursor.y + 1..resolution.y,
+                    Erase::All => return self.clear(),
+                };
+
+                self.execute(Sequence::EraseLine(erase))?;
+
+                for y in lines {
+                    self.clear_line(y)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Backspaces one character
+    fn backspace(&mut self) -> Result<(), TerminalOutputError<E>> {
+        if self.cursor_pos() == Point::new(0, 0) {
+            return Err(TerminalOutputError::BackspaceUnavailable(
+                BackspaceUnavailableCause::TopOfTerminal)
+            );
+        }
+
+        if self.cursor_pos().x == 0 {
+            self.set_cursor_pos(Point {
+                x: self.resolution().x - 1,
+                y: self.cursor_pos().y - 1,
+            })?;
+        } else {
+            self.set_cursor_pos(Point {
+                x: self.cursor_pos().x - 1,
+                ..self.cursor_pos()
+            })?;
+        }
+
+        let blank = TerminalCharacter::new(' ', ColorPair::new(
+            self.color().background, self.color().background,
+        ));
+
+        self.set_char(blank, self.cursor_pos())
+    }
+}
+
+kernel_test!(fn points_add() {
+    test_assert_eq!(Point::new(1, 2) + Point::new(3, 4), Point::new(4, 6));
+});
+
+kernel_test!(fn scroll_region_confines_new_lines() {
+    let writer = RwLock::new(vga::VgaWriter::new());
+    let mut pane = Pane::new(&writer, 0, 10);
+
+    // Scroll rows 2 to 5, which are lines 8 to 5
+    test_assert!(pane.write_string("\x1B[2;5r").is_ok());
+    test_assert_eq!(pane.scroll_region(), Some(ScrollRegion::new(5, 4)));
+    test_assert!(pane.write_string("\x1B[5;3H\x1B7\n\n").is_ok());
+    test_assert_eq!(pane.cursor_pos(), Point::new(0, 5));
+
+    test_assert!(pane.write_string("\x1B8").is_ok());
+    test_assert_eq!(pane.cursor_pos(), Point::new(2, 5));
+
+    // Lines outside of the region are not affected by inserting lines
+    test_assert!(pane.set_cursor_pos(Point::new(4, 9)).is_ok());
+    test_assert!(pane.insert_lines(1).is_ok());
+    test_assert_eq!(pane.cursor_pos(), Point::new(4, 9));
+});
+
+kernel_test!(fn pane_scrolls_independently() {
+    let writer = RwLock::new(vga::VgaWriter::new());
+    let mut pane = Pane::new(&writer, 2, 2);
+
+    test_assert_eq!(pane.resolution(), Resolution::new(vga::RESOLUTION.x, 2));
+    test_assert!(pane.write_string("a\nb\nc").is_ok());
+    test_assert_eq!(pane.cursor_pos(), Point::new(1, 0));
+    test_assert!(pane.set_char(TerminalCharacter::new('x', pane.color()), Point::new(0, 2)).is_err());
+});
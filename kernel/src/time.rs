@@ -0,0 +1,241 @@
+//! # Time
+//!
+//! Keeps the kernel's clocks. `Clock::Monotonic` counts the nanoseconds since boot, and never jumps
+//! or goes backwards. `Clock::Realtime` counts the nanoseconds since the Unix epoch. It is read from
+//! the RTC at boot, and can then be corrected, for example by an NTP client.
+//!
+//! Both clocks are read from the TSC if it is invariant, and otherwise from the HPET if there is
+//! one with a 64 bit counter. The TSC's frequency is measured against the HPET, or against the PIT
+//! if there is no HPET.
+//!
+//! Small corrections to the wall clock are slewed: the clock is run faster or slower by at most
+//! `MAX_SLEW_PPM` until the correction has been applied, so that it never jumps. Corrections of more
+//! than `STEP_THRESHOLD` are stepped immediately, as slewing them would take too long.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let start = time::now(Clock::Monotonic);
+//! do_work();
+//! println!("took {} ns", time::now(Clock::Monotonic) - start);
+//! ```
+
+use arch;
+use arch::cpuid::{self, Features};
+use core::cmp;
+use drivers::{hpet, pit, rtc};
+use drivers::hpet::Hpet;
+use spin::RwLock;
+
+pub const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+/// The largest rate at which the wall clock is slewed, in parts per million
+pub const MAX_SLEW_PPM: u64 = 500;
+/// Corrections larger than this, in nanoseconds, are stepped rather than slewed
+pub const STEP_THRESHOLD: u64 = 128_000_000;
+
+/// The time spent measuring the TSC's frequency, in milliseconds
+const CALIBRATION_MS: u64 = 50;
+
+static STATE: RwLock<Option<State>> = RwLock::new(None);
+
+/// A clock which can be read with `now`
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Clock {
+    /// The time since boot, which never jumps
+    Monotonic,
+    /// The time since the Unix epoch, which jumps if the wall clock is set
+    Realtime,
+}
+
+/// The counter the clocks are read from
+#[derive(Copy, Clone)]
+enum Source {
+    Tsc,
+    Hpet(&'static Hpet),
+}
+
+struct State {
+    source: Source,
+    /// The frequency of the source, in hertz
+    frequency: u64,
+    /// The value of the source at boot
+    start: u64,
+    wall: WallClock,
+}
+
+impl State {
+    fn ticks(&self) -> u64 {
+        let counter = match self.source {
+            Source::Tsc => arch::rdtsc(),
+            Source::Hpet(hpet) => hpet.counter(),
+        };
+
+        counter.wrapping_sub(self.start)
+    }
+
+    fn monotonic(&self) -> u64 {
+        ticks_to_nanoseconds(self.ticks(), self.frequency)
+    }
+}
+
+/// The wall clock, as an offset from the monotonic clock with a correction being slewed
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct WallClock {
+    /// The wall clock time at monotonic time zero, once all corrections before `slew_start` are applied
+    offset: i64,
+    /// The correction being slewed, in nanoseconds
+    slew: i64,
+    /// The monotonic time the correction started being slewed at
+    slew_start: u64,
+}
+
+impl WallClock {
+    fn new(offset: i64) -> Self {
+        WallClock { offset, slew: 0, slew_start: 0 }
+    }
+
+    /// The part of the correction being slewed which has been applied by the given monotonic time
+    fn slewed(&self, monotonic: u64) -> i64 {
+        let elapsed = monotonic.saturating_sub(self.slew_start);
+        let limit = (elapsed / 1_000_000 * MAX_SLEW_PPM) as i64;
+
+        cmp::max(cmp::min(self.slew, limit), -limit)
+    }
+
+    /// Reads the wall clock at the given monotonic time
+    fn read(&self, monotonic: u64) -> u64 {
+        (self.offset + monotonic as i64 + self.slewed(monotonic)) as u64
+    }
+
+    /// Corrects the wall clock by the given amount of nanoseconds at the given monotonic time
+    fn adjust(&mut self, delta: i64, monotonic: u64) {
+        // The part of the previous correction already applied is kept, and the rest is replaced
+        self.offset += self.slewed(monotonic);
+
+        if delta.abs() as u64 > STEP_THRESHOLD {
+            self.offset += delta;
+            self.slew = 0;
+        } else {
+            self.slew = delta;
+        }
+
+        self.slew_start = monotonic;
+    }
+}
+
+/// Chooses a clock source and reads the wall clock from the RTC. This must be called after
+/// `acpi::init`.
+pub fn init() {
+    let hpet = hpet::get();
+    let invariant = cpuid::has(Features::INVARIANT_TSC);
+
+    let (source, frequency) = match hpet {
+        Some(hpet) if !invariant && hpet.is_wide() => (Source::Hpet(hpet), hpet.frequency()),
+        _ => (Source::Tsc, measure_tsc(hpet)),
+    };
+
+    if !invariant {
+        match source {
+            Source::Hpet(_) => {
+                debug!("time: tsc not invariant, using hpet");
+            }
+            Source::Tsc => warn!("time: tsc not invariant and no 64 bit hpet, clocks may drift"),
+        }
+    }
+
+    let start = match source {
+        Source::Tsc => arch::rdtsc(),
+        Source::Hpet(hpet) => hpet.counter(),
+    };
+
+    let date = rtc::read();
+    let wall = WallClock::new((date.unix_seconds() * NANOSECONDS_PER_SECOND) as i64);
+
+    info!("time: {} MHz clock, booted at {}-{:02}-{:02} {:02}:{:02}:{:02} UTC", frequency / 1_000_000,
+          date.year, date.month, date.day, date.hours, date.minutes, date.seconds);
+
+    *STATE.write() = Some(State { source, frequency, start, wall });
+}
+
+/// Reads the given clock, in nanoseconds, or returns 0 if `init` has not been called
+pub fn now(clock: Clock) -> u64 {
+    match *STATE.read() {
+        Some(ref state) => {
+            let monotonic = state.monotonic();
+            match clock {
+                Clock::Monotonic => monotonic,
+                Clock::Realtime => state.wall.read(monotonic),
+            }
+        }
+        None => 0,
+    }
+}
+
+/// Corrects the wall clock by the given amount of nanoseconds. Small corrections are slewed, so
+/// that the wall clock never jumps.
+#[allow(dead_code)] // Part of API
+pub fn adjust(delta: i64) {
+    if let Some(ref mut state) = *STATE.write() {
+        let monotonic = state.monotonic();
+        state.wall.adjust(delta, monotonic);
+    }
+}
+
+/// Sets the wall clock to the given amount of nanoseconds since the Unix epoch, stepping it
+#[allow(dead_code)] // Part of API
+pub fn set_realtime(nanoseconds: u64) {
+    if let Some(ref mut state) = *STATE.write() {
+        let monotonic = state.monotonic();
+        state.wall = WallClock::new(nanoseconds as i64 - monotonic as i64);
+    }
+}
+
+/// Measures the TSC's frequency against the HPET, or the PIT if there is none
+fn measure_tsc(hpet: Option<&Hpet>) -> u64 {
+    let (tsc, nanoseconds) = match hpet {
+        Some(hpet) => {
+            let ticks = hpet.frequency() * CALIBRATION_MS / 1000;
+            let (hpet_start, tsc_start) = (hpet.counter(), arch::rdtsc());
+
+            let mut elapsed = 0;
+            while elapsed < ticks {
+                elapsed = hpet.counter().wrapping_sub(hpet_start) & counter_mask(hpet);
+            }
+
+            (arch::rdtsc() - tsc_start, ticks_to_nanoseconds(elapsed, hpet.frequency()))
+        }
+        None => {
+            let tsc_start = arch::rdtsc();
+            pit::sleep_ms(CALIBRATION_MS as u32);
+            (arch::rdtsc() - tsc_start, CALIBRATION_MS * 1_000_000)
+        }
+    };
+
+    tsc * 1_000_000 / (nanoseconds / 1000)
+}
+
+/// Gets the mask of the bits of the HPET's counter which count
+fn counter_mask(hpet: &Hpet) -> u64 {
+    if hpet.is_wide() { u64::max_value() } else { 0xFFFF_FFFF }
+}
+
+/// Converts the given amount of ticks at the given frequency to nanoseconds, without overflowing
+fn ticks_to_nanoseconds(ticks: u64, frequency: u64) -> u64 {
+    ticks / frequency * NANOSECONDS_PER_SECOND + ticks % frequency * NANOSECONDS_PER_SECOND / frequency
+}
+
+kernel_test!(fn slews_wall_clock() {
+    test_assert_eq!(ticks_to_nanoseconds(3_000_000_001, 1_000_000_000), 3_000_000_001);
+
+    let mut wall = WallClock::new(1_000);
+    wall.adjust(1_000_000, 0);
+
+    // 1ms is slewed over 2s at 500ppm
+    test_assert_eq!(wall.read(1_000_000_000), 1_000_000_000 + 1_000 + 500_000);
+    test_assert_eq!(wall.read(3_000_000_000), 3_000_000_000 + 1_000 + 1_000_000);
+
+    // Large corrections are stepped
+    wall.adjust(-1_000_000_000, 3_000_000_000);
+    test_assert_eq!(wall.read(3_000_000_000), 2_000_000_000 + 1_000 + 1_000_000);
+});
@@ -0,0 +1,744 @@
+//! # Stack unwinding
+//!
+//! Walks the stack for backtraces using the DWARF call frame information in `.eh_frame`, so that
+//! frame pointers are not needed. For each frame, the FDE covering its instruction pointer is found
+//! by binary searching the table in `.eh_frame_hdr`, which the linker generates with
+//! `--eh-frame-hdr`. Its CFA program is then run up to the instruction pointer, giving the rules to
+//! restore the caller's registers and return address.
+//!
+//! Unwinding can start either from the current function, with `Registers::capture`, or from the
+//! context an exception interrupted, with `Registers::from_exception`. The frame of an interrupt
+//! handler cannot be unwound through, as the CPU pushes more than a return address, so exception
+//! handlers start from the interrupted context instead.
+//!
+//! Unwinding stops at a frame without unwind information, such as the assembly boot code, at a
+//! rule which is not supported, such as a DWARF expression, or if the stack is not mapped.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! let backtrace = Backtrace::new(Registers::capture());
+//! print!("{}", backtrace);
+//! ```
+
+use core::{fmt, mem, ptr};
+use memory::paging::ActivePageTable;
+use symbols;
+use x86_64::structures::idt::ExceptionStackFrame;
+
+/// The amount of registers tracked, which are the general purpose registers and the return address
+const REGISTER_COUNT: usize = 17;
+/// The DWARF register number of `rbx`
+const RBX: usize = 3;
+/// The DWARF register number of `rbp`
+const RBP: usize = 6;
+/// The DWARF register number of `rsp`
+const RSP: usize = 7;
+/// The DWARF register number of `r12`. `r13` to `r15` follow it.
+const R12: usize = 12;
+/// The DWARF register number of the return address
+const RETURN_ADDRESS: usize = 16;
+
+/// The most frames which are walked, in case the stack is corrupt and loops
+const MAX_FRAMES: usize = 64;
+/// The deepest `DW_CFA_remember_state` nesting supported
+const MAX_REMEMBERED_STATES: usize = 4;
+
+/// Pointer encodings
+const DW_EH_PE_OMIT: u8 = 0xFF;
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0A;
+const DW_EH_PE_SDATA4: u8 = 0x0B;
+const DW_EH_PE_SDATA8: u8 = 0x0C;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+const DW_EH_PE_INDIRECT: u8 = 0x80;
+
+/// CFA instructions which have their operand in their low 6 bits
+const DW_CFA_ADVANCE_LOC: u8 = 0x1;
+const DW_CFA_OFFSET: u8 = 0x2;
+const DW_CFA_RESTORE: u8 = 0x3;
+
+/// CFA instructions which have their own opcode
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_SET_LOC: u8 = 0x01;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+const DW_CFA_RESTORE_EXTENDED: u8 = 0x06;
+const DW_CFA_UNDEFINED: u8 = 0x07;
+const DW_CFA_SAME_VALUE: u8 = 0x08;
+const DW_CFA_REGISTER: u8 = 0x09;
+const DW_CFA_REMEMBER_STATE: u8 = 0x0A;
+const DW_CFA_RESTORE_STATE: u8 = 0x0B;
+const DW_CFA_DEF_CFA: u8 = 0x0C;
+const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0D;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0E;
+const DW_CFA_OFFSET_EXTENDED_SF: u8 = 0x11;
+const DW_CFA_DEF_CFA_SF: u8 = 0x12;
+const DW_CFA_DEF_CFA_OFFSET_SF: u8 = 0x13;
+const DW_CFA_VAL_OFFSET: u8 = 0x14;
+const DW_CFA_VAL_OFFSET_SF: u8 = 0x15;
+const DW_CFA_GNU_ARGS_SIZE: u8 = 0x2E;
+const DW_CFA_GNU_NEGATIVE_OFFSET_EXTENDED: u8 = 0x2F;
+
+#[allow(non_upper_case_globals)]
+extern {
+    /// The start of the `.eh_frame_hdr` section, provided by the linker
+    static eh_frame_hdr_start: u8;
+    /// The end of the `.eh_frame_hdr` section, provided by the linker
+    static eh_frame_hdr_end: u8;
+}
+
+/// The values of the registers in a frame, indexed by DWARF register number. Registers whose value
+/// is not known are `None`.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    values: [Option<u64>; REGISTER_COUNT],
+    /// The instruction pointer
+    rip: u64,
+}
+
+impl Registers {
+    /// Captures the registers of the calling function, so that unwinding starts from it
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let (rip, rsp, rbp, rbx): (u64, u64, u64, u64);
+        let (r12, r13, r14, r15): (u64, u64, u64, u64);
+
+        unsafe {
+            asm!("lea (%rip), $0; mov %rsp, $1; mov %rbp, $2; mov %rbx, $3"
+                 : "=r"(rip), "=r"(rsp), "=r"(rbp), "=r"(rbx) ::: "volatile");
+            asm!("mov %r12, $0; mov %r13, $1; mov %r14, $2; mov %r15, $3"
+                 : "=r"(r12), "=r"(r13), "=r"(r14), "=r"(r15) ::: "volatile");
+        }
+
+        let mut values = [None; REGISTER_COUNT];
+        values[RSP] = Some(rsp);
+        values[RBP] = Some(rbp);
+        values[RBX] = Some(rbx);
+        values[R12] = Some(r12);
+        values[R12 + 1] = Some(r13);
+        values[R12 + 2] = Some(r14);
+        values[R12 + 3] = Some(r15);
+
+        Registers { values, rip }
+    }
+
+    /// Gets the registers of the context an exception interrupted. Only the instruction and stack
+    /// pointers are pushed by the CPU, so unwinding stops at frames which need other registers.
+    pub fn from_exception(frame: &ExceptionStackFrame) -> Self {
+        let mut values = [None; REGISTER_COUNT];
+        values[RSP] = Some(frame.stack_pointer.0 as u64);
+
+        Registers { values, rip: frame.instruction_pointer.0 as u64 }
+    }
+}
+
+/// A backtrace from the given registers, which is walked each time it is printed or its frames are
+/// iterated
+#[derive(Copy, Clone, Debug)]
+pub struct Backtrace {
+    registers: Registers,
+}
+
+impl Backtrace {
+    pub fn new(registers: Registers) -> Self {
+        Backtrace { registers }
+    }
+
+    /// Gets an iterator over the instruction addresses of the frames, from the innermost. The
+    /// addresses of callers are within the call instruction, rather than its return address.
+    pub fn frames(&self) -> Frames {
+        Frames {
+            registers: Some(self.registers),
+            count: 0,
+        }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, address) in self.frames().enumerate() {
+            write!(f, "{:>2}: {:#018x}", index, address)?;
+
+            if let Some((symbol, offset)) = symbols::resolve(address) {
+                write!(f, " {}+{:#x}", symbol.name, offset)?;
+            }
+
+            writeln!(f, "")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An iterator over the frames of a backtrace
+pub struct Frames {
+    /// The registers of the next frame
+    registers: Option<Registers>,
+    count: usize,
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let registers = self.registers.take()?;
+        if self.count == MAX_FRAMES {
+            return None;
+        }
+
+        // A return address is just after the call, which may be the start of another function
+        let address = (if self.count == 0 { registers.rip } else { registers.rip - 1 }) as usize;
+        self.count += 1;
+
+        self.registers = unwind_frame(&registers, address);
+        Some(address)
+    }
+}
+
+/// How a register of the caller is restored
+#[derive(Copy, Clone, Debug)]
+enum Rule {
+    Undefined,
+    SameValue,
+    /// Saved at the given offset from the CFA
+    Offset(i64),
+    /// The CFA plus the given offset
+    ValOffset(i64),
+    /// Saved in the given register
+    Register(usize),
+}
+
+/// The rules to unwind a frame at an instruction
+#[derive(Copy, Clone, Debug)]
+struct Row {
+    cfa_register: usize,
+    cfa_offset: i64,
+    rules: [Rule; REGISTER_COUNT],
+}
+
+/// A common information entry, shared by many FDEs
+struct Cie {
+    code_alignment: u64,
+    data_alignment: i64,
+    return_address_register: usize,
+    /// The encoding of the addresses in FDEs
+    fde_encoding: u8,
+    /// Whether the FDEs have augmentation data
+    has_augmentation_data: bool,
+    instructions: usize,
+    instructions_end: usize,
+}
+
+/// A frame description entry, describing a single function
+struct Fde {
+    cie: Cie,
+    start: usize,
+    end: usize,
+    instructions: usize,
+    instructions_end: usize,
+}
+
+/// Reads DWARF data from memory
+struct Reader {
+    address: usize,
+}
+
+impl Reader {
+    /// Creates a reader at the given address
+    ///
+    /// # Safety
+    ///
+    /// All memory read must be mapped
+    unsafe fn new(address: usize) -> Self {
+        Reader { address }
+    }
+
+    fn read<T: Copy>(&mut self) -> T {
+        let value = unsafe { ptr::read_unaligned(self.address as *const T) };
+        self.address += mem::size_of::<T>();
+        value
+    }
+
+    fn uleb128(&mut self) -> u64 {
+        let (mut value, mut shift) = (0, 0);
+        loop {
+            let byte: u8 = self.read();
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> i64 {
+        let (mut value, mut shift) = (0i64, 0);
+        loop {
+            let byte: u8 = self.read();
+            if shift < 64 {
+                value |= ((byte & 0x7F) as i64) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                // Sign extend from the last byte's sign bit
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return value;
+            }
+        }
+    }
+
+    /// Reads a pointer in the given encoding. Data relative pointers are relative to `data_base`.
+    fn pointer(&mut self, encoding: u8, data_base: usize) -> Option<usize> {
+        if encoding == DW_EH_PE_OMIT {
+            return None;
+        }
+
+        let position = self.address;
+        let value = match encoding & 0x0F {
+            DW_EH_PE_ABSPTR | DW_EH_PE_UDATA8 | DW_EH_PE_SDATA8 => self.read::<u64>() as usize,
+            DW_EH_PE_ULEB128 => self.uleb128() as usize,
+            DW_EH_PE_UDATA2 => self.read::<u16>() as usize,
+            DW_EH_PE_UDATA4 => self.read::<u32>() as usize,
+            DW_EH_PE_SLEB128 => self.sleb128() as usize,
+            DW_EH_PE_SDATA2 => self.read::<i16>() as isize as usize,
+            DW_EH_PE_SDATA4 => self.read::<i32>() as isize as usize,
+            _ => return None,
+        };
+
+        let base = match encoding & 0x70 {
+            0 => 0,
+            DW_EH_PE_PCREL => position,
+            DW_EH_PE_DATAREL => data_base,
+            _ => return None,
+        };
+
+        let address = base.wrapping_add(value);
+        if encoding & DW_EH_PE_INDIRECT != 0 {
+            Some(unsafe { *(address as *const usize) })
+        } else {
+            Some(address)
+        }
+    }
+
+    /// Reads the length of an entry, returning the address of its end
+    fn entry_length(&mut self) -> Option<usize> {
+        let length: u32 = self.read();
+        match length {
+            0 => None,
+            0xFFFF_FFFF => {
+                let length: u64 = self.read();
+                Some(self.address + length as usize)
+            }
+            length => Some(self.address + length as usize),
+        }
+    }
+}
+
+/// Unwinds a frame, returning the registers of its caller
+fn unwind_frame(registers: &Registers, address: usize) -> Option<Registers> {
+    let fde = find_fde(address)?;
+    let row = execute(&fde, address)?;
+
+    let cfa = (registers.values[row.cfa_register]? as i64 + row.cfa_offset) as u64;
+
+    // Safe because the tables are only read
+    let table = unsafe { ActivePageTable::unlocked() };
+
+    let mut caller = Registers { values: [None; REGISTER_COUNT], rip: 0 };
+    for (register, rule) in row.rules.iter().enumerate() {
+        caller.values[register] = match *rule {
+            Rule::Undefined => None,
+            Rule::SameValue => registers.values[register],
+            Rule::Offset(offset) => {
+                let address = (cfa as i64 + offset) as usize;
+                if address % 8 != 0 || table.translate(address).is_none() {
+                    return None;
+                }
+                Some(unsafe { *(address as *const u64) })
+            }
+            Rule::ValOffset(offset) => Some((cfa as i64 + offset) as u64),
+            Rule::Register(other) => registers.values[other],
+        };
+    }
+
+    // The CFA is the stack pointer before the call
+    caller.values[RSP] = Some(cfa);
+    caller.rip = caller.values[fde.cie.return_address_register]?;
+    caller.values[RETURN_ADDRESS] = None;
+
+    if caller.rip == 0 {
+        None
+    } else {
+        Some(caller)
+    }
+}
+
+/// Finds the FDE describing the function containing the given address, by binary searching the
+/// table in `.eh_frame_hdr`
+fn find_fde(address: usize) -> Option<Fde> {
+    let (start, end) = unsafe {
+        (&eh_frame_hdr_start as *const u8 as usize, &eh_frame_hdr_end as *const u8 as usize)
+    };
+
+    if end - start < 4 {
+        return None;
+    }
+
+    // Safe because the header is within the section
+    let mut reader = unsafe { Reader::new(start) };
+    let version: u8 = reader.read();
+    let eh_frame_encoding: u8 = reader.read();
+    let count_encoding: u8 = reader.read();
+    let table_encoding: u8 = reader.read();
+
+    // The linker always writes the table as 32 bit offsets from the header
+    if version != 1 || table_encoding != DW_EH_PE_DATAREL | DW_EH_PE_SDATA4 {
+        return None;
+    }
+
+    reader.pointer(eh_frame_encoding, start)?;
+    let count = reader.pointer(count_encoding, start)?;
+    let table = reader.address;
+
+    let entry = |index: usize| unsafe {
+        let mut reader = Reader::new(table + index * 8);
+        let initial_location = start.wrapping_add(reader.read::<i32>() as isize as usize);
+        let fde = start.wrapping_add(reader.read::<i32>() as isize as usize);
+        (initial_location, fde)
+    };
+
+    // Find the last entry starting at or before the address
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if entry(middle).0 <= address {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    if low == 0 {
+        return None;
+    }
+
+    let fde = parse_fde(entry(low - 1).1)?;
+    if address >= fde.start && address < fde.end {
+        Some(fde)
+    } else {
+        None
+    }
+}
+
+fn parse_fde(address: usize) -> Option<Fde> {
+    // Safe because the address is from the `.eh_frame_hdr` table, so is within `.eh_frame`
+    let mut reader = unsafe { Reader::new(address) };
+    let end = reader.entry_length()?;
+
+    // The CIE pointer is relative to its own position
+    let cie_pointer_position = reader.address;
+    let cie_pointer: u32 = reader.read();
+    if cie_pointer == 0 {
+        return None;
+    }
+
+    let cie = parse_cie(cie_pointer_position - cie_pointer as usize)?;
+
+    let start = reader.pointer(cie.fde_encoding, 0)?;
+    // The range is never relative
+    let range = reader.pointer(cie.fde_encoding & 0x0F, 0)?;
+
+    if cie.has_augmentation_data {
+        let length = reader.uleb128() as usize;
+        reader.address += length;
+    }
+
+    Some(Fde {
+        cie,
+        start,
+        end: start + range,
+        instructions: reader.address,
+        instructions_end: end,
+    })
+}
+
+fn parse_cie(address: usize) -> Option<Cie> {
+    // Safe because the address is from an FDE, so is within `.eh_frame`
+    let mut reader = unsafe { Reader::new(address) };
+    let end = reader.entry_length()?;
+
+    let id: u32 = reader.read();
+    let version: u8 = reader.read();
+    if id != 0 || (version != 1 && version != 3) {
+        return None;
+    }
+
+    let augmentation = reader.address;
+    while reader.read::<u8>() != 0 {}
+    let augmentation_end = reader.address - 1;
+
+    let code_alignment = reader.uleb128();
+    let data_alignment = reader.sleb128();
+    let return_address_register = if version == 1 {
+        reader.read::<u8>() as usize
+    } else {
+        reader.uleb128() as usize
+    };
+
+    if return_address_register >= REGISTER_COUNT {
+        return None;
+    }
+
+    let mut cie = Cie {
+        code_alignment,
+        data_alignment,
+        return_address_register,
+        fde_encoding: DW_EH_PE_ABSPTR,
+        has_augmentation_data: false,
+        instructions: 0,
+        instructions_end: end,
+    };
+
+    let mut data_end = None;
+    for character in augmentation..augmentation_end {
+        match unsafe { *(character as *const u8) } {
+            b'z' => {
+                let length = reader.uleb128() as usize;
+                data_end = Some(reader.address + length);
+                cie.has_augmentation_data = true;
+            }
+            b'R' => cie.fde_encoding = reader.read(),
+            b'P' => {
+                let encoding: u8 = reader.read();
+                reader.pointer(encoding, 0)?;
+            }
+            b'L' => {
+                let _encoding: u8 = reader.read();
+            }
+            b'S' => (),
+            // Unknown augmentations can only be skipped if their data's length is known
+            _ => match data_end {
+                Some(_) => break,
+                None => return None,
+            },
+        }
+    }
+
+    cie.instructions = data_end.unwrap_or(reader.address);
+    Some(cie)
+}
+
+/// Runs the CIE's and FDE's CFA programs up to the given address, returning the row for it
+fn execute(fde: &Fde, address: usize) -> Option<Row> {
+    let mut row = Row {
+        cfa_register: RSP,
+        cfa_offset: 0,
+        rules: [Rule::SameValue; REGISTER_COUNT],
+    };
+
+    row = run_program(&fde.cie, fde.cie.instructions, fde.cie.instructions_end, row, None, usize::max_value())?;
+    let initial = row;
+
+    run_program(&fde.cie, fde.instructions, fde.instructions_end, row, Some((fde.start, &initial)), address)
+}
+
+/// Runs a CFA program on the given row. For an FDE's program, `location` is its start address and
+/// the row after its CIE's program, which `DW_CFA_restore` restores rules to.
+fn run_program(cie: &Cie, start: usize, end: usize, mut row: Row, location: Option<(usize, &Row)>,
+               address: usize) -> Option<Row>
+{
+    // Safe because the program is within the CIE or FDE
+    let mut reader = unsafe { Reader::new(start) };
+    let mut current = location.map(|(start, _)| start).unwrap_or(0);
+
+    let mut remembered = [row; MAX_REMEMBERED_STATES];
+    let mut remembered_count = 0;
+
+    while reader.address < end {
+        let instruction: u8 = reader.read();
+        let operand = instruction & 0x3F;
+
+        let advance = match instruction >> 6 {
+            DW_CFA_ADVANCE_LOC => Some(operand as u64),
+            DW_CFA_OFFSET => {
+                let offset = reader.uleb128() as i64 * cie.data_alignment;
+                set_rule(&mut row, operand as usize, Rule::Offset(offset));
+                None
+            }
+            DW_CFA_RESTORE => {
+                let (_, initial) = location?;
+                restore_rule(&mut row, operand as usize, initial);
+                None
+            }
+            _ => match instruction {
+                DW_CFA_NOP => None,
+                DW_CFA_SET_LOC => {
+                    current = reader.pointer(cie.fde_encoding, 0)?;
+                    None
+                }
+                DW_CFA_ADVANCE_LOC1 => Some(reader.read::<u8>() as u64),
+                DW_CFA_ADVANCE_LOC2 => Some(reader.read::<u16>() as u64),
+                DW_CFA_ADVANCE_LOC4 => Some(reader.read::<u32>() as u64),
+                DW_CFA_OFFSET_EXTENDED => {
+                    let register = reader.uleb128() as usize;
+                    let offset = reader.uleb128() as i64 * cie.data_alignment;
+                    set_rule(&mut row, register, Rule::Offset(offset));
+                    None
+                }
+                DW_CFA_RESTORE_EXTENDED => {
+                    let register = reader.uleb128() as usize;
+                    let (_, initial) = location?;
+                    restore_rule(&mut row, register, initial);
+                    None
+                }
+                DW_CFA_UNDEFINED => {
+                    let register = reader.uleb128() as usize;
+                    set_rule(&mut row, register, Rule::Undefined);
+                    None
+                }
+                DW_CFA_SAME_VALUE => {
+                    let register = reader.uleb128() as usize;
+                    set_rule(&mut row, register, Rule::SameValue);
+                    None
+                }
+                DW_CFA_REGISTER => {
+                    let register = reader.uleb128() as usize;
+                    let other = reader.uleb128() as usize;
+                    if other >= REGISTER_COUNT {
+                        return None;
+                    }
+                    set_rule(&mut row, register, Rule::Register(other));
+                    None
+                }
+                DW_CFA_REMEMBER_STATE => {
+                    if remembered_count == MAX_REMEMBERED_STATES {
+                        return None;
+                    }
+                    remembered[remembered_count] = row;
+                    remembered_count += 1;
+                    None
+                }
+                DW_CFA_RESTORE_STATE => {
+                    if remembered_count == 0 {
+                        return None;
+                    }
+                    remembered_count -= 1;
+
+                    // The CFA is not part of the remembered state
+                    let (cfa_register, cfa_offset) = (row.cfa_register, row.cfa_offset);
+                    row = remembered[remembered_count];
+                    row.cfa_register = cfa_register;
+                    row.cfa_offset = cfa_offset;
+                    None
+                }
+                DW_CFA_DEF_CFA => {
+                    row.cfa_register = reader.uleb128() as usize;
+                    row.cfa_offset = reader.uleb128() as i64;
+                    None
+                }
+                DW_CFA_DEF_CFA_SF => {
+                    row.cfa_register = reader.uleb128() as usize;
+                    row.cfa_offset = reader.sleb128() * cie.data_alignment;
+                    None
+                }
+                DW_CFA_DEF_CFA_REGISTER => {
+                    row.cfa_register = reader.uleb128() as usize;
+                    None
+                }
+                DW_CFA_DEF_CFA_OFFSET => {
+                    row.cfa_offset = reader.uleb128() as i64;
+                    None
+                }
+                DW_CFA_DEF_CFA_OFFSET_SF => {
+                    row.cfa_offset = reader.sleb128() * cie.data_alignment;
+                    None
+                }
+                DW_CFA_OFFSET_EXTENDED_SF => {
+                    let register = reader.uleb128() as usize;
+                    let offset = reader.sleb128() * cie.data_alignment;
+                    set_rule(&mut row, register, Rule::Offset(offset));
+                    None
+                }
+                DW_CFA_VAL_OFFSET => {
+                    let register = reader.uleb128() as usize;
+                    let offset = reader.uleb128() as i64 * cie.data_alignment;
+                    set_rule(&mut row, register, Rule::ValOffset(offset));
+                    None
+                }
+                DW_CFA_VAL_OFFSET_SF => {
+                    let register = reader.uleb128() as usize;
+                    let offset = reader.sleb128() * cie.data_alignment;
+                    set_rule(&mut row, register, Rule::ValOffset(offset));
+                    None
+                }
+                DW_CFA_GNU_ARGS_SIZE => {
+                    reader.uleb128();
+                    None
+                }
+                DW_CFA_GNU_NEGATIVE_OFFSET_EXTENDED => {
+                    let register = reader.uleb128() as usize;
+                    let offset = -(reader.uleb128() as i64) * cie.data_alignment;
+                    set_rule(&mut row, register, Rule::Offset(offset));
+                    None
+                }
+                // DWARF expressions are not supported
+                _ => return None,
+            },
+        };
+
+        if let Some(advance) = advance {
+            current += (advance * cie.code_alignment) as usize;
+            if current > address {
+                break;
+            }
+        }
+    }
+
+    if row.cfa_register >= REGISTER_COUNT {
+        return None;
+    }
+
+    Some(row)
+}
+
+/// Sets the rule of a register. Rules for untracked registers, such as vector registers, are
+/// ignored, as they are never needed to unwind.
+fn set_rule(row: &mut Row, register: usize, rule: Rule) {
+    if let Some(slot) = row.rules.get_mut(register) {
+        *slot = rule;
+    }
+}
+
+/// Restores the rule of a register to its rule after the CIE's program
+fn restore_rule(row: &mut Row, register: usize, initial: &Row) {
+    if let Some(&rule) = initial.rules.get(register) {
+        set_rule(row, register, rule);
+    }
+}
+
+kernel_test!(fn unwinds_own_stack() {
+    let backtrace = Backtrace::new(Registers::capture());
+    let mut frames = backtrace.frames();
+
+    let (symbol, _) = symbols::resolve(frames.next().ok_or("No frames")?)
+        .ok_or("Function not in symbol table")?;
+    test_assert!(symbol.name.contains("unwinds_own_stack"));
+
+    // The test is called by the test runner
+    test_assert!(frames.next().is_some());
+});
@@ -52,6 +52,68 @@ macro_rules! from_discriminator {
     };
 }
 
+/// Fails the current test if the condition is false
+#[cfg(feature = "integration-test")]
+macro_rules! test_assert {
+    ($condition:expr) => {
+        if !$condition {
+            return Err(concat!(file!(), ":", line!(), ": assertion failed: ", stringify!($condition)));
+        }
+    };
+}
+
+/// Fails the current test if the two values are not equal
+#[cfg(feature = "integration-test")]
+macro_rules! test_assert_eq {
+    ($left:expr, $right:expr) => {
+        if $left != $right {
+            return Err(concat!(
+                file!(), ":", line!(), ": assertion failed: ", stringify!($left), " == ", stringify!($right)
+            ));
+        }
+    };
+}
+
+/// Registers a test which is run at boot in integration test builds, and compiled out otherwise.
+/// Tests can be declared next to the code they test, and fail through `test_assert!` and
+/// `test_assert_eq!`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// kernel_test!(fn addition_works() {
+///     test_assert_eq!(1 + 1, 2);
+/// });
+/// ```
+#[cfg(feature = "integration-test")]
+macro_rules! kernel_test {
+    (fn $name:ident() $body:block) => {
+        #[allow(non_snake_case)]
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            fn run() -> ::integration::TestResult {
+                $body
+                Ok(())
+            }
+
+            #[used]
+            #[allow(dead_code)]
+            #[link_section = ".kernel_tests"]
+            static TEST: ::integration::TestCase = ::integration::TestCase {
+                name: module_path!(),
+                run,
+            };
+        }
+    };
+}
+
+#[cfg(not(feature = "integration-test"))]
+macro_rules! kernel_test {
+    (fn $name:ident() $body:block) => {};
+}
+
 pub struct UnknownDiscriminator(pub u64);
 
 pub trait FromDiscriminator {
@@ -0,0 +1,131 @@
+//! # Workqueue
+//!
+//! Interrupt handlers should return quickly, so work which takes longer, such as re-probing a PS/2
+//! device, is deferred to the workqueue instead. Queued work is run later from the kernel's main
+//! loop with interrupts enabled.
+//!
+//! There is no heap, so work is a function pointer and a `usize` argument rather than a closure,
+//! and the queue holds at most `QUEUE_SIZE` items.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! fn reprobe(port: usize) {
+//!     // Slow work...
+//! }
+//!
+//! fn irq_handler(_irq: u8) -> bool {
+//!     if workqueue::schedule(reprobe, 1).is_err() {
+//!         warn!("ps2: work dropped");
+//!     }
+//!     true
+//! }
+//! ```
+
+use sync::{IrqLock, LockStats};
+
+/// The maximum amount of work which can be queued at once
+pub const QUEUE_SIZE: usize = 64;
+
+/// A function run by the workqueue, called with the argument it was scheduled with
+pub type Work = fn(usize);
+
+/// An error returned when the workqueue has no space for more work
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct QueueFull;
+
+/// The queue of pending work
+static QUEUE: IrqLock<WorkQueue> = IrqLock::new(WorkQueue::new());
+
+/// A ring buffer of pending work
+struct WorkQueue {
+    items: [Option<(Work, usize)>; QUEUE_SIZE],
+    head: usize,
+    length: usize,
+}
+
+impl WorkQueue {
+    const fn new() -> Self {
+        WorkQueue {
+            items: [None; QUEUE_SIZE],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    fn push(&mut self, work: Work, argument: usize) -> Result<(), QueueFull> {
+        if self.length == QUEUE_SIZE {
+            return Err(QueueFull);
+        }
+
+        self.items[(self.head + self.length) % QUEUE_SIZE] = Some((work, argument));
+        self.length += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<(Work, usize)> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % QUEUE_SIZE;
+        self.length -= 1;
+        item
+    }
+}
+
+/// Queues work to be run later from the main loop. This is safe to call from interrupt handlers.
+#[allow(dead_code)] // Part of API
+pub fn schedule(work: Work, argument: usize) -> Result<(), QueueFull> {
+    QUEUE.lock().push(work, argument)
+}
+
+/// Returns `true` if there is queued work waiting to be run
+pub fn has_pending() -> bool {
+    QUEUE.lock().length > 0
+}
+
+/// Gets the statistics of the lock protecting the queue
+#[allow(dead_code)] // Part of API
+pub fn lock_stats() -> &'static LockStats {
+    QUEUE.stats()
+}
+
+/// Runs all queued work, including any queued while it runs. Returns the amount of work run.
+pub fn run_pending() -> usize {
+    let mut count = 0;
+
+    loop {
+        // The lock is released before running each item, so that work and interrupt handlers can
+        // queue more work
+        let next = QUEUE.lock().pop();
+
+        match next {
+            Some((work, argument)) => work(argument),
+            None => return count,
+        }
+
+        count += 1;
+    }
+}
+
+kernel_test!(fn queue_wraps() {
+    fn nothing(_: usize) {}
+
+    let mut queue = WorkQueue::new();
+
+    for round in 0..3 {
+        for argument in 0..QUEUE_SIZE {
+            test_assert!(queue.push(nothing, round * QUEUE_SIZE + argument).is_ok());
+        }
+
+        test_assert_eq!(queue.push(nothing, 0), Err(QueueFull));
+
+        for argument in 0..QUEUE_SIZE {
+            test_assert_eq!(queue.pop().map(|item| item.1), Some(round * QUEUE_SIZE + argument));
+        }
+
+        test_assert!(queue.pop().is_none());
+    }
+});